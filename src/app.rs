@@ -1,4 +1,161 @@
-use crate::expense::Expense;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use ratatui::layout::Rect;
+
+use crate::audit::AuditEntry;
+use crate::bulk::BulkAction;
+use crate::config::Config;
+use crate::crypto::Key;
+use crate::duplicates::{DuplicateAction, ImportPreview};
+use crate::expense::{now_timestamp, Expense};
+use crate::filter::ExpenseFilter;
+use crate::input::TextInput;
+use crate::keymap::{Action, Keymap};
+use crate::ledger::{Ledger, LedgerSettings};
+use crate::period::{days_in_month, next_month, previous_month};
+use crate::reconcile::Reconciliation;
+use crate::summary::{CategoryTotal, DashboardData, PeriodSummary};
+use crate::sync::{MergeOutcome, Resolution};
+use crate::table::Sort;
+use crate::theme::Theme;
+use crate::undo::History;
+
+/// The top-level screens, in tab order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Screen {
+    Transactions,
+    Dashboard,
+    Calendar,
+    Budgets,
+    Categories,
+    Recurring,
+    Reports,
+    Trash,
+    Settings,
+}
+
+impl Screen {
+    pub const ALL: [Screen; 9] = [
+        Screen::Transactions,
+        Screen::Dashboard,
+        Screen::Calendar,
+        Screen::Budgets,
+        Screen::Categories,
+        Screen::Recurring,
+        Screen::Reports,
+        Screen::Trash,
+        Screen::Settings,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Screen::Transactions => "Transactions",
+            Screen::Dashboard => "Dashboard",
+            Screen::Calendar => "Calendar",
+            Screen::Budgets => "Budgets",
+            Screen::Categories => "Categories",
+            Screen::Recurring => "Recurring",
+            Screen::Reports => "Reports",
+            Screen::Trash => "Trash",
+            Screen::Settings => "Settings",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|screen| screen == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(&self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// A step of the navigation stack: where going back returns to after a
+/// drill-down into the transactions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavEntry {
+    pub screen: Screen,
+    /// The transaction filter the drill-down replaced.
+    pub filter: ExpenseFilter,
+}
+
+/// The reports the Reports screen offers, in the order they are listed.
+pub const REPORTS: [Action; 3] = [
+    Action::ExportXlsx,
+    Action::HtmlReport,
+    Action::MarkdownReport,
+];
+
+/// An expense waiting for the user to confirm that it should go.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingDelete {
+    pub expense: Expense,
+    /// Deleting for good from the trash rather than moving to it.
+    pub purge: bool,
+}
+
+/// The menu of bulk actions for the marked expenses.
+#[derive(Clone, Debug, Default)]
+pub struct BulkMenu {
+    pub selected: usize,
+    /// What's being typed for the chosen action, `Some` once it asks for
+    /// something.
+    pub input: Option<String>,
+}
+
+impl BulkMenu {
+    pub fn action(&self) -> BulkAction {
+        BulkAction::ALL[self.selected.min(BulkAction::ALL.len() - 1)]
+    }
+}
+
+/// The recorded changes to one expense, open while they're being browsed.
+pub struct ExpenseHistory {
+    pub expense: Expense,
+    /// The latest change first.
+    pub entries: Vec<AuditEntry>,
+    pub selected: usize,
+}
+
+impl ExpenseHistory {
+    pub fn new(expense: Expense, entries: Vec<AuditEntry>) -> Self {
+        Self {
+            expense,
+            entries,
+            selected: 0,
+        }
+    }
+
+    /// The expense with the values the selected change left behind, keeping
+    /// its id, uid and status. `None` when that's what it holds already.
+    pub fn reverted(&self) -> Option<Expense> {
+        let version = self.entries.get(self.selected)?.after()?;
+        let reverted = Expense {
+            date: version.date,
+            name: version.name,
+            category: version.category,
+            amount: version.amount,
+            external_id: version.external_id,
+            account: version.account,
+            notes: version.notes,
+            tags: version.tags,
+            ..self.expense.clone()
+        };
+        (reverted != self.expense).then(|| Expense {
+            modified_at: now_timestamp(),
+            ..reverted
+        })
+    }
+}
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum InputMode {
@@ -6,24 +163,581 @@ pub enum InputMode {
     Name,
     Category,
     Amount,
+    Account,
+    Notes,
+    Tags,
+}
+
+impl InputMode {
+    pub fn next(self) -> Self {
+        match self {
+            InputMode::Date => InputMode::Name,
+            InputMode::Name => InputMode::Category,
+            InputMode::Category => InputMode::Amount,
+            InputMode::Amount => InputMode::Account,
+            InputMode::Account => InputMode::Notes,
+            InputMode::Notes => InputMode::Tags,
+            InputMode::Tags => InputMode::Date,
+        }
+    }
+
+    pub fn previous(self) -> Self {
+        match self {
+            InputMode::Date => InputMode::Tags,
+            InputMode::Tags => InputMode::Notes,
+            InputMode::Name => InputMode::Date,
+            InputMode::Category => InputMode::Name,
+            InputMode::Amount => InputMode::Category,
+            InputMode::Account => InputMode::Amount,
+            InputMode::Notes => InputMode::Account,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReviewKind {
+    /// Matches between an import file and the existing expenses.
+    Import,
+    /// Matches among the existing expenses.
+    Existing,
+}
+
+/// Suspected duplicates waiting for the user to decide what to do with each.
+pub struct DuplicateReview {
+    pub kind: ReviewKind,
+    pub preview: ImportPreview,
+    pub actions: Vec<DuplicateAction>,
+    pub selected: usize,
+}
+
+impl DuplicateReview {
+    pub fn new(kind: ReviewKind, preview: ImportPreview) -> Self {
+        // Merging is the safe default for an import; for data that's already
+        // in the ledger nothing is removed unless the user asks for it.
+        let action = match kind {
+            ReviewKind::Import => DuplicateAction::Merge,
+            ReviewKind::Existing => DuplicateAction::Keep,
+        };
+        Self {
+            kind,
+            actions: vec![action; preview.matches.len()],
+            preview,
+            selected: 0,
+        }
+    }
+
+    pub fn toggle_selected(&mut self) {
+        if let Some(action) = self.actions.get_mut(self.selected) {
+            *action = match action {
+                DuplicateAction::Keep => DuplicateAction::Merge,
+                DuplicateAction::Merge => DuplicateAction::Keep,
+            };
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReconcileField {
+    Account,
+    StatementDate,
+    ClosingBalance,
+}
+
+/// Statement details typed in before a reconciliation starts.
+pub struct ReconcileSetup {
+    pub account: String,
+    pub statement_date: String,
+    pub closing_balance: String,
+    pub field: ReconcileField,
+}
+
+impl Default for ReconcileSetup {
+    fn default() -> Self {
+        Self {
+            account: String::new(),
+            statement_date: chrono::Local::now()
+                .date_naive()
+                .format("%Y-%m-%d")
+                .to_string(),
+            closing_balance: String::new(),
+            field: ReconcileField::Account,
+        }
+    }
+}
+
+impl ReconcileSetup {
+    pub fn field_mut(&mut self) -> &mut String {
+        match self.field {
+            ReconcileField::Account => &mut self.account,
+            ReconcileField::StatementDate => &mut self.statement_date,
+            ReconcileField::ClosingBalance => &mut self.closing_balance,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            ReconcileField::Account => ReconcileField::StatementDate,
+            ReconcileField::StatementDate => ReconcileField::ClosingBalance,
+            ReconcileField::ClosingBalance => ReconcileField::Account,
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SettingsField {
+    Description,
+    Currency,
+}
+
+/// The open ledger's settings being edited from the Settings screen.
+pub struct SettingsForm {
+    pub description: String,
+    /// Left empty to use the currency from the config.
+    pub currency: String,
+    pub field: SettingsField,
+}
+
+impl SettingsForm {
+    pub fn new(settings: &LedgerSettings) -> Self {
+        Self {
+            description: settings.description.clone(),
+            currency: settings.currency.clone().unwrap_or_default(),
+            field: SettingsField::Description,
+        }
+    }
+
+    pub fn field_mut(&mut self) -> &mut String {
+        match self.field {
+            SettingsField::Description => &mut self.description,
+            SettingsField::Currency => &mut self.currency,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            SettingsField::Description => SettingsField::Currency,
+            SettingsField::Currency => SettingsField::Description,
+        };
+    }
+
+    pub fn settings(&self) -> LedgerSettings {
+        let currency = self.currency.trim();
+        LedgerSettings {
+            description: self.description.trim().to_string(),
+            currency: (!currency.is_empty()).then(|| currency.to_string()),
+        }
+    }
+}
+
+/// New passphrase being typed, asked for twice before anything changes.
+#[derive(Default)]
+pub struct PassphraseChange {
+    pub input: String,
+    /// The first entry, once it has been typed and is waiting to be confirmed.
+    pub first: Option<String>,
+}
+
+/// The passphrase asked for before syncing an encrypted ledger. Every copy has
+/// its own salt, so the key of the open ledger can't open the others.
+pub struct SyncPassphrase {
+    /// The other copy the ledger is being synced with.
+    pub other_file: String,
+    pub input: String,
+}
+
+/// Conflicts from a sync, each waiting for a choice between our version and
+/// the other copy's.
+pub struct MergeReview {
+    pub outcome: MergeOutcome,
+    pub resolutions: Vec<Resolution>,
+    pub selected: usize,
+    /// The other copy the ledger is being synced with.
+    pub other_file: String,
+}
+
+impl MergeReview {
+    pub fn new(outcome: MergeOutcome, other_file: String) -> Self {
+        Self {
+            resolutions: vec![Resolution::Ours; outcome.conflicts.len()],
+            outcome,
+            selected: 0,
+            other_file,
+        }
+    }
+
+    pub fn set_selected(&mut self, resolution: Resolution) {
+        if let Some(slot) = self.resolutions.get_mut(self.selected) {
+            *slot = resolution;
+        }
+    }
+}
+
+pub struct LedgerPicker {
+    pub ledgers: Vec<Ledger>,
+    pub selected: usize,
+    /// Name being typed for a new ledger.
+    pub new_name: Option<String>,
+}
+
+impl LedgerPicker {
+    pub fn new(ledgers: Vec<Ledger>) -> Self {
+        Self {
+            ledgers,
+            selected: 0,
+            new_name: None,
+        }
+    }
+}
+
+/// A month of daily spending with one day picked.
+pub struct Calendar {
+    pub selected: NaiveDate,
+    /// Spending on each day of the selected day's month, as loaded by
+    /// `summary::daily_spending`.
+    pub daily: Vec<f64>,
+}
+
+impl Calendar {
+    pub fn new(selected: NaiveDate, daily: Vec<f64>) -> Self {
+        Self { selected, daily }
+    }
+
+    pub fn year(&self) -> i32 {
+        self.selected.year()
+    }
+
+    pub fn month(&self) -> u32 {
+        self.selected.month()
+    }
+
+    /// Spending on `day`, zero for days of other months.
+    pub fn total(&self, day: NaiveDate) -> f64 {
+        if day.year() != self.year() || day.month() != self.month() {
+            return 0.0;
+        }
+        self.daily.get(day.day0() as usize).copied().unwrap_or(0.0)
+    }
+
+    /// How hot a day is: 0 without spending, otherwise 1 to 3 by its share
+    /// of the month's biggest day.
+    pub fn heat(&self, day: NaiveDate) -> u8 {
+        let total = self.total(day);
+        let highest = self.daily.iter().copied().fold(0.0, f64::max);
+        if total <= 0.0 || highest <= 0.0 {
+            0
+        } else if total * 3.0 <= highest {
+            1
+        } else if total * 3.0 <= highest * 2.0 {
+            2
+        } else {
+            3
+        }
+    }
+
+    /// Moves the selection by a number of days. Returns true when it ends up
+    /// in another month, whose totals then need loading.
+    pub fn move_by(&mut self, days: i64) -> bool {
+        let (year, month) = (self.year(), self.month());
+        if let Some(day) = self.selected.checked_add_signed(Duration::days(days)) {
+            self.selected = day;
+        }
+        (year, month) != (self.year(), self.month())
+    }
+
+    /// Moves to the same day of the previous or next month, or its last day
+    /// when the month is shorter.
+    pub fn move_month(&mut self, forward: bool) {
+        let (year, month) = if forward {
+            next_month(self.year(), self.month())
+        } else {
+            previous_month(self.year(), self.month())
+        };
+        let day = self.selected.day().min(days_in_month(year, month));
+        if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+            self.selected = date;
+        }
+    }
+}
+
+/// The add/edit form. Fields keep the raw text as typed and are only parsed
+/// when the form is submitted.
+#[derive(Clone, Debug, Default)]
+pub struct ExpenseForm {
+    /// The expense being edited, `None` for a new one. Everything the form
+    /// doesn't show (id, status, uid, ...) comes from here.
+    pub base: Option<Expense>,
+    pub date: TextInput,
+    pub name: TextInput,
+    pub category: TextInput,
+    pub amount: TextInput,
+    pub account: TextInput,
+    pub notes: TextInput,
+    pub tags: TextInput,
+}
+
+impl ExpenseForm {
+    pub fn field(&self, mode: InputMode) -> &TextInput {
+        match mode {
+            InputMode::Date => &self.date,
+            InputMode::Name => &self.name,
+            InputMode::Category => &self.category,
+            InputMode::Amount => &self.amount,
+            InputMode::Account => &self.account,
+            InputMode::Notes => &self.notes,
+            InputMode::Tags => &self.tags,
+        }
+    }
+
+    pub fn field_mut(&mut self, mode: InputMode) -> &mut TextInput {
+        match mode {
+            InputMode::Date => &mut self.date,
+            InputMode::Name => &mut self.name,
+            InputMode::Category => &mut self.category,
+            InputMode::Amount => &mut self.amount,
+            InputMode::Account => &mut self.account,
+            InputMode::Notes => &mut self.notes,
+            InputMode::Tags => &mut self.tags,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterField {
+    From,
+    To,
+    Categories,
+    MinAmount,
+    MaxAmount,
+}
+
+impl FilterField {
+    pub const ALL: [FilterField; 5] = [
+        FilterField::From,
+        FilterField::To,
+        FilterField::Categories,
+        FilterField::MinAmount,
+        FilterField::MaxAmount,
+    ];
+
+    fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|field| *field == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// The filter panel. Like the expense form, fields hold raw text until the
+/// panel is applied; empty fields don't restrict anything.
+#[derive(Clone, Debug)]
+pub struct FilterPanel {
+    pub field: FilterField,
+    pub from: TextInput,
+    pub to: TextInput,
+    /// Comma separated.
+    pub categories: TextInput,
+    pub min_amount: TextInput,
+    pub max_amount: TextInput,
+}
+
+impl FilterPanel {
+    /// A panel showing the criteria of `filter`.
+    pub fn new(filter: &ExpenseFilter, config: &Config) -> Self {
+        let date = |date: Option<NaiveDate>| {
+            TextInput::new(&date.map_or(String::new(), |d| config.format_input_date(d)))
+        };
+        let amount = |amount: Option<f64>| {
+            TextInput::new(&amount.map_or(String::new(), |a| config.format_amount(a)))
+        };
+        Self {
+            field: FilterField::From,
+            from: date(filter.start),
+            to: date(filter.end),
+            categories: TextInput::new(&filter.categories.join(", ")),
+            min_amount: amount(filter.min_amount),
+            max_amount: amount(filter.max_amount),
+        }
+    }
+
+    pub fn field(&self, field: FilterField) -> &TextInput {
+        match field {
+            FilterField::From => &self.from,
+            FilterField::To => &self.to,
+            FilterField::Categories => &self.categories,
+            FilterField::MinAmount => &self.min_amount,
+            FilterField::MaxAmount => &self.max_amount,
+        }
+    }
+
+    pub fn field_mut(&mut self, field: FilterField) -> &mut TextInput {
+        match field {
+            FilterField::From => &mut self.from,
+            FilterField::To => &mut self.to,
+            FilterField::Categories => &mut self.categories,
+            FilterField::MinAmount => &mut self.min_amount,
+            FilterField::MaxAmount => &mut self.max_amount,
+        }
+    }
+
+    /// `filter` with the panel's criteria in place of its own, or a message
+    /// saying which field doesn't parse.
+    pub fn apply_to(
+        &self,
+        filter: &ExpenseFilter,
+        config: &Config,
+    ) -> Result<ExpenseFilter, String> {
+        let date = |input: &TextInput, label: &str| match input.value().trim() {
+            "" => Ok(None),
+            text => config.parse_input_date(text).map(Some).ok_or_else(|| {
+                format!(
+                    "{} must look like {}",
+                    label,
+                    config.format_input_date(chrono::Local::now().date_naive())
+                )
+            }),
+        };
+        let amount = |input: &TextInput, label: &str| match input.value().trim() {
+            "" => Ok(None),
+            text => config
+                .parse_amount(text)
+                .map(Some)
+                .ok_or_else(|| format!("{} '{}' is not an amount", label, text)),
+        };
+        Ok(ExpenseFilter {
+            start: date(&self.from, "From")?,
+            end: date(&self.to, "To")?,
+            categories: self
+                .categories
+                .value()
+                .split(',')
+                .map(str::trim)
+                .filter(|category| !category.is_empty())
+                .map(str::to_string)
+                .collect(),
+            min_amount: amount(&self.min_amount, "Minimum")?,
+            max_amount: amount(&self.max_amount, "Maximum")?,
+            ..filter.clone()
+        })
+    }
 }
 
 pub struct App {
+    pub config: Config,
+    pub screen: Screen,
+    pub nav_stack: Vec<NavEntry>,
+    /// Selected row of the list screens other than the transactions, kept
+    /// while switching between screens.
+    pub screen_rows: BTreeMap<Screen, usize>,
+    /// Bindings built from `config.keys`.
+    pub keymap: Keymap,
+    pub theme: Theme,
+    /// Every expense in the ledger.
+    pub all_expenses: Vec<Expense>,
+    /// The expenses the filter lets through, as shown in the list.
     pub expenses: Vec<Expense>,
+    pub filter: ExpenseFilter,
+    /// Search text being typed, `Some` while the search bar has focus.
+    pub search: Option<TextInput>,
+    pub filter_panel: Option<FilterPanel>,
+    pub sort: Sort,
+    /// Where the table header was last drawn, so clicks on it can sort.
+    pub table_header: Cell<Option<Rect>>,
     pub selected_index: Option<usize>,
+    /// Whether the expense form is open, for a new expense or an edit.
     pub adding_expense: bool,
-    pub new_expense: Expense,
+    pub form: ExpenseForm,
+    /// Id of the expense the form is editing, `None` when adding.
+    pub editing: Option<i64>,
     pub input_mode: InputMode,
+    pub status_message: Option<String>,
+    pub summary: Option<PeriodSummary>,
+    /// Path being typed for an import, `Some` while the prompt is open.
+    pub import_path: Option<String>,
+    pub duplicate_review: Option<DuplicateReview>,
+    pub reconcile_setup: Option<ReconcileSetup>,
+    pub reconciliation: Option<Reconciliation>,
+    /// Key of the encrypted data file, `None` when it's stored in plaintext.
+    pub encryption_key: Option<Key>,
+    /// Passphrase being typed to open an encrypted data file.
+    pub unlock_prompt: Option<String>,
+    pub passphrase_change: Option<PassphraseChange>,
+    /// The open ledger, `None` until one has been picked.
+    pub ledger: Option<Ledger>,
+    pub ledger_settings: LedgerSettings,
+    pub settings_form: Option<SettingsForm>,
+    pub ledger_picker: Option<LedgerPicker>,
+    /// Path of the copy to sync with, `Some` while the prompt is open.
+    pub sync_path: Option<String>,
+    /// Passphrase being typed to open the copies of an encrypted ledger.
+    pub sync_passphrase: Option<SyncPassphrase>,
+    pub merge_review: Option<MergeReview>,
+    /// Charts for one month, `Some` while the dashboard is open.
+    pub dashboard: Option<DashboardData>,
+    pub calendar: Option<Calendar>,
+    pub pending_delete: Option<PendingDelete>,
+    pub expense_history: Option<ExpenseHistory>,
+    /// Ids of the expenses marked for a bulk action.
+    pub marked: BTreeSet<i64>,
+    pub bulk_menu: Option<BulkMenu>,
+    /// Deleted expenses, the most recently deleted first.
+    pub trash: Vec<Expense>,
+    /// Changes that can be undone and redone until the app exits.
+    pub history: History,
 }
 
 impl Default for App {
     fn default() -> Self {
         Self {
+            config: Config::default(),
+            screen: Screen::Transactions,
+            nav_stack: Vec::new(),
+            screen_rows: BTreeMap::new(),
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            all_expenses: Vec::new(),
             expenses: Vec::new(),
+            filter: ExpenseFilter::default(),
+            search: None,
+            filter_panel: None,
+            sort: Sort::default(),
+            table_header: Cell::new(None),
             selected_index: None,
             adding_expense: false,
-            new_expense: Expense::new(chrono::Local::now().date_naive(), "", "", 0.0).unwrap(),
+            form: ExpenseForm::default(),
+            editing: None,
             input_mode: InputMode::Date,
+            status_message: None,
+            summary: None,
+            import_path: None,
+            duplicate_review: None,
+            reconcile_setup: None,
+            reconciliation: None,
+            encryption_key: None,
+            unlock_prompt: None,
+            passphrase_change: None,
+            ledger: None,
+            ledger_settings: LedgerSettings::default(),
+            settings_form: None,
+            ledger_picker: None,
+            sync_path: None,
+            sync_passphrase: None,
+            merge_review: None,
+            dashboard: None,
+            calendar: None,
+            pending_delete: None,
+            expense_history: None,
+            marked: BTreeSet::new(),
+            bulk_menu: None,
+            trash: Vec::new(),
+            history: History::default(),
         }
     }
 }
@@ -34,6 +748,584 @@ impl App {
     }
 
     pub fn set_expenses(&mut self, expenses: Vec<Expense>) {
-        self.expenses = expenses;
+        self.all_expenses = expenses;
+        // Marks on expenses that are gone go with them
+        let ids: BTreeSet<i64> = self.all_expenses.iter().filter_map(|e| e.id).collect();
+        self.marked.retain(|id| ids.contains(id));
+        self.apply_filter();
+    }
+
+    /// Marks or unmarks the selected expense and moves on to the next one.
+    pub fn toggle_mark(&mut self) {
+        let Some(id) = self.selected_expense_id() else {
+            return;
+        };
+        if !self.marked.remove(&id) {
+            self.marked.insert(id);
+        }
+        self.step_selection(true);
+    }
+
+    /// Marks the selected expense and the one above or below it, so holding
+    /// shift while moving marks a range.
+    pub fn extend_marks(&mut self, down: bool) {
+        self.marked.extend(self.selected_expense_id());
+        self.step_selection(down);
+        self.marked.extend(self.selected_expense_id());
+    }
+
+    /// Marks everything the filter lets through, or unmarks it all when it
+    /// already is marked.
+    pub fn mark_all(&mut self) {
+        let ids: Vec<i64> = self.expenses.iter().filter_map(|e| e.id).collect();
+        if ids.iter().all(|id| self.marked.contains(id)) {
+            for id in &ids {
+                self.marked.remove(id);
+            }
+        } else {
+            self.marked.extend(ids);
+        }
+    }
+
+    /// The marked expenses in list order. Marks survive filtering, so this
+    /// includes ones the filter hides.
+    pub fn marked_expenses(&self) -> Vec<Expense> {
+        let mut marked: Vec<Expense> = self
+            .all_expenses
+            .iter()
+            .filter(|e| e.id.is_some_and(|id| self.marked.contains(&id)))
+            .cloned()
+            .collect();
+        self.sort.apply(&mut marked);
+        marked
+    }
+
+    fn selected_expense_id(&self) -> Option<i64> {
+        self.selected_index
+            .and_then(|index| self.expenses.get(index))
+            .and_then(|expense| expense.id)
+    }
+
+    fn step_selection(&mut self, down: bool) {
+        let last = self.expenses.len().saturating_sub(1);
+        self.selected_index = match self.selected_index {
+            Some(index) if down => Some((index + 1).min(last)),
+            Some(index) => Some(index.saturating_sub(1)),
+            None => None,
+        };
+    }
+
+    /// Rebuilds the visible list from the filter, keeping the selected
+    /// expense selected if it's still there.
+    pub fn apply_filter(&mut self) {
+        let selected_id = self
+            .selected_index
+            .and_then(|index| self.expenses.get(index))
+            .and_then(|expense| expense.id);
+        self.expenses = self.filter.apply(self.all_expenses.clone());
+        self.sort.apply(&mut self.expenses);
+        self.selected_index = match self.selected_index {
+            None => None,
+            Some(_) if self.expenses.is_empty() => None,
+            Some(index) => selected_id
+                .and_then(|id| self.expenses.iter().position(|e| e.id == Some(id)))
+                .or(Some(index.min(self.expenses.len() - 1))),
+        };
+    }
+
+    pub fn set_sort(&mut self, sort: Sort) {
+        self.sort = sort;
+        self.apply_filter();
+    }
+
+    /// Sorts by the next visible column, wrapping around.
+    pub fn sort_next_column(&mut self) {
+        let columns = &self.config.display.columns;
+        let next = columns
+            .iter()
+            .position(|column| *column == self.sort.column)
+            .map_or(0, |index| (index + 1) % columns.len());
+        let sort = Sort {
+            column: columns[next],
+            descending: false,
+        };
+        self.set_sort(sort);
+    }
+
+    /// Opens the search bar with the current search text.
+    pub fn start_search(&mut self) {
+        self.search = Some(TextInput::new(self.filter.text.as_deref().unwrap_or("")));
+    }
+
+    /// Filters by what's in the search bar, as it's being typed.
+    pub fn update_search(&mut self) {
+        if let Some(search) = &self.search {
+            let text = search.value().trim();
+            self.filter.text = (!text.is_empty()).then(|| text.to_string());
+            self.apply_filter();
+        }
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter = ExpenseFilter::default();
+        self.search = None;
+        self.apply_filter();
+    }
+
+    /// Shows the transactions matching `filter`, remembering the current
+    /// screen and filter so that going back restores both.
+    pub fn drill_down(&mut self, filter: ExpenseFilter) {
+        self.nav_stack.push(NavEntry {
+            screen: self.screen,
+            filter: std::mem::replace(&mut self.filter, filter),
+        });
+        self.screen = Screen::Transactions;
+        self.search = None;
+        self.apply_filter();
+        self.selected_index = if self.expenses.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+    }
+
+    /// Lists the expenses of `day`.
+    pub fn show_day(&mut self, day: NaiveDate) {
+        self.drill_down(ExpenseFilter {
+            start: Some(day),
+            end: Some(day),
+            ..ExpenseFilter::default()
+        });
+    }
+
+    /// Returns to where the last drill-down started. Returns false when
+    /// there is nothing to go back to.
+    pub fn go_back(&mut self) -> bool {
+        let Some(entry) = self.nav_stack.pop() else {
+            return false;
+        };
+        self.screen = entry.screen;
+        self.filter = entry.filter;
+        self.search = None;
+        self.apply_filter();
+        true
+    }
+
+    /// The selected row of a list screen.
+    pub fn screen_row(&self, screen: Screen) -> usize {
+        self.screen_rows.get(&screen).copied().unwrap_or(0)
+    }
+
+    /// Moves the current screen's selection, staying within its `len` rows.
+    pub fn move_screen_row(&mut self, down: bool, len: usize) {
+        let row = self.screen_row(self.screen);
+        let row = if down {
+            (row + 1).min(len.saturating_sub(1))
+        } else {
+            row.saturating_sub(1)
+        };
+        self.screen_rows.insert(self.screen, row);
+    }
+
+    /// Spending per category over the whole ledger, the biggest first.
+    pub fn category_totals(&self) -> Vec<CategoryTotal> {
+        let mut totals: Vec<CategoryTotal> = Vec::new();
+        for expense in &self.all_expenses {
+            match totals
+                .iter_mut()
+                .find(|t| t.category.eq_ignore_ascii_case(&expense.category))
+            {
+                Some(total) => {
+                    total.total += expense.amount;
+                    total.count += 1;
+                }
+                None => totals.push(CategoryTotal {
+                    category: expense.category.clone(),
+                    total: expense.amount,
+                    count: 1,
+                }),
+            }
+        }
+        totals.sort_by(|a, b| {
+            b.total
+                .total_cmp(&a.total)
+                .then(a.category.cmp(&b.category))
+        });
+        totals
+    }
+
+    /// Short description of the active filter for the list title, `None`
+    /// when nothing is filtered.
+    pub fn filter_description(&self) -> Option<String> {
+        if self.filter.is_empty() {
+            return None;
+        }
+        let filter = &self.filter;
+        let mut parts = Vec::new();
+        if let Some(text) = &filter.text {
+            parts.push(format!("\"{}\"", text));
+        }
+        if filter.start.is_some() || filter.end.is_some() {
+            let date = |date: Option<NaiveDate>| {
+                date.map_or("…".to_string(), |d| self.config.format_date(d))
+            };
+            parts.push(format!("{} – {}", date(filter.start), date(filter.end)));
+        }
+        if let Some(category) = &filter.category {
+            parts.push(category.clone());
+        }
+        if !filter.categories.is_empty() {
+            parts.push(filter.categories.join("/"));
+        }
+        if filter.min_amount.is_some() || filter.max_amount.is_some() {
+            let amount = |amount: Option<f64>| {
+                amount.map_or("…".to_string(), |a| self.config.format_amount(a))
+            };
+            parts.push(format!(
+                "{} – {}",
+                amount(filter.min_amount),
+                amount(filter.max_amount)
+            ));
+        }
+        Some(parts.join(", "))
+    }
+
+    /// Opens the form for a new expense dated `today`.
+    pub fn start_adding(&mut self, today: NaiveDate) {
+        self.adding_expense = true;
+        self.editing = None;
+        self.input_mode = InputMode::Date;
+        self.form = ExpenseForm {
+            date: TextInput::new(&self.config.format_input_date(today)),
+            ..ExpenseForm::default()
+        };
+    }
+
+    /// Opens the form filled in with the selected expense. Returns false when
+    /// nothing is selected.
+    pub fn start_editing(&mut self) -> bool {
+        let Some(expense) = self
+            .selected_index
+            .and_then(|index| self.expenses.get(index))
+        else {
+            return false;
+        };
+        let date = match NaiveDate::parse_from_str(&expense.date, "%Y-%m-%d") {
+            Ok(date) => self.config.format_input_date(date),
+            Err(_) => expense.date.clone(),
+        };
+        self.form = ExpenseForm {
+            base: Some(expense.clone()),
+            date: TextInput::new(&date),
+            name: TextInput::new(&expense.name),
+            category: TextInput::new(&expense.category),
+            amount: TextInput::new(&self.config.format_amount(expense.amount)),
+            account: TextInput::new(&expense.account),
+            notes: TextInput::new(&expense.notes),
+            tags: TextInput::new(&expense.tags),
+        };
+        self.adding_expense = true;
+        self.editing = expense.id;
+        self.input_mode = InputMode::Date;
+        true
+    }
+
+    pub fn close_form(&mut self) {
+        self.adding_expense = false;
+        self.editing = None;
+    }
+
+    /// The expense the form describes, checked and with the date in the
+    /// stored format, or a message saying what's wrong.
+    pub fn form_expense(&self) -> Result<Expense, String> {
+        let form = &self.form;
+        let date = self
+            .config
+            .parse_input_date(form.date.value())
+            .ok_or_else(|| {
+                format!(
+                    "Date must look like {}",
+                    self.config
+                        .format_input_date(chrono::Local::now().date_naive())
+                )
+            })?;
+        let name = form.name.value().trim();
+        if name.is_empty() {
+            return Err("Name must not be empty".to_string());
+        }
+        let category = form.category.value().trim();
+        if category.is_empty() {
+            return Err("Category must not be empty".to_string());
+        }
+        let amount = self
+            .config
+            .parse_amount(form.amount.value())
+            .filter(|amount| amount.is_finite())
+            .ok_or_else(|| format!("'{}' is not an amount", form.amount.value().trim()))?;
+        if amount < 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        let mut expense = match &form.base {
+            Some(base) => base.clone(),
+            None => Expense::new(date, name, category, amount)?,
+        };
+        expense.date = date.format("%Y-%m-%d").to_string();
+        expense.name = name.to_string();
+        expense.category = category.to_string();
+        expense.amount = amount;
+        expense.account = form.account.value().trim().to_string();
+        expense.notes = form.notes.value().trim().to_string();
+        expense.tags = form.tags.value().trim().to_string();
+        Ok(expense)
+    }
+
+    /// Selects the expense with `id` if it's in the list.
+    pub fn select_id(&mut self, id: i64) {
+        if let Some(index) = self.expenses.iter().position(|e| e.id == Some(id)) {
+            self.selected_index = Some(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditAction;
+    use crate::expense::ExpenseStatus;
+    use crate::test_support::stored;
+
+    #[test]
+    fn test_settings_form() {
+        let mut form = SettingsForm::new(&LedgerSettings {
+            description: "Family".to_string(),
+            currency: Some("£".to_string()),
+        });
+        form.field_mut().push_str(" home ");
+        form.next_field();
+        form.field_mut().clear();
+        assert_eq!(
+            form.settings(),
+            LedgerSettings {
+                description: "Family home".to_string(),
+                currency: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_edit_form() {
+        let mut app = App::new();
+        app.config.display.date_input_format = "%d/%m/%Y".to_string();
+        assert!(!app.start_editing());
+
+        app.set_expenses(vec![
+            stored(1, "2024-05-01", "Tea", "Food", 4.5),
+            stored(7, "2024-05-01", "Cofee", "Food", 4.5),
+        ]);
+        app.selected_index = Some(1);
+        assert!(app.start_editing());
+        assert_eq!(app.editing, Some(7));
+        assert_eq!(app.form.date.value(), "01/05/2024");
+        assert_eq!(app.form.amount.value(), "4.50");
+
+        app.form.name = TextInput::new(" Coffee ");
+        app.form.amount = TextInput::new("0.05");
+        let expense = app.form_expense().unwrap();
+        assert_eq!(expense.id, Some(7));
+        assert_eq!(expense.date, "2024-05-01");
+        assert_eq!(expense.name, "Coffee");
+        assert_eq!(expense.amount, 0.05);
+
+        app.form.amount = TextInput::new("5 quid");
+        assert!(app.form_expense().unwrap_err().contains("amount"));
+        app.form.amount = TextInput::new("5");
+        app.form.date = TextInput::new("2024-05-01");
+        assert!(app.form_expense().unwrap_err().starts_with("Date"));
+        app.form.date = TextInput::new("01/05/2024");
+        app.form.name = TextInput::default();
+        assert!(app.form_expense().is_err());
+
+        app.start_adding(NaiveDate::from_ymd_opt(2024, 6, 2).unwrap());
+        assert_eq!(app.form.date.value(), "02/06/2024");
+        assert_eq!(app.form.amount.value(), "");
+
+        app.close_form();
+        assert_eq!(app.editing, None);
+        app.select_id(1);
+        assert_eq!(app.selected_index, Some(0));
+    }
+
+    #[test]
+    fn test_search_keeps_selection() {
+        let mut app = App::new();
+        app.set_expenses(vec![
+            stored(1, "2024-05-01", "Tea", "Food", 4.5),
+            stored(2, "2024-05-01", "Cake", "Food", 4.5),
+            stored(3, "2024-05-01", "Teapot", "Food", 4.5),
+        ]);
+        app.selected_index = Some(2);
+
+        app.start_search();
+        app.search.as_mut().unwrap().insert_str("tea");
+        app.update_search();
+        assert_eq!(app.expenses.len(), 2);
+        assert_eq!(app.selected_index, Some(1));
+        assert_eq!(app.filter_description().unwrap(), "\"tea\"");
+
+        app.search.as_mut().unwrap().insert_str("x");
+        app.update_search();
+        assert_eq!(app.selected_index, None);
+
+        app.clear_filter();
+        assert_eq!(app.expenses.len(), 3);
+        assert_eq!(app.filter_description(), None);
+    }
+
+    #[test]
+    fn test_filter_panel() {
+        let mut app = App::new();
+        app.config.display.date_input_format = "%d/%m/%Y".to_string();
+        app.filter.text = Some("tea".to_string());
+        let mut panel = FilterPanel::new(&app.filter, &app.config);
+        assert_eq!(panel.field.previous(), FilterField::MaxAmount);
+        panel.from = TextInput::new("01/05/2024");
+        panel.categories = TextInput::new("Food, Drinks ,");
+        panel.min_amount = TextInput::new("2.5");
+        let filter = panel.apply_to(&app.filter, &app.config).unwrap();
+        assert_eq!(filter.start, NaiveDate::from_ymd_opt(2024, 5, 1));
+        assert_eq!(filter.end, None);
+        assert_eq!(filter.categories, vec!["Food", "Drinks"]);
+        assert_eq!(filter.min_amount, Some(2.5));
+        assert_eq!(filter.text.as_deref(), Some("tea"));
+
+        panel.to = TextInput::new("2024-05-31");
+        assert!(panel
+            .apply_to(&app.filter, &app.config)
+            .unwrap_err()
+            .starts_with("To"));
+    }
+
+    #[test]
+    fn test_calendar() {
+        let day = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let mut daily = vec![0.0; 29];
+        daily[0] = 30.0;
+        daily[1] = 12.0;
+        daily[2] = 5.0;
+        let mut calendar = Calendar::new(day(2, 1), daily);
+        assert_eq!(calendar.heat(day(2, 1)), 3);
+        assert_eq!(calendar.heat(day(2, 2)), 2);
+        assert_eq!(calendar.heat(day(2, 3)), 1);
+        assert_eq!(calendar.heat(day(2, 4)), 0);
+        assert_eq!(calendar.total(day(3, 1)), 0.0);
+
+        assert!(!calendar.move_by(7));
+        assert!(calendar.move_by(-8));
+        assert_eq!(calendar.selected, day(1, 31));
+        calendar.move_month(true);
+        assert_eq!(calendar.selected, day(2, 29));
+
+        let mut app = App::new();
+        let mut other = stored(2, "2024-05-01", "Cake", "Food", 4.5);
+        other.date = "2024-05-02".to_string();
+        app.set_expenses(vec![stored(1, "2024-05-01", "Tea", "Food", 4.5), other]);
+        app.calendar = Some(calendar);
+        app.screen = Screen::Calendar;
+        app.show_day(day(5, 2));
+        assert_eq!(app.screen, Screen::Transactions);
+        assert_eq!(app.expenses.len(), 1);
+        assert_eq!(app.selected_index, Some(0));
+        assert!(app.filter_description().is_some());
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut app = App::new();
+        let mut other = stored(2, "2024-05-01", "Cake", "Food", 4.5);
+        other.category = "Treats".to_string();
+        app.set_expenses(vec![
+            stored(1, "2024-05-01", "Tea", "Food", 4.5),
+            stored(3, "2024-05-01", "Bread", "Food", 4.5),
+            other,
+        ]);
+        app.filter.text = Some("e".to_string());
+        app.apply_filter();
+        assert_eq!(app.expenses.len(), 3);
+
+        app.screen = Screen::Categories;
+        app.move_screen_row(true, 2);
+        app.move_screen_row(true, 2);
+        assert_eq!(app.screen_row(Screen::Categories), 1);
+        assert_eq!(app.category_totals()[0].category, "Food");
+        app.drill_down(ExpenseFilter {
+            categories: vec!["Treats".to_string()],
+            ..ExpenseFilter::default()
+        });
+        assert_eq!(app.screen, Screen::Transactions);
+        assert_eq!(app.expenses.len(), 1);
+
+        assert!(app.go_back());
+        assert_eq!(app.screen, Screen::Categories);
+        assert_eq!(app.filter.text.as_deref(), Some("e"));
+        assert_eq!(app.screen_row(Screen::Categories), 1);
+        assert!(!app.go_back());
+
+        assert_eq!(Screen::Transactions.previous(), Screen::Settings);
+        assert_eq!(Screen::Settings.next(), Screen::Transactions);
+    }
+
+    #[test]
+    fn test_expense_history_revert() {
+        let original = stored(3, "2024-05-01", "Rent", "Food", 4.5);
+        let mut current = original.clone();
+        current.amount = 9.0;
+        current.status = ExpenseStatus::Cleared;
+        let entry = |before: Option<&Expense>, after: &Expense| AuditEntry {
+            uid: after.uid.clone(),
+            timestamp: now_timestamp(),
+            actor: "anna@laptop".to_string(),
+            action: AuditAction::Update,
+            old_values: before.and_then(|e| serde_json::to_string(e).ok()),
+            new_values: serde_json::to_string(after).ok(),
+        };
+        let mut history = ExpenseHistory::new(
+            current.clone(),
+            vec![entry(Some(&original), &current), entry(None, &original)],
+        );
+        assert_eq!(history.reverted(), None);
+
+        history.selected = 1;
+        let reverted = history.reverted().unwrap();
+        assert_eq!(reverted.amount, 4.5);
+        assert_eq!(reverted.id, Some(3));
+        // The status belongs to reconciliation and isn't reverted
+        assert_eq!(reverted.status, ExpenseStatus::Cleared);
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut app = App::new();
+        app.set_expenses(vec![
+            stored(1, "2024-05-01", "Tea", "Food", 4.5),
+            stored(2, "2024-05-01", "Coffee", "Food", 4.5),
+            stored(3, "2024-05-01", "Cake", "Food", 4.5),
+        ]);
+        app.selected_index = Some(0);
+        app.toggle_mark();
+        assert_eq!(app.selected_index, Some(1));
+        app.extend_marks(true);
+        assert_eq!(app.marked, BTreeSet::from([1, 2, 3]));
+        app.toggle_mark();
+        assert_eq!(app.marked, BTreeSet::from([1, 2]));
+
+        app.filter.text = Some("c".to_string());
+        app.apply_filter();
+        app.mark_all();
+        assert_eq!(app.marked.len(), 3);
+        app.mark_all();
+        assert_eq!(app.marked, BTreeSet::from([1]));
+
+        app.set_expenses(vec![stored(2, "2024-05-01", "Coffee", "Food", 4.5)]);
+        assert!(app.marked.is_empty());
     }
 }
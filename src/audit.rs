@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::expense::Expense;
+
+/// What a change did to an expense.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum AuditAction {
+    Insert,
+    Update,
+    /// Moved to the trash.
+    Delete,
+    /// Taken back out of the trash.
+    Restore,
+    /// Deleted for good.
+    Purge,
+}
+
+impl AuditAction {
+    /// Works out the action from the expense as stored before and after the
+    /// change, `None` when there's nothing to record.
+    pub fn between(before: Option<&Expense>, after: Option<&Expense>) -> Option<Self> {
+        match (before, after) {
+            (None, None) => None,
+            (None, Some(_)) => Some(AuditAction::Insert),
+            (Some(_), None) => Some(AuditAction::Purge),
+            (Some(before), Some(after)) => {
+                match (before.deleted_at.is_some(), after.deleted_at.is_some()) {
+                    (false, true) => Some(AuditAction::Delete),
+                    (true, false) => Some(AuditAction::Restore),
+                    _ if same_values(before, after) => None,
+                    _ => Some(AuditAction::Update),
+                }
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Insert => "added",
+            AuditAction::Update => "edited",
+            AuditAction::Delete => "deleted",
+            AuditAction::Restore => "restored",
+            AuditAction::Purge => "purged",
+        }
+    }
+}
+
+/// One recorded change to an expense. The expense is identified by its uid,
+/// which unlike the id survives saving and syncing.
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub uid: String,
+    /// When the change was made, as an RFC 3339 UTC timestamp.
+    pub timestamp: String,
+    /// The user and device that made it, e.g. `anna@laptop`.
+    pub actor: String,
+    pub action: AuditAction,
+    /// The expense as JSON before the change, `None` for an insert.
+    pub old_values: Option<String>,
+    /// The expense as JSON after the change, `None` for a purge.
+    pub new_values: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn before(&self) -> Option<Expense> {
+        self.old_values
+            .as_deref()
+            .and_then(|json| serde_json::from_str(json).ok())
+    }
+
+    pub fn after(&self) -> Option<Expense> {
+        self.new_values
+            .as_deref()
+            .and_then(|json| serde_json::from_str(json).ok())
+    }
+
+    /// The fields the change touched, as `(field, old, new)`.
+    pub fn changes(&self) -> Vec<(&'static str, String, String)> {
+        let (Some(before), Some(after)) = (self.before(), self.after()) else {
+            return Vec::new();
+        };
+        fields(&before)
+            .into_iter()
+            .zip(fields(&after))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, old), (_, new))| (field, old, new))
+            .collect()
+    }
+}
+
+/// `user@host` for the audit log, from the environment. Falls back to
+/// whichever half is known.
+pub fn device_name() -> String {
+    let var = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .map(|value| value.trim().to_string())
+            .find(|value| !value.is_empty())
+    };
+    let user = var(&["USER", "USERNAME", "LOGNAME"]);
+    let host = var(&["HOSTNAME", "COMPUTERNAME"]).or_else(|| {
+        std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+    });
+    match (user, host) {
+        (Some(user), Some(host)) => format!("{}@{}", user, host),
+        (Some(name), None) | (None, Some(name)) => name,
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+/// Whether two versions of an expense hold the same values. The id and
+/// modification time don't count, they change without the user editing.
+fn same_values(a: &Expense, b: &Expense) -> bool {
+    fields(a) == fields(b)
+}
+
+fn fields(expense: &Expense) -> Vec<(&'static str, String)> {
+    vec![
+        ("date", expense.date.clone()),
+        ("name", expense.name.clone()),
+        ("category", expense.category.clone()),
+        ("amount", format!("{:.2}", expense.amount)),
+        ("account", expense.account.clone()),
+        ("notes", expense.notes.clone()),
+        ("tags", expense.tags.clone()),
+        ("status", expense.status.as_str().to_string()),
+        (
+            "external id",
+            expense.external_id.clone().unwrap_or_default(),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_audit_action_and_changes() {
+        let before = Expense::new(
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            "Rent",
+            "Housing",
+            700.0,
+        )
+        .unwrap();
+        let mut after = before.clone();
+        after.modified_at = "2024-03-02T00:00:00.000Z".to_string();
+        assert_eq!(AuditAction::between(Some(&before), Some(&after)), None);
+
+        after.amount = 730.0;
+        assert_eq!(
+            AuditAction::between(Some(&before), Some(&after)),
+            Some(AuditAction::Update)
+        );
+        let mut trashed = after.clone();
+        trashed.deleted_at = Some("2024-03-03T00:00:00.000Z".to_string());
+        assert_eq!(
+            AuditAction::between(Some(&after), Some(&trashed)),
+            Some(AuditAction::Delete)
+        );
+        assert_eq!(
+            AuditAction::between(Some(&trashed), Some(&after)),
+            Some(AuditAction::Restore)
+        );
+        assert_eq!(
+            AuditAction::between(None, Some(&before)),
+            Some(AuditAction::Insert)
+        );
+
+        let entry = AuditEntry {
+            uid: before.uid.clone(),
+            timestamp: after.modified_at.clone(),
+            actor: "anna@laptop".to_string(),
+            action: AuditAction::Update,
+            old_values: serde_json::to_string(&before).ok(),
+            new_values: serde_json::to_string(&after).ok(),
+        };
+        assert_eq!(
+            entry.changes(),
+            vec![("amount", "700.00".to_string(), "730.00".to_string())]
+        );
+        assert_eq!(entry.after(), Some(after));
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Monthly spending limit for a category.
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Budget {
+    pub category: String,
+    pub amount: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BudgetStatus {
+    pub category: String,
+    pub limit: f64,
+    pub spent: f64,
+}
+
+impl BudgetStatus {
+    pub fn remaining(&self) -> f64 {
+        self.limit - self.spent
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.spent > self.limit
+    }
+
+    /// Share of the limit that has been spent, in percent.
+    pub fn used_percent(&self) -> f64 {
+        if self.limit <= 0.0 {
+            0.0
+        } else {
+            self.spent / self.limit * 100.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_status() {
+        let status = BudgetStatus {
+            category: "Food".to_string(),
+            limit: 200.0,
+            spent: 250.0,
+        };
+        assert!(status.is_over());
+        assert_eq!(status.remaining(), -50.0);
+        assert_eq!(status.used_percent(), 125.0);
+    }
+}
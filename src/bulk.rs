@@ -0,0 +1,188 @@
+use chrono::{Duration, NaiveDate};
+
+use crate::expense::{now_timestamp, Expense, ExpenseStatus};
+use crate::undo::Change;
+
+/// Something that can be done to all the marked expenses at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BulkAction {
+    Delete,
+    ChangeCategory,
+    AddTag,
+    RemoveTag,
+    ShiftDate,
+    Export,
+}
+
+impl BulkAction {
+    pub const ALL: [BulkAction; 6] = [
+        BulkAction::Delete,
+        BulkAction::ChangeCategory,
+        BulkAction::AddTag,
+        BulkAction::RemoveTag,
+        BulkAction::ShiftDate,
+        BulkAction::Export,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            BulkAction::Delete => "Move to the trash",
+            BulkAction::ChangeCategory => "Change the category",
+            BulkAction::AddTag => "Add a tag",
+            BulkAction::RemoveTag => "Remove a tag",
+            BulkAction::ShiftDate => "Shift the date",
+            BulkAction::Export => "Export to CSV",
+        }
+    }
+
+    /// What to ask for before the action can run, `None` when it needs
+    /// nothing.
+    pub fn prompt(&self) -> Option<&'static str> {
+        match self {
+            BulkAction::Delete => None,
+            BulkAction::ChangeCategory => Some("New category"),
+            BulkAction::AddTag | BulkAction::RemoveTag => Some("Tag"),
+            BulkAction::ShiftDate => Some("Days to move by, negative for earlier"),
+            BulkAction::Export => Some("File to write"),
+        }
+    }
+
+    /// What the prompt starts out with.
+    pub fn default_input(&self) -> &'static str {
+        match self {
+            BulkAction::Export => "selection.csv",
+            _ => "",
+        }
+    }
+}
+
+/// The changes that carry out the action on `expenses`, as one step to undo
+/// and redo. Reconciled expenses are locked and left out, as are ones the
+/// action wouldn't change. Export writes nothing to the ledger and gives an
+/// empty step.
+pub fn plan(action: BulkAction, expenses: &[Expense], input: &str) -> Result<Change, String> {
+    let input = input.trim();
+    let days = match action {
+        BulkAction::ShiftDate => input
+            .parse::<i64>()
+            .map_err(|_| format!("'{}' is not a number of days", input))?,
+        _ => 0,
+    };
+    if input.is_empty() {
+        match action {
+            BulkAction::ChangeCategory => return Err("Category must not be empty".to_string()),
+            BulkAction::AddTag | BulkAction::RemoveTag => {
+                return Err("Tag must not be empty".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    let unlocked = expenses
+        .iter()
+        .filter(|expense| expense.status != ExpenseStatus::Reconciled);
+    let mut changes = Vec::new();
+    for expense in unlocked {
+        let mut after = expense.clone();
+        let changed = match action {
+            BulkAction::Delete => {
+                changes.push(Change::Delete(Box::new(expense.clone())));
+                continue;
+            }
+            BulkAction::Export => false,
+            BulkAction::ChangeCategory => {
+                after.category = input.to_string();
+                after.category != expense.category
+            }
+            BulkAction::AddTag => after.add_tag(input),
+            BulkAction::RemoveTag => after.remove_tag(input),
+            BulkAction::ShiftDate => {
+                let date = NaiveDate::parse_from_str(&expense.date, "%Y-%m-%d")
+                    .map_err(|_| format!("'{}' has no valid date", expense.name))?;
+                let date = date
+                    .checked_add_signed(Duration::days(days))
+                    .ok_or_else(|| format!("Can't move '{}' that far", expense.name))?;
+                after.date = date.format("%Y-%m-%d").to_string();
+                days != 0
+            }
+        };
+        if changed {
+            after.modified_at = now_timestamp();
+            changes.push(Change::Update {
+                before: Box::new(expense.clone()),
+                after: Box::new(after),
+            });
+        }
+    }
+
+    let count = match changes.len() {
+        1 => "1 expense".to_string(),
+        n => format!("{} expenses", n),
+    };
+    let description = match action {
+        BulkAction::Delete => format!("deleting {}", count),
+        BulkAction::ChangeCategory => format!("moving {} to '{}'", count, input),
+        BulkAction::AddTag => format!("tagging {} '{}'", count, input),
+        BulkAction::RemoveTag => format!("removing '{}' from {}", input, count),
+        BulkAction::ShiftDate => format!("shifting {} by {} days", count, days),
+        BulkAction::Export => format!("exporting {}", count),
+    };
+    Ok(Change::Bulk {
+        description,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::stored;
+
+    fn updated(change: &Change) -> Vec<Expense> {
+        let Change::Bulk { changes, .. } = change else {
+            panic!("not a bulk change");
+        };
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Update { after, .. } => Some((**after).clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut locked = stored(3, "2024-05-02", "Statement", "Food", 5.0);
+        locked.status = ExpenseStatus::Reconciled;
+        let mut tagged = stored(2, "2024-05-31", "Dinner", "Food", 5.0);
+        tagged.tags = "Holiday".to_string();
+        let expenses = vec![
+            stored(1, "2024-05-01", "Lunch", "Food", 5.0),
+            tagged,
+            locked,
+        ];
+
+        let change = plan(BulkAction::ChangeCategory, &expenses, " Eating out ").unwrap();
+        assert_eq!(change.describe(), "moving 2 expenses to 'Eating out'");
+        assert!(updated(&change)
+            .iter()
+            .all(|expense| expense.category == "Eating out"));
+
+        let change = plan(BulkAction::AddTag, &expenses, "holiday").unwrap();
+        let tagged = updated(&change);
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].tags, "holiday");
+        let change = plan(BulkAction::RemoveTag, &expenses, "HOLIDAY").unwrap();
+        assert_eq!(updated(&change)[0].tags, "");
+
+        let change = plan(BulkAction::ShiftDate, &expenses, "1").unwrap();
+        let dates: Vec<String> = updated(&change).into_iter().map(|e| e.date).collect();
+        assert_eq!(dates, vec!["2024-05-02", "2024-06-01"]);
+        assert!(plan(BulkAction::ShiftDate, &expenses, "soon").is_err());
+        assert!(plan(BulkAction::AddTag, &expenses, " ").is_err());
+
+        let change = plan(BulkAction::Delete, &expenses, "").unwrap();
+        assert_eq!(change.describe(), "deleting 2 expenses");
+    }
+}
@@ -0,0 +1,762 @@
+use std::fmt;
+use std::io::Write;
+
+use chrono::{Datelike, NaiveDate};
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::crypto::Key;
+use crate::db::{Database, ExpenseChange};
+use crate::duplicates::{commit_import, preview_import_file, DuplicateAction, DuplicateOptions};
+use crate::expense::{now_timestamp, Expense, ExpenseStatus};
+use crate::filter::ExpenseFilter;
+use crate::output::{write_records, OutputFormat};
+use crate::period::{month_bounds, parse_month, Month};
+use crate::report::{self, text::TextStyle};
+use crate::summary::PeriodSummary;
+
+/// Commands that work on a ledger without opening the TUI.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add an expense
+    Add(NewExpense),
+    /// List expenses, optionally filtered
+    List {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Change fields of an expense
+    Edit {
+        id: i64,
+        #[command(flatten)]
+        changes: ExpenseChanges,
+    },
+    /// Delete expenses by id
+    Delete {
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+    /// Import expenses from a JSON file. Likely duplicates are merged into the
+    /// existing entry unless --keep-duplicates is given.
+    Import {
+        file: String,
+        #[arg(long)]
+        keep_duplicates: bool,
+    },
+    /// Export every expense to a file
+    Export {
+        #[arg(value_enum)]
+        kind: ExportKind,
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Print or write a summary report, for the current month by default
+    Report(ReportArgs),
+    /// Show or change monthly budgets
+    Budget {
+        #[command(subcommand)]
+        action: BudgetCommand,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct NewExpense {
+    /// Defaults to today
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+    #[arg(long)]
+    pub name: String,
+    #[arg(long)]
+    pub category: String,
+    #[arg(long)]
+    pub amount: f64,
+    #[arg(long, default_value = "")]
+    pub account: String,
+    #[arg(long, default_value = "")]
+    pub notes: String,
+    /// Comma separated
+    #[arg(long, default_value = "")]
+    pub tags: String,
+    #[arg(long)]
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExpenseChanges {
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+    #[arg(long)]
+    pub name: Option<String>,
+    #[arg(long)]
+    pub category: Option<String>,
+    #[arg(long)]
+    pub amount: Option<f64>,
+    #[arg(long)]
+    pub account: Option<String>,
+    #[arg(long)]
+    pub notes: Option<String>,
+    /// Comma separated
+    #[arg(long)]
+    pub tags: Option<String>,
+    #[arg(long)]
+    pub external_id: Option<String>,
+    /// pending or cleared; expenses are reconciled by finishing a
+    /// reconciliation
+    #[arg(long)]
+    pub status: Option<ExpenseStatus>,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct FilterArgs {
+    /// Text to look for in the name, category or notes
+    #[arg(long)]
+    pub search: Option<String>,
+    #[arg(long)]
+    pub category: Option<String>,
+    /// YYYY-MM
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub month: Option<String>,
+    /// First day to include
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+    /// Last day to include
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+    #[arg(long)]
+    pub account: Option<String>,
+    /// pending, cleared or reconciled
+    #[arg(long)]
+    pub status: Option<ExpenseStatus>,
+    #[arg(long)]
+    pub min_amount: Option<f64>,
+    #[arg(long)]
+    pub max_amount: Option<f64>,
+}
+
+impl FilterArgs {
+    pub fn to_filter(&self) -> Result<ExpenseFilter, CliError> {
+        let (start, end) = match &self.month {
+            Some(month) => {
+                let (first, last) = month_range(month)?;
+                (Some(first), Some(last))
+            }
+            None => (self.from, self.to),
+        };
+        Ok(ExpenseFilter {
+            text: self.search.clone(),
+            category: self.category.clone(),
+            categories: Vec::new(),
+            start,
+            end,
+            account: self.account.clone(),
+            status: self.status,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+        })
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct PeriodArgs {
+    /// YYYY-MM
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub month: Option<String>,
+    #[arg(long, requires = "to")]
+    pub from: Option<NaiveDate>,
+    #[arg(long, requires = "from")]
+    pub to: Option<NaiveDate>,
+}
+
+impl PeriodArgs {
+    /// The chosen range, or the month `today` falls in.
+    pub fn range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), CliError> {
+        match (&self.month, self.from, self.to) {
+            (Some(month), _, _) => month_range(month),
+            (None, Some(from), Some(to)) if from <= to => Ok((from, to)),
+            (None, Some(_), Some(_)) => Err(CliError::Invalid(
+                "--from must not be after --to".to_string(),
+            )),
+            _ => Ok(month_bounds(today.year(), today.month()).expect("today is a valid month")),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub period: PeriodArgs,
+    /// Layout of the table format
+    #[arg(long, value_enum, default_value_t = ReportStyle::Markdown)]
+    pub style: ReportStyle,
+    /// json gives the whole summary; jsonl, csv and tsv give one row per
+    /// category
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Write to this file instead of printing
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ExportKind {
+    Xlsx,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ReportStyle {
+    Markdown,
+    Ascii,
+    /// Only for whole months
+    Html,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BudgetCommand {
+    List {
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    Set {
+        category: String,
+        amount: f64,
+    },
+    Delete {
+        category: String,
+    },
+}
+
+/// Error from a command, mapped to the process exit code.
+#[derive(Debug)]
+pub enum CliError {
+    /// Bad arguments or input data.
+    Invalid(String),
+    NotFound(String),
+    Failed(String),
+}
+
+impl CliError {
+    /// 2 matches clap's own usage errors, so scripts can treat all input
+    /// mistakes alike.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Failed(_) => 1,
+            CliError::Invalid(_) => 2,
+            CliError::NotFound(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Invalid(message)
+            | CliError::NotFound(message)
+            | CliError::Failed(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<sqlx::Error> for CliError {
+    fn from(err: sqlx::Error) -> Self {
+        CliError::Failed(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(err: std::io::Error) -> Self {
+        CliError::Failed(err.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for CliError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        CliError::Failed(err.to_string())
+    }
+}
+
+fn month_range(month: &str) -> Result<(NaiveDate, NaiveDate), CliError> {
+    parse_month(month)
+        .and_then(|(year, month)| month_bounds(year, month))
+        .ok_or_else(|| CliError::Invalid(format!("invalid month '{}', use YYYY-MM", month)))
+}
+
+/// Runs a command against an already loaded ledger, writing its output to
+/// `out`. Returns whether the data changed and has to be saved.
+pub async fn run(
+    command: &Command,
+    db: &Database,
+    key: Option<&Key>,
+    today: NaiveDate,
+    out: &mut dyn Write,
+) -> Result<bool, CliError> {
+    match command {
+        Command::Add(new) => {
+            let mut expense = Expense::new(
+                new.date.unwrap_or(today),
+                &new.name,
+                &new.category,
+                new.amount,
+            )
+            .map_err(CliError::Invalid)?;
+            expense.account = new.account.clone();
+            expense.notes = new.notes.clone();
+            expense.tags = new.tags.clone();
+            expense.external_id = new.external_id.clone();
+            let id = db.insert_expense(&expense).await?;
+            writeln!(out, "Added expense {}", id)?;
+            Ok(true)
+        }
+        Command::List { filter, format } => {
+            let expenses = filter.to_filter()?.apply(db.list_expenses().await?);
+            match format {
+                OutputFormat::Table => write_expense_table(out, &expenses)?,
+                format => write_records(out, *format, &expenses)?,
+            }
+            Ok(false)
+        }
+        Command::Edit { id, changes } => {
+            edit(db, *id, changes).await?;
+            writeln!(out, "Updated expense {}", id)?;
+            Ok(true)
+        }
+        Command::Delete { ids } => {
+            for id in ids {
+                if db.get_expense(*id).await?.is_none() {
+                    return Err(CliError::NotFound(format!("no expense with id {}", id)));
+                }
+            }
+            // All of them go to the trash or, e.g. if one is locked, none do
+            let changes: Vec<ExpenseChange> =
+                ids.iter().map(|id| ExpenseChange::Trash(*id)).collect();
+            db.apply_changes(&changes).await?;
+            writeln!(out, "Deleted {} expenses", ids.len())?;
+            Ok(true)
+        }
+        Command::Import {
+            file,
+            keep_duplicates,
+        } => {
+            let preview = preview_import_file(db, file, &DuplicateOptions::default())
+                .await
+                .map_err(|err| CliError::Invalid(format!("can't read {}: {}", file, err)))?;
+            let action = if *keep_duplicates {
+                DuplicateAction::Keep
+            } else {
+                DuplicateAction::Merge
+            };
+            let actions = vec![action; preview.matches.len()];
+            let inserted = commit_import(db, &preview, &actions).await?;
+            writeln!(
+                out,
+                "Imported {} expenses, {} likely duplicates {}",
+                inserted,
+                preview.matches.len(),
+                if *keep_duplicates { "kept" } else { "merged" }
+            )?;
+            Ok(inserted > 0 || !preview.matches.is_empty())
+        }
+        Command::Export { kind, output } => {
+            let written = match kind {
+                ExportKind::Xlsx => {
+                    let filename = output.as_deref().unwrap_or("expenses.xlsx");
+                    report::xlsx::export_workbook(db, filename, key).await?
+                }
+                ExportKind::Json => {
+                    let filename = output.as_deref().unwrap_or("expenses-export.json");
+                    let json = serde_json::to_vec_pretty(&db.list_expenses().await?)
+                        .map_err(|err| CliError::Failed(err.to_string()))?;
+                    report::write_report(filename, &json, key)?
+                }
+            };
+            writeln!(out, "Wrote {}", written)?;
+            Ok(false)
+        }
+        Command::Report(args) => {
+            report(db, key, today, args, out).await?;
+            Ok(false)
+        }
+        Command::Budget { action } => budget(db, action, out).await,
+    }
+}
+
+async fn edit(db: &Database, id: i64, changes: &ExpenseChanges) -> Result<(), CliError> {
+    let mut expense = db
+        .get_expense(id)
+        .await?
+        .ok_or_else(|| CliError::NotFound(format!("no expense with id {}", id)))?;
+    if changes.amount.is_some_and(|amount| amount < 0.0) {
+        return Err(CliError::Invalid("Amount must be positive".to_string()));
+    }
+    if changes.status == Some(ExpenseStatus::Reconciled) {
+        return Err(CliError::Invalid(
+            "expenses are reconciled by finishing a reconciliation".to_string(),
+        ));
+    }
+
+    if let Some(date) = changes.date {
+        expense.date = date.format("%Y-%m-%d").to_string();
+    }
+    if let Some(name) = &changes.name {
+        expense.name = name.clone();
+    }
+    if let Some(category) = &changes.category {
+        expense.category = category.clone();
+    }
+    if let Some(amount) = changes.amount {
+        expense.amount = amount;
+    }
+    if let Some(account) = &changes.account {
+        expense.account = account.clone();
+    }
+    if let Some(notes) = &changes.notes {
+        expense.notes = notes.clone();
+    }
+    if let Some(tags) = &changes.tags {
+        expense.tags = tags.clone();
+    }
+    if let Some(external_id) = &changes.external_id {
+        expense.external_id = Some(external_id.clone());
+    }
+    if let Some(status) = changes.status {
+        expense.status = status;
+    }
+    let changed = changes.date.is_some()
+        || changes.name.is_some()
+        || changes.category.is_some()
+        || changes.amount.is_some()
+        || changes.account.is_some()
+        || changes.notes.is_some()
+        || changes.tags.is_some()
+        || changes.external_id.is_some()
+        || changes.status.is_some();
+    if changed {
+        // The fields and the status change together or not at all
+        expense.modified_at = now_timestamp();
+        db.apply_changes(&[ExpenseChange::Put(Box::new(expense))])
+            .await?;
+    }
+    Ok(())
+}
+
+async fn report(
+    db: &Database,
+    key: Option<&Key>,
+    today: NaiveDate,
+    args: &ReportArgs,
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    let (start, end) = args.period.range(today)?;
+    let text = match (args.format, args.style) {
+        (OutputFormat::Table, style @ (ReportStyle::Markdown | ReportStyle::Ascii)) => {
+            let text_style = if style == ReportStyle::Markdown {
+                TextStyle::Markdown
+            } else {
+                TextStyle::Ascii
+            };
+            let summary = PeriodSummary::load(db, start, end).await?;
+            report::text::render_summary(&summary, text_style)
+        }
+        (OutputFormat::Table, ReportStyle::Html) => {
+            let whole_month = start.day() == 1
+                && month_bounds(start.year(), start.month()).is_some_and(|(_, last)| last == end);
+            if !whole_month {
+                return Err(CliError::Invalid(
+                    "HTML reports cover a whole month, use --month".to_string(),
+                ));
+            }
+            let month = Month::new(start.year(), start.month()).expect("checked above");
+            let summary = crate::summary::MonthlySummary::load(db, month).await?;
+            let expenses = db.filter_by_month(start.year(), start.month()).await?;
+            report::html::render_monthly_report(&summary, &expenses)
+        }
+        (OutputFormat::Json, _) => {
+            let summary = PeriodSummary::load(db, start, end).await?;
+            let mut json = serde_json::to_string_pretty(&summary)
+                .map_err(|err| CliError::Failed(err.to_string()))?;
+            json.push('\n');
+            json
+        }
+        (format, _) => {
+            let categories = db.category_totals(start, end).await?;
+            let mut buffer = Vec::new();
+            write_records(&mut buffer, format, &categories)?;
+            String::from_utf8_lossy(&buffer).into_owned()
+        }
+    };
+
+    match &args.output {
+        Some(filename) => {
+            let written = report::write_report(filename, text.as_bytes(), key)?;
+            writeln!(out, "Wrote {}", written)?;
+        }
+        None => write!(out, "{}", text)?,
+    }
+    Ok(())
+}
+
+async fn budget(
+    db: &Database,
+    action: &BudgetCommand,
+    out: &mut dyn Write,
+) -> Result<bool, CliError> {
+    match action {
+        BudgetCommand::List { format } => {
+            write_records(out, *format, &db.list_budgets().await?)?;
+            Ok(false)
+        }
+        BudgetCommand::Set { category, amount } => {
+            if *amount < 0.0 {
+                return Err(CliError::Invalid(
+                    "budget amount must be positive".to_string(),
+                ));
+            }
+            db.set_budget(category, *amount).await?;
+            writeln!(out, "Budget for {} set to {:.2}", category, amount)?;
+            Ok(true)
+        }
+        BudgetCommand::Delete { category } => {
+            let exists = db
+                .list_budgets()
+                .await?
+                .iter()
+                .any(|budget| &budget.category == category);
+            if !exists {
+                return Err(CliError::NotFound(format!("no budget for {}", category)));
+            }
+            db.delete_budget(category).await?;
+            writeln!(out, "Deleted budget for {}", category)?;
+            Ok(true)
+        }
+    }
+}
+
+fn write_expense_table(out: &mut dyn Write, expenses: &[Expense]) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{:>5}  {:<10}  {:<24}  {:<16}  {:>10}  {:<12}  STATUS",
+        "ID", "DATE", "NAME", "CATEGORY", "AMOUNT", "ACCOUNT"
+    )?;
+    for expense in expenses {
+        writeln!(
+            out,
+            "{:>5}  {:<10}  {:<24}  {:<16}  {:>10.2}  {:<12}  {}",
+            expense.id.unwrap_or_default(),
+            expense.date,
+            expense.name,
+            expense.category,
+            expense.amount,
+            expense.account,
+            expense.status.as_str()
+        )?;
+    }
+    let total: f64 = expenses.iter().map(|expense| expense.amount).sum();
+    writeln!(out, "{} expenses, total {:.2}", expenses.len(), total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(subcommand)]
+        command: Command,
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 15).unwrap()
+    }
+
+    async fn exec(db: &Database, args: &[&str]) -> Result<(bool, String), CliError> {
+        let cli =
+            TestCli::try_parse_from(std::iter::once("expense_tracker").chain(args.iter().copied()))
+                .map_err(|err| CliError::Invalid(err.to_string()))?;
+        let mut out = Vec::new();
+        let changed = run(&cli.command, db, None, today(), &mut out).await?;
+        Ok((changed, String::from_utf8(out).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_add_list_edit_delete() {
+        let db = Database::new().await.unwrap();
+        let (changed, output) = exec(
+            &db,
+            &[
+                "add",
+                "--name",
+                "Lunch",
+                "--category",
+                "Food",
+                "--amount",
+                "12.5",
+            ],
+        )
+        .await
+        .unwrap();
+        assert!(changed);
+        assert_eq!(output, "Added expense 1\n");
+        exec(
+            &db,
+            &[
+                "add",
+                "--date",
+                "2024-04-01",
+                "--name",
+                "Rent",
+                "--category",
+                "Home",
+                "--amount",
+                "900",
+            ],
+        )
+        .await
+        .unwrap();
+
+        let (changed, output) = exec(&db, &["list", "--month", "2024-05"]).await.unwrap();
+        assert!(!changed);
+        assert!(output.contains("Lunch"));
+        assert!(!output.contains("Rent"));
+        assert!(output.ends_with("1 expenses, total 12.50\n"));
+
+        exec(&db, &["edit", "1", "--amount", "14", "--status", "cleared"])
+            .await
+            .unwrap();
+        let lunch = db.get_expense(1).await.unwrap().unwrap();
+        assert_eq!(lunch.amount, 14.0);
+        assert_eq!(lunch.status, ExpenseStatus::Cleared);
+        let err = exec(
+            &db,
+            &["edit", "1", "--amount", "15", "--status", "reconciled"],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(db.get_expense(1).await.unwrap().unwrap().amount, 14.0);
+
+        let err = exec(&db, &["delete", "1", "7"]).await.unwrap_err();
+        assert_eq!(err.exit_code(), 3);
+        assert_eq!(db.list_expenses().await.unwrap().len(), 2);
+
+        let mut statement = db.get_expense(2).await.unwrap().unwrap();
+        statement.id = None;
+        statement.uid = String::new();
+        statement.account = "Bank".to_string();
+        statement.status = ExpenseStatus::Cleared;
+        let locked = db.insert_expense(&statement).await.unwrap();
+        let date = NaiveDate::parse_from_str(&statement.date, "%Y-%m-%d").unwrap();
+        db.finish_reconciliation("Bank", date).await.unwrap();
+        let locked_arg = locked.to_string();
+        assert!(exec(&db, &["delete", "1", &locked_arg]).await.is_err());
+        assert_eq!(db.list_expenses().await.unwrap().len(), 3);
+
+        exec(&db, &["delete", "1", "2"]).await.unwrap();
+        assert_eq!(db.list_expenses().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_formats() {
+        let db = Database::new().await.unwrap();
+        exec(
+            &db,
+            &[
+                "add",
+                "--name",
+                "Tea",
+                "--category",
+                "Food",
+                "--amount",
+                "2.5",
+            ],
+        )
+        .await
+        .unwrap();
+
+        let (_, output) = exec(&db, &["list", "--format", "jsonl"]).await.unwrap();
+        let expense: Expense = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(expense.name, "Tea");
+
+        let (_, output) = exec(&db, &["list", "--format", "tsv", "--category", "Travel"])
+            .await
+            .unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with("id\tdate\tname"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input() {
+        let db = Database::new().await.unwrap();
+        let err = exec(
+            &db,
+            &[
+                "add",
+                "--name",
+                "Refund",
+                "--category",
+                "Food",
+                "--amount",
+                "-3",
+            ],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+
+        let err = exec(&db, &["list", "--month", "May"]).await.unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+
+        let err = exec(
+            &db,
+            &[
+                "report",
+                "--from",
+                "2024-05-01",
+                "--to",
+                "2024-05-10",
+                "--style",
+                "html",
+            ],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_budget_and_report() {
+        let db = Database::new().await.unwrap();
+        exec(&db, &["budget", "set", "Food", "100"]).await.unwrap();
+        exec(
+            &db,
+            &[
+                "add",
+                "--name",
+                "Groceries",
+                "--category",
+                "Food",
+                "--amount",
+                "120",
+            ],
+        )
+        .await
+        .unwrap();
+
+        let (_, output) = exec(&db, &["budget", "list"]).await.unwrap();
+        assert_eq!(output, "CATEGORY  AMOUNT\nFood      100.00\n");
+
+        let (_, output) = exec(&db, &["report", "--style", "markdown"]).await.unwrap();
+        assert!(output.contains("2024-05-01"));
+        assert!(output.contains("Groceries"));
+
+        let err = exec(&db, &["budget", "delete", "Travel"])
+            .await
+            .unwrap_err();
+        assert_eq!(err.exit_code(), 3);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, Weekday};
+use serde::Deserialize;
+
+use crate::keymap::{Keymap, KeysConfig};
+use crate::ledger::DEFAULT_ROOT;
+use crate::table::Column;
+use crate::theme::{Theme, ThemeConfig};
+
+const APP_DIR: &str = "expense-tracker";
+const CONFIG_FILE: &str = "config.toml";
+
+/// Locales that write `1.234,56` rather than `1,234.56`.
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "cs", "da", "de", "es", "fi", "fr", "id", "it", "nb", "nl", "pl", "pt", "ru", "sv", "tr", "uk",
+];
+
+/// Settings read from `config.toml`. Every key is optional; missing ones keep
+/// the defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data: DataConfig,
+    pub display: DisplayConfig,
+    pub behavior: BehaviorConfig,
+    /// Key bindings replacing the defaults, per mode.
+    pub keys: KeysConfig,
+    /// User themes by name, chosen with `display.theme`.
+    pub themes: BTreeMap<String, ThemeConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    /// Directory holding the ledgers. `~/` is expanded.
+    pub dir: PathBuf,
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_ROOT),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Shown next to amounts when the ledger doesn't set its own.
+    pub currency: String,
+    /// Language tag such as `en-GB` or `de-DE`, used for number formatting.
+    pub locale: String,
+    /// strftime format dates are shown in.
+    pub date_format: String,
+    /// strftime format dates are typed in.
+    pub date_input_format: String,
+    pub week_start: WeekStart,
+    /// A built-in theme (dark, light, high-contrast, monochrome) or one from
+    /// `[themes]`.
+    pub theme: String,
+    /// Columns of the expense table, in order.
+    pub columns: Vec<Column>,
+    /// Widths in characters for columns that shouldn't use the default.
+    pub column_widths: BTreeMap<Column, u16>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            currency: String::new(),
+            locale: "en".to_string(),
+            date_format: "%Y-%m-%d".to_string(),
+            date_input_format: "%Y-%m-%d".to_string(),
+            week_start: WeekStart::Monday,
+            theme: "dark".to_string(),
+            columns: Column::DEFAULT_VISIBLE.to_vec(),
+            column_widths: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BehaviorConfig {
+    /// Save after every change instead of only when quitting.
+    pub autosave: bool,
+    /// Ask before deleting an expense in the TUI.
+    pub confirm_delete: bool,
+    /// Days deleted expenses stay in the trash before they are purged when
+    /// the ledger is opened. 0 keeps them until purged by hand.
+    pub trash_retention_days: u32,
+}
+
+impl Default for BehaviorConfig {
+    fn default() -> Self {
+        Self {
+            autosave: false,
+            confirm_delete: true,
+            trash_retention_days: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl WeekStart {
+    pub fn weekday(&self) -> Weekday {
+        match self {
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    /// Syntax errors, unknown keys and values of the wrong type. The message
+    /// from the TOML parser already names the key and shows the line.
+    Parse(PathBuf, String),
+    /// A value of the right type that still makes no sense.
+    Invalid {
+        path: PathBuf,
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Parse(path, message) => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Invalid { path, key, message } => {
+                write!(f, "{}: invalid `{}`: {}", path.display(), key, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/expense-tracker/config.toml` or the platform's
+    /// equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR).join(CONFIG_FILE))
+    }
+
+    /// Loads the config from `path`, or from the default location when no
+    /// path is given. Only a missing default file is fine; a file named
+    /// explicitly must exist.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents, &path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+                Ok(Self::default())
+            }
+            Err(err) => Err(ConfigError::Io(path, err)),
+        }
+    }
+
+    /// Parses and validates config file contents; `path` is only used in
+    /// error messages.
+    pub fn parse(contents: &str, path: &Path) -> Result<Self, ConfigError> {
+        let mut config: Config = toml::from_str(contents)
+            .map_err(|err| ConfigError::Parse(path.to_path_buf(), err.to_string()))?;
+        config.validate(path)?;
+        config.data.dir = expand_home(&config.data.dir);
+        Ok(config)
+    }
+
+    fn validate(&self, path: &Path) -> Result<(), ConfigError> {
+        let invalid = |key: &str, message: String| ConfigError::Invalid {
+            path: path.to_path_buf(),
+            key: key.to_string(),
+            message,
+        };
+        if self.data.dir.as_os_str().is_empty() {
+            return Err(invalid("data.dir", "must not be empty".to_string()));
+        }
+        check_date_format(&self.display.date_format)
+            .map_err(|message| invalid("display.date_format", message))?;
+        check_date_format(&self.display.date_input_format)
+            .map_err(|message| invalid("display.date_input_format", message))?;
+        let language = self.display.locale.split(['-', '_']).next().unwrap_or("");
+        if language.len() < 2 || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid(
+                "display.locale",
+                format!("'{}' is not a language tag like en-GB", self.display.locale),
+            ));
+        }
+        if self.display.columns.is_empty() {
+            return Err(invalid("display.columns", "must not be empty".to_string()));
+        }
+        for (index, column) in self.display.columns.iter().enumerate() {
+            if self.display.columns[..index].contains(column) {
+                return Err(invalid(
+                    "display.columns",
+                    format!("'{}' is listed twice", column.header().to_lowercase()),
+                ));
+            }
+        }
+        if self.display.column_widths.values().any(|width| *width == 0) {
+            return Err(invalid(
+                "display.column_widths",
+                "widths must be at least 1".to_string(),
+            ));
+        }
+        Keymap::from_config(&self.keys).map_err(|err| invalid(&err.key, err.message))?;
+        for name in self.themes.keys().chain([&self.display.theme]) {
+            Theme::load(name, &self.themes, false).map_err(|err| invalid(&err.key, err.message))?;
+        }
+        Ok(())
+    }
+
+    /// Currency of the ledger if it has one, otherwise the configured one.
+    pub fn currency<'a>(&'a self, ledger_currency: Option<&'a str>) -> &'a str {
+        ledger_currency.unwrap_or(&self.display.currency)
+    }
+
+    fn decimal_comma(&self) -> bool {
+        let language = self.display.locale.split(['-', '_']).next().unwrap_or("");
+        DECIMAL_COMMA_LANGUAGES.contains(&language.to_ascii_lowercase().as_str())
+    }
+
+    /// Formats an amount with two decimals and digit grouping for the
+    /// configured locale.
+    pub fn format_amount(&self, amount: f64) -> String {
+        let (group, decimal) = if self.decimal_comma() {
+            ('.', ',')
+        } else {
+            (',', '.')
+        };
+        let formatted = format!("{:.2}", amount.abs());
+        let (whole, fraction) = formatted.split_once('.').unwrap_or((&formatted, "00"));
+        let mut grouped = String::new();
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0 && (whole.len() - index) % 3 == 0 {
+                grouped.push(group);
+            }
+            grouped.push(digit);
+        }
+        let sign = if amount < 0.0 { "-" } else { "" };
+        format!("{}{}{}{}", sign, grouped, decimal, fraction)
+    }
+
+    /// Parses an amount typed in either the locale's or the plain `1234.56`
+    /// notation.
+    pub fn parse_amount(&self, input: &str) -> Option<f64> {
+        let input = input.trim();
+        if self.decimal_comma() && input.contains(',') {
+            input.replace('.', "").replace(',', ".").parse().ok()
+        } else {
+            input.replace(',', "").parse().ok()
+        }
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        date.format(&self.display.date_format).to_string()
+    }
+
+    /// Shows a stored `YYYY-MM-DD` date in the display format, or as is if it
+    /// doesn't parse.
+    pub fn display_date(&self, stored: &str) -> String {
+        match NaiveDate::parse_from_str(stored, "%Y-%m-%d") {
+            Ok(date) => self.format_date(date),
+            Err(_) => stored.to_string(),
+        }
+    }
+
+    pub fn format_input_date(&self, date: NaiveDate) -> String {
+        date.format(&self.display.date_input_format).to_string()
+    }
+
+    pub fn parse_input_date(&self, input: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(input.trim(), &self.display.date_input_format).ok()
+    }
+}
+
+fn check_date_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("'{}' is not a valid strftime format", format));
+    }
+    let sample = NaiveDate::from_ymd_opt(2024, 12, 31).expect("valid date");
+    let formatted = sample.format(format).to_string();
+    if NaiveDate::parse_from_str(&formatted, format) != Ok(sample) {
+        return Err(format!("'{}' must contain the day, month and year", format));
+    }
+    Ok(())
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Config, ConfigError> {
+        Config::parse(contents, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn test_defaults_and_overrides() {
+        assert_eq!(parse("").unwrap(), Config::default());
+
+        let config = parse(
+            r#"
+            [data]
+            dir = "/srv/ledgers"
+
+            [display]
+            currency = "€"
+            locale = "de-DE"
+            date_format = "%d.%m.%Y"
+            week_start = "sunday"
+
+            [behavior]
+            autosave = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.data.dir, PathBuf::from("/srv/ledgers"));
+        assert_eq!(config.display.week_start.weekday(), Weekday::Sun);
+        assert_eq!(config.display.date_input_format, "%Y-%m-%d");
+        assert!(config.behavior.autosave);
+        assert!(config.behavior.confirm_delete);
+        assert_eq!(config.behavior.trash_retention_days, 30);
+        assert_eq!(config.display_date("2024-03-05"), "05.03.2024".to_string());
+        assert_eq!(config.currency(None), "€");
+        assert_eq!(config.currency(Some("£")), "£");
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let err = parse("[display]\nweek_start = \"friday\"\n").unwrap_err();
+        assert!(err.to_string().contains("week_start"), "{}", err);
+
+        let err = parse("[display]\ncolour = \"red\"\n").unwrap_err();
+        assert!(err.to_string().contains("colour"), "{}", err);
+
+        let err = parse("[behavior]\nautosave = \"yes\"\n").unwrap_err();
+        assert!(err.to_string().contains("autosave"), "{}", err);
+
+        let err = parse("[display]\ndate_format = \"%d/%m\"\n").unwrap_err();
+        assert!(err.to_string().contains("`display.date_format`"), "{}", err);
+
+        let err = parse("[display]\nlocale = \"1\"\n").unwrap_err();
+        assert!(err.to_string().contains("`display.locale`"), "{}", err);
+
+        let err = parse("[keys.form]\nsubmit = \"Ctrl-Hyper-s\"\n").unwrap_err();
+        assert!(err.to_string().contains("`keys.form.submit`"), "{}", err);
+
+        let err = parse("[display]\ncolumns = [\"date\", \"date\"]\n").unwrap_err();
+        assert!(err.to_string().contains("`display.columns`"), "{}", err);
+
+        let err = parse("[display]\ncolumns = [\"payee\"]\n").unwrap_err();
+        assert!(err.to_string().contains("payee"), "{}", err);
+
+        let err = parse("[display]\ntheme = \"neon\"\n").unwrap_err();
+        assert!(err.to_string().contains("`display.theme`"), "{}", err);
+    }
+
+    #[test]
+    fn test_amounts() {
+        let mut config = Config::default();
+        assert_eq!(config.format_amount(1234567.891), "1,234,567.89");
+        assert_eq!(config.format_amount(-5.0), "-5.00");
+        assert_eq!(config.parse_amount("1,234.50"), Some(1234.5));
+
+        config.display.locale = "de-DE".to_string();
+        assert_eq!(config.format_amount(1234.5), "1.234,50");
+        assert_eq!(config.parse_amount("1.234,50"), Some(1234.5));
+        assert_eq!(config.parse_amount("0,05"), Some(0.05));
+        assert_eq!(config.parse_amount("0.05"), Some(0.05));
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("nope.toml");
+        assert!(matches!(
+            Config::load(Some(&missing)),
+            Err(ConfigError::Io(..))
+        ));
+
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[display]\ncurrency = \"$\"\n").unwrap();
+        assert_eq!(Config::load(Some(&path)).unwrap().display.currency, "$");
+    }
+}
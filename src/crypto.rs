@@ -0,0 +1,240 @@
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+/// Every encrypted file starts with this, followed by a format version byte.
+const MAGIC: &[u8; 6] = b"EXPENC";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// magic + version + m_cost + t_cost + p_cost + salt + nonce
+const HEADER_LEN: usize = MAGIC.len() + 1 + 12 + SALT_LEN + NONCE_LEN;
+/// Upper bounds on the key derivation parameters a file may ask for, so a
+/// crafted header can't make opening it take gigabytes or hours. A few times
+/// the defaults that are written (19 MiB, two passes, one lane).
+const MAX_M_COST: u32 = 64 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    /// The passphrase is wrong or the file was tampered with; the two can't
+    /// be told apart with authenticated encryption.
+    WrongPassphrase,
+    /// The data doesn't look like one of our encrypted files.
+    Malformed,
+    KeyDerivation(String),
+    Encryption,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::WrongPassphrase => {
+                write!(f, "wrong passphrase or the file has been modified")
+            }
+            CryptoError::Malformed => write!(f, "not a valid encrypted file"),
+            CryptoError::KeyDerivation(err) => write!(f, "key derivation failed: {}", err),
+            CryptoError::Encryption => write!(f, "encryption failed"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// A key derived from a passphrase, kept so the data can be saved again
+/// without asking for the passphrase or holding on to it.
+#[derive(Clone)]
+pub struct Key {
+    salt: [u8; SALT_LEN],
+    params: (u32, u32, u32),
+    key: [u8; 32],
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key").finish_non_exhaustive()
+    }
+}
+
+impl Key {
+    /// Derives a key for a new passphrase with a fresh random salt.
+    pub fn new(passphrase: &str) -> Result<Self, CryptoError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let defaults = Params::default();
+        Self::derive(
+            passphrase,
+            salt,
+            (defaults.m_cost(), defaults.t_cost(), defaults.p_cost()),
+        )
+    }
+
+    fn derive(
+        passphrase: &str,
+        salt: [u8; SALT_LEN],
+        params: (u32, u32, u32),
+    ) -> Result<Self, CryptoError> {
+        let argon_params = Params::new(params.0, params.1, params.2, Some(32))
+            .map_err(|err| CryptoError::KeyDerivation(err.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|err| CryptoError::KeyDerivation(err.to_string()))?;
+        Ok(Key { salt, params, key })
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts with a fresh nonce. The salt and key derivation parameters are
+/// stored in the header so the file can be opened with just the passphrase.
+pub fn encrypt(key: &Key, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let cipher = XChaCha20Poly1305::new((&key.key).into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&key.params.0.to_le_bytes());
+    out.extend_from_slice(&key.params.1.to_le_bytes());
+    out.extend_from_slice(&key.params.2.to_le_bytes());
+    out.extend_from_slice(&key.salt);
+    out.extend_from_slice(&nonce);
+    //INFO: The header is authenticated too, so its parameters can't be swapped
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &out,
+            },
+        )
+        .map_err(|_| CryptoError::Encryption)?;
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypts data written by [`encrypt`], returning the plaintext and the key
+/// so later saves can reuse it.
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<(Vec<u8>, Key), CryptoError> {
+    let header = Header::parse(data)?;
+    let key = Key::derive(passphrase, header.salt, header.params)?;
+    let plaintext = open(&key, &header, data)?;
+    Ok((plaintext, key))
+}
+
+struct Header<'a> {
+    params: (u32, u32, u32),
+    salt: [u8; SALT_LEN],
+    nonce: &'a XNonce,
+}
+
+impl<'a> Header<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, CryptoError> {
+        if data.len() < HEADER_LEN || !is_encrypted(data) {
+            return Err(CryptoError::Malformed);
+        }
+        if data[MAGIC.len()] != VERSION {
+            return Err(CryptoError::Malformed);
+        }
+        let mut offset = MAGIC.len() + 1;
+        let mut next_u32 = || {
+            let value = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+            offset += 4;
+            value
+        };
+        let params = (next_u32(), next_u32(), next_u32());
+        if params.0 > MAX_M_COST || params.1 > MAX_T_COST || params.2 > MAX_P_COST {
+            return Err(CryptoError::Malformed);
+        }
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&data[offset..offset + SALT_LEN]);
+        offset += SALT_LEN;
+        let nonce = XNonce::from_slice(&data[offset..offset + NONCE_LEN]);
+        Ok(Header {
+            params,
+            salt,
+            nonce,
+        })
+    }
+}
+
+fn open(key: &Key, header: &Header, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let cipher = XChaCha20Poly1305::new((&key.key).into());
+    cipher
+        .decrypt(
+            header.nonce,
+            Payload {
+                msg: &data[HEADER_LEN..],
+                aad: &data[..HEADER_LEN],
+            },
+        )
+        .map_err(|_| CryptoError::WrongPassphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let key = Key::new("correct horse").unwrap();
+        let encrypted = encrypt(&key, b"salary: 1234").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.windows(6).any(|w| w == b"salary"));
+
+        let (plaintext, reused) = decrypt(&encrypted, "correct horse").unwrap();
+        assert_eq!(plaintext, b"salary: 1234");
+
+        let again = encrypt(&reused, b"second save").unwrap();
+        assert_ne!(again[..HEADER_LEN], encrypted[..HEADER_LEN]);
+        assert_eq!(decrypt(&again, "correct horse").unwrap().0, b"second save");
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_tampering() {
+        let key = Key::new("correct horse").unwrap();
+        let mut encrypted = encrypt(&key, b"data").unwrap();
+        assert!(matches!(
+            decrypt(&encrypted, "battery staple"),
+            Err(CryptoError::WrongPassphrase)
+        ));
+
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(matches!(
+            decrypt(&encrypted, "correct horse"),
+            Err(CryptoError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            decrypt(b"[]", "correct horse"),
+            Err(CryptoError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_header_is_checked() {
+        let key = Key::new("correct horse").unwrap();
+        let encrypted = encrypt(&key, b"data").unwrap();
+
+        let mut costly = encrypted.clone();
+        costly[MAGIC.len() + 1..MAGIC.len() + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            decrypt(&costly, "correct horse"),
+            Err(CryptoError::Malformed)
+        ));
+
+        let mut unknown = encrypted.clone();
+        unknown[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(
+            decrypt(&unknown, "correct horse"),
+            Err(CryptoError::Malformed)
+        ));
+    }
+}
@@ -1,10 +1,55 @@
+use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
+use std::path::Path;
 
-use crate::expense::Expense;
-use sqlx::{query, query_as, sqlite::SqlitePool, Pool, Sqlite};
+use crate::audit::{device_name, AuditAction, AuditEntry};
+use crate::budget::Budget;
+use crate::crypto::{self, Key};
+use crate::expense::{legacy_uid, new_uid, now_timestamp, Expense, ExpenseStatus};
+use crate::period::month_bounds;
+use crate::summary::{CategoryMonthTotal, CategoryTotal, DayTotal, MerchantTotal, MonthTotal};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, sqlite::SqlitePool, Pool, Sqlite, SqliteConnection};
+use tempfile::NamedTempFile;
+
+/// On-disk layout of the data file. Older files only contain the bare list of
+/// expenses, so both shapes are accepted when loading.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DataFile {
+    Full {
+        expenses: Vec<Expense>,
+        #[serde(default)]
+        budgets: Vec<Budget>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        audit: Vec<AuditEntry>,
+    },
+    ExpensesOnly(Vec<Expense>),
+}
+
+/// A low-level write for [`Database::apply_changes`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpenseChange {
+    /// Writes the expense exactly as given, id, uid, status and modification
+    /// time included, replacing whatever has that id.
+    Put(Box<Expense>),
+    /// Moves the expense with this id to the trash.
+    Trash(i64),
+    /// Deletes the expense with this id for good.
+    Remove(i64),
+}
+
+const AUDIT_COLUMNS: &str = "uid, timestamp, actor, action, old_values, new_values";
+
+const EXPENSE_COLUMNS: &str =
+    "id, date, name, category, amount, external_id, account, notes, status, uid, modified_at, deleted_at, tags";
 
 pub struct Database {
     pool: Pool<Sqlite>,
+    /// Who changes are recorded as in the audit log.
+    actor: String,
 }
 
 impl Database {
@@ -17,55 +62,299 @@ impl Database {
                 date TEXT NOT NULL,
                 name TEXT NOT NULL,
                 category TEXT NOT NULL,
+                amount REAL NOT NULL,
+                external_id TEXT,
+                account TEXT NOT NULL DEFAULT '',
+                notes TEXT NOT NULL DEFAULT '',
+                status TEXT NOT NULL DEFAULT 'pending',
+                uid TEXT NOT NULL UNIQUE,
+                modified_at TEXT NOT NULL,
+                deleted_at TEXT,
+                tags TEXT NOT NULL DEFAULT ''
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS budgets (
+                category TEXT PRIMARY KEY,
                 amount REAL NOT NULL
             )",
         )
         .execute(&pool)
         .await?;
 
-        Ok(Database { pool })
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS audit (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                uid TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                old_values TEXT,
+                new_values TEXT
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Database {
+            pool,
+            actor: device_name(),
+        })
     }
 
+    /// Inserts an expense, keeping its uid and modification time when it has
+    /// them (e.g. when loading a file) and assigning fresh ones otherwise.
     pub async fn insert_expense(&self, expense: &Expense) -> Result<i64, sqlx::Error> {
-        let result =
-            sqlx::query("INSERT INTO expenses (date, name, category, amount) VALUES (?, ?, ?, ?)")
-                .bind(expense.date.to_string())
-                .bind(&expense.name)
-                .bind(&expense.category)
-                .bind(expense.amount)
-                .execute(&self.pool)
-                .await?;
+        let mut tx = self.pool.begin().await?;
+        let id = insert_row(&mut tx, expense).await?;
+        self.record(&mut tx, id, None).await?;
+        tx.commit().await?;
 
-        Ok(result.last_insert_rowid())
+        Ok(id)
     }
 
+    /// The expense with this id, `None` when there is none or it's in the
+    /// trash.
     pub async fn get_expense(&self, id: i64) -> Result<Option<Expense>, sqlx::Error> {
-        query_as::<_, Expense>("SELECT id, date, name, category, amount FROM expenses WHERE id = ?")
-            .bind(id)
-            .fetch_optional(&self.pool)
-            .await
+        query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE id = ? AND deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
     }
 
+    /// Updates everything but the status, which only changes through
+    /// [`Database::set_expense_status`]. Reconciled expenses are locked.
     pub async fn update_expense(&self, expense: &Expense) -> Result<(), sqlx::Error> {
-        query("UPDATE expenses SET date = ?, name = ?, category = ?, amount = ? WHERE id = ?")
-            .bind(expense.date.to_string())
-            .bind(&expense.name)
-            .bind(&expense.category)
-            .bind(expense.amount)
-            .bind(expense.id)
-            .execute(&self.pool)
+        let Some(id) = expense.id else {
+            return Ok(());
+        };
+        let mut tx = self.pool.begin().await?;
+        let before = fetch_row(&mut tx, id).await?;
+        ensure_unlocked(before.as_ref())?;
+        query(
+            "UPDATE expenses SET date = ?, name = ?, category = ?, amount = ?, external_id = ?,
+             account = ?, notes = ?, tags = ?, modified_at = ? WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(expense.date.to_string())
+        .bind(&expense.name)
+        .bind(&expense.category)
+        .bind(expense.amount)
+        .bind(&expense.external_id)
+        .bind(&expense.account)
+        .bind(&expense.notes)
+        .bind(&expense.tags)
+        .bind(now_timestamp())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        self.record(&mut tx, id, before).await?;
+        tx.commit().await
+    }
+
+    /// Moves the expense to the trash. It stays there, left out of every
+    /// list and total, until it's restored or purged.
+    pub async fn delete_expense(&self, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let before = fetch_row(&mut tx, id).await?;
+        ensure_unlocked(before.as_ref())?;
+        query("UPDATE expenses SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(now_timestamp())
+            .bind(id)
+            .execute(&mut *tx)
             .await?;
+        self.record(&mut tx, id, before).await?;
+        tx.commit().await
+    }
 
-        Ok(())
+    /// Expenses in the trash, the most recently deleted first.
+    pub async fn list_trash(&self) -> Result<Vec<Expense>, sqlx::Error> {
+        query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE deleted_at IS NOT NULL
+             ORDER BY deleted_at DESC, id",
+            EXPENSE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
     }
 
-    pub async fn delete_expense(&self, id: i64) -> Result<(), sqlx::Error> {
-        query("DELETE FROM expenses WHERE id = ?")
+    /// Takes an expense back out of the trash.
+    pub async fn restore_expense(&self, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let before = fetch_row(&mut tx, id).await?;
+        query("UPDATE expenses SET deleted_at = NULL WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        self.record(&mut tx, id, before).await?;
+        tx.commit().await
+    }
 
-        Ok(())
+    /// Deletes an expense in the trash for good.
+    pub async fn purge_expense(&self, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let before = fetch_row(&mut tx, id).await?;
+        let result = query("DELETE FROM expenses WHERE id = ? AND deleted_at IS NOT NULL")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() > 0 {
+            self.record(&mut tx, id, before).await?;
+        }
+        tx.commit().await
+    }
+
+    /// Deletes everything that went into the trash before `cutoff`, an RFC
+    /// 3339 timestamp. Returns how many expenses were purged.
+    pub async fn purge_trash(&self, cutoff: &str) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let expired = query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE deleted_at < ?",
+            EXPENSE_COLUMNS
+        ))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+        for expense in &expired {
+            query("DELETE FROM expenses WHERE id = ?")
+                .bind(expense.id)
+                .execute(&mut *tx)
+                .await?;
+            self.audit(&mut tx, Some(expense), None).await?;
+        }
+        tx.commit().await?;
+
+        Ok(expired.len() as u64)
+    }
+
+    /// Applies the changes in order in one transaction, so either all of
+    /// them happen or none do. Reconciled expenses are locked here too.
+    pub async fn apply_changes(&self, changes: &[ExpenseChange]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for change in changes {
+            let id = match change {
+                ExpenseChange::Put(expense) => expense.id,
+                ExpenseChange::Trash(id) | ExpenseChange::Remove(id) => Some(*id),
+            };
+            let before = match id {
+                Some(id) => fetch_row(&mut tx, id).await?,
+                None => None,
+            };
+            ensure_unlocked(before.as_ref())?;
+            let id = match change {
+                ExpenseChange::Put(expense) => put_row(&mut tx, expense).await?,
+                ExpenseChange::Trash(id) => {
+                    query("UPDATE expenses SET deleted_at = ? WHERE id = ?")
+                        .bind(now_timestamp())
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    *id
+                }
+                ExpenseChange::Remove(id) => {
+                    query("DELETE FROM expenses WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    *id
+                }
+            };
+            self.record(&mut tx, id, before).await?;
+        }
+        tx.commit().await
+    }
+
+    /// Reconciled expenses keep their status, only
+    /// [`Database::finish_reconciliation`] moves expenses into it for good,
+    /// so asking for it here is an error too.
+    pub async fn set_expense_status(
+        &self,
+        id: i64,
+        status: ExpenseStatus,
+    ) -> Result<(), sqlx::Error> {
+        if status == ExpenseStatus::Reconciled {
+            return Err(LockedExpense::reconciling_only(id).into());
+        }
+        let mut tx = self.pool.begin().await?;
+        let before = fetch_row(&mut tx, id).await?;
+        ensure_unlocked(before.as_ref())?;
+        query("UPDATE expenses SET status = ?, modified_at = ? WHERE id = ?")
+            .bind(status)
+            .bind(now_timestamp())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        self.record(&mut tx, id, before).await?;
+        tx.commit().await
+    }
+
+    /// Sum of everything already reconciled on an account, i.e. the balance
+    /// the next statement starts from.
+    pub async fn reconciled_balance(&self, account: &str) -> Result<f64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0.0) FROM expenses
+             WHERE account = ? AND status = 'reconciled' AND deleted_at IS NULL",
+        )
+        .bind(account)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Unreconciled expenses on an account up to and including the statement
+    /// end date.
+    pub async fn reconciliation_candidates(
+        &self,
+        account: &str,
+        statement_date: NaiveDate,
+    ) -> Result<Vec<Expense>, sqlx::Error> {
+        query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses
+             WHERE account = ? AND status != 'reconciled' AND date <= ?
+               AND deleted_at IS NULL
+             ORDER BY date, id",
+            EXPENSE_COLUMNS
+        ))
+        .bind(account)
+        .bind(statement_date.format("%Y-%m-%d").to_string())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Locks every cleared expense on the account up to the statement date.
+    /// Returns how many expenses were reconciled.
+    pub async fn finish_reconciliation(
+        &self,
+        account: &str,
+        statement_date: NaiveDate,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let cleared = query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses
+             WHERE account = ? AND status = 'cleared' AND date <= ? AND deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ))
+        .bind(account)
+        .bind(statement_date.format("%Y-%m-%d").to_string())
+        .fetch_all(&mut *tx)
+        .await?;
+        for expense in &cleared {
+            query("UPDATE expenses SET status = 'reconciled', modified_at = ? WHERE id = ?")
+                .bind(now_timestamp())
+                .bind(expense.id)
+                .execute(&mut *tx)
+                .await?;
+            if let Some(id) = expense.id {
+                self.record(&mut tx, id, Some(expense.clone())).await?;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(cleared.len() as u64)
     }
 
     pub async fn clear_expenses(&self) -> Result<(), sqlx::Error> {
@@ -75,39 +364,318 @@ impl Database {
         Ok(())
     }
 
+    /// Replaces every expense with the given ones, e.g. the result of a
+    /// merge. Budgets are left alone, and so is the trash apart from
+    /// expenses the new ones bring back. Expenses in `trash` that aren't here
+    /// at all are added to the trash.
+    pub async fn replace_expenses(
+        &self,
+        expenses: &[Expense],
+        trash: &[Expense],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut previous: HashMap<String, Expense> =
+            query_as::<_, Expense>(&format!("SELECT {} FROM expenses", EXPENSE_COLUMNS))
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|expense| (expense.uid.clone(), expense))
+                .collect();
+        for expense in expenses {
+            let before = previous.remove(&expense.uid);
+            // Expenses that are already here keep their id
+            let id = match &before {
+                Some(row) => {
+                    let mut expense = expense.clone();
+                    expense.id = row.id;
+                    put_row(&mut tx, &expense).await?
+                }
+                None => insert_row(&mut tx, expense).await?,
+            };
+            self.record(&mut tx, id, before).await?;
+        }
+        // Whatever was live and didn't come back is gone
+        for expense in previous.values().filter(|e| e.deleted_at.is_none()) {
+            query("DELETE FROM expenses WHERE id = ?")
+                .bind(expense.id)
+                .execute(&mut *tx)
+                .await?;
+            self.audit(&mut tx, Some(expense), None).await?;
+        }
+        for expense in trash {
+            let known = query("SELECT 1 FROM expenses WHERE uid = ?")
+                .bind(&expense.uid)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if known.is_none() {
+                insert_row(&mut tx, expense).await?;
+            }
+        }
+        tx.commit().await
+    }
+
+    /// Every recorded change to the expense with this uid, the latest first.
+    pub async fn expense_history(&self, uid: &str) -> Result<Vec<AuditEntry>, sqlx::Error> {
+        query_as::<_, AuditEntry>(&format!(
+            "SELECT {} FROM audit WHERE uid = ? ORDER BY timestamp DESC, id DESC",
+            AUDIT_COLUMNS
+        ))
+        .bind(uid)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// The whole audit log, oldest first.
+    pub async fn list_audit(&self) -> Result<Vec<AuditEntry>, sqlx::Error> {
+        query_as::<_, AuditEntry>(&format!(
+            "SELECT {} FROM audit ORDER BY timestamp, id",
+            AUDIT_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Adds entries from a data file or another copy of the ledger to the
+    /// audit log, skipping the ones it already has.
+    pub async fn add_audit_entries(&self, entries: &[AuditEntry]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for entry in entries {
+            query(&format!(
+                "INSERT INTO audit ({columns})
+                 SELECT ?, ?, ?, ?, ?, ?
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM audit
+                     WHERE uid = ? AND timestamp = ? AND actor = ? AND action = ?
+                 )",
+                columns = AUDIT_COLUMNS
+            ))
+            .bind(&entry.uid)
+            .bind(&entry.timestamp)
+            .bind(&entry.actor)
+            .bind(entry.action)
+            .bind(&entry.old_values)
+            .bind(&entry.new_values)
+            .bind(&entry.uid)
+            .bind(&entry.timestamp)
+            .bind(&entry.actor)
+            .bind(entry.action)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Records what happened to the expense with this id, given how it was
+    /// stored before.
+    async fn record(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i64,
+        before: Option<Expense>,
+    ) -> Result<(), sqlx::Error> {
+        let after = fetch_row(conn, id).await?;
+        self.audit(conn, before.as_ref(), after.as_ref()).await
+    }
+
+    async fn audit(
+        &self,
+        conn: &mut SqliteConnection,
+        before: Option<&Expense>,
+        after: Option<&Expense>,
+    ) -> Result<(), sqlx::Error> {
+        let Some(action) = AuditAction::between(before, after) else {
+            return Ok(());
+        };
+        let uid = after.or(before).map_or("", |expense| expense.uid.as_str());
+        let json = |expense: Option<&Expense>| expense.and_then(|e| serde_json::to_string(e).ok());
+        query(&format!(
+            "INSERT INTO audit ({}) VALUES (?, ?, ?, ?, ?, ?)",
+            AUDIT_COLUMNS
+        ))
+        .bind(uid)
+        .bind(now_timestamp())
+        .bind(&self.actor)
+        .bind(action)
+        .bind(json(before))
+        .bind(json(after))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn list_expenses(&self) -> Result<Vec<Expense>, sqlx::Error> {
-        query_as::<_, Expense>("SELECT id, date, name, category, amount FROM expenses")
-            .fetch_all(&self.pool)
-            .await
+        query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE deleted_at IS NULL ORDER BY date, id",
+            EXPENSE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// One page of the expenses matching the filters, along with how many
+    /// match across all pages. Filters left as `None` match everything.
+    pub async fn page_expenses(
+        &self,
+        category: Option<&str>,
+        range: Option<(NaiveDate, NaiveDate)>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Expense>, usize), sqlx::Error> {
+        const FILTER: &str = "deleted_at IS NULL AND (?1 IS NULL OR category = ?1)
+             AND (?2 IS NULL OR date >= ?2) AND (?3 IS NULL OR date <= ?3)";
+        let start = range.map(|(start, _)| start.format("%Y-%m-%d").to_string());
+        let end = range.map(|(_, end)| end.format("%Y-%m-%d").to_string());
+        let total: i64 =
+            sqlx::query_scalar(&format!("SELECT COUNT(*) FROM expenses WHERE {}", FILTER))
+                .bind(category)
+                .bind(&start)
+                .bind(&end)
+                .fetch_one(&self.pool)
+                .await?;
+        let expenses = query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE {} ORDER BY date, id LIMIT ?4 OFFSET ?5",
+            EXPENSE_COLUMNS, FILTER
+        ))
+        .bind(category)
+        .bind(&start)
+        .bind(&end)
+        .bind(limit.min(i64::MAX as usize) as i64)
+        .bind(offset.min(i64::MAX as usize) as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok((expenses, total as usize))
     }
 
     pub async fn save_expenses_to_file(
         &self,
         filename: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let expenses = self.list_expenses().await?;
-        let json = serde_json::to_string(&expenses)?;
-        fs::write(filename, json)?;
+        self.save_to_file(filename, None).await
+    }
+
+    /// Saves the data file, encrypted when a key is given. The file is written
+    /// next to the target and renamed over it, so a failed save never leaves a
+    /// half-written file behind.
+    pub async fn save_to_file(
+        &self,
+        filename: &str,
+        key: Option<&Key>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = self.snapshot().await?;
+        let data = DataFile::Full {
+            expenses: snapshot.expenses,
+            budgets: snapshot.budgets,
+            audit: snapshot.audit,
+        };
+        let json = serde_json::to_vec(&data)?;
+        let bytes = match key {
+            Some(key) => crypto::encrypt(key, &json)?,
+            None => json,
+        };
+        write_atomically(filename, &bytes)?;
         Ok(())
     }
 
+    /// Everything the data file is saved from, the trash and audit log
+    /// included.
+    pub async fn snapshot(&self) -> Result<DataContents, sqlx::Error> {
+        // The trash is saved too, marked by its deletion time
+        let expenses = query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses ORDER BY date, id",
+            EXPENSE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(DataContents {
+            expenses,
+            budgets: self.list_budgets().await?,
+            audit: self.list_audit().await?,
+        })
+    }
+
+    /// Puts everything back the way [`Database::snapshot`] found it, ids
+    /// included, dropping whatever changed since.
+    pub async fn restore_snapshot(&self, snapshot: &DataContents) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for table in ["expenses", "budgets", "audit"] {
+            query(&format!("DELETE FROM {}", table))
+                .execute(&mut *tx)
+                .await?;
+        }
+        for expense in &snapshot.expenses {
+            put_row(&mut tx, expense).await?;
+        }
+        for budget in &snapshot.budgets {
+            query("INSERT INTO budgets (category, amount) VALUES (?, ?)")
+                .bind(&budget.category)
+                .bind(budget.amount)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for entry in &snapshot.audit {
+            query(&format!(
+                "INSERT INTO audit ({}) VALUES (?, ?, ?, ?, ?, ?)",
+                AUDIT_COLUMNS
+            ))
+            .bind(&entry.uid)
+            .bind(&entry.timestamp)
+            .bind(&entry.actor)
+            .bind(entry.action)
+            .bind(&entry.old_values)
+            .bind(&entry.new_values)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
     pub async fn load_expenses_from_file(
         &self,
         filename: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if let Ok(contents) = fs::read_to_string(filename) {
-            let expenses: Vec<Expense> = serde_json::from_str(&contents)?;
-            for expense in expenses {
-                self.insert_expense(&expense).await?;
+        if let Ok(contents) = fs::read(filename) {
+            if crypto::is_encrypted(&contents) {
+                return Err(format!("{} is encrypted and needs a passphrase", filename).into());
             }
+            self.load_data(&contents).await?;
         }
         Ok(())
     }
 
+    /// Decrypts and loads an encrypted data file. Nothing is loaded when the
+    /// passphrase is wrong. Returns the key for saving the file again.
+    pub async fn load_encrypted_file(
+        &self,
+        filename: &str,
+        passphrase: &str,
+    ) -> Result<Key, Box<dyn std::error::Error>> {
+        let contents = fs::read(filename)?;
+        let (plaintext, key) = crypto::decrypt(&contents, passphrase)?;
+        self.load_data(&plaintext).await?;
+        Ok(key)
+    }
+
+    /// Loads a data file as is. Loading isn't a change, so nothing is
+    /// added to the audit log beyond what the file brings.
+    async fn load_data(&self, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let data = parse_data(contents)?;
+        let mut conn = self.pool.acquire().await?;
+        for expense in &data.expenses {
+            insert_row(&mut conn, expense).await?;
+        }
+        for budget in data.budgets {
+            self.set_budget(&budget.category, budget.amount).await?;
+        }
+        self.add_audit_entries(&data.audit).await?;
+        Ok(())
+    }
+
     pub async fn filter_by_category(&self, category: &str) -> Result<Vec<Expense>, sqlx::Error> {
-        sqlx::query_as::<_, Expense>(
-            "SELECT id, date, name, category, amount FROM expenses WHERE category = ?",
-        )
+        sqlx::query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE category = ? AND deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ))
         .bind(category)
         .fetch_all(&self.pool)
         .await
@@ -118,19 +686,405 @@ impl Database {
         year: i32,
         month: u32,
     ) -> Result<Vec<Expense>, sqlx::Error> {
-        let start_date = format!("{:04}-{:02}-01", year, month);
-        let end_date = format!("{:04}-{:02}-31", year, month);
+        match month_bounds(year, month) {
+            Some((start, end)) => self.filter_by_date_range(start, end).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn filter_by_date_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Expense>, sqlx::Error> {
+        sqlx::query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses WHERE date >= ? AND date <= ? AND deleted_at IS NULL",
+            EXPENSE_COLUMNS
+        ))
+        .bind(start.format("%Y-%m-%d").to_string())
+        .bind(end.format("%Y-%m-%d").to_string())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn category_totals_by_month(&self) -> Result<Vec<CategoryMonthTotal>, sqlx::Error> {
+        query_as::<_, CategoryMonthTotal>(
+            "SELECT substr(date, 1, 7) AS month, category, SUM(amount) AS total
+             FROM expenses
+             WHERE deleted_at IS NULL
+             GROUP BY month, category
+             ORDER BY month, category",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn set_budget(&self, category: &str, amount: f64) -> Result<(), sqlx::Error> {
+        query("INSERT OR REPLACE INTO budgets (category, amount) VALUES (?, ?)")
+            .bind(category)
+            .bind(amount)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_budget(&self, category: &str) -> Result<(), sqlx::Error> {
+        query("DELETE FROM budgets WHERE category = ?")
+            .bind(category)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Replaces every budget with the given ones.
+    pub async fn replace_budgets(&self, budgets: &[Budget]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        query("DELETE FROM budgets").execute(&mut *tx).await?;
+        for budget in budgets {
+            query("INSERT INTO budgets (category, amount) VALUES (?, ?)")
+                .bind(&budget.category)
+                .bind(budget.amount)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    pub async fn list_budgets(&self) -> Result<Vec<Budget>, sqlx::Error> {
+        query_as::<_, Budget>("SELECT category, amount FROM budgets ORDER BY category")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn largest_expenses(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        limit: i64,
+    ) -> Result<Vec<Expense>, sqlx::Error> {
+        query_as::<_, Expense>(&format!(
+            "SELECT {} FROM expenses
+             WHERE date >= ? AND date <= ? AND deleted_at IS NULL
+             ORDER BY amount DESC, date
+             LIMIT ?",
+            EXPENSE_COLUMNS
+        ))
+        .bind(start.format("%Y-%m-%d").to_string())
+        .bind(end.format("%Y-%m-%d").to_string())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn category_totals(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<CategoryTotal>, sqlx::Error> {
+        query_as::<_, CategoryTotal>(
+            "SELECT category, SUM(amount) AS total, COUNT(*) AS count
+             FROM expenses
+             WHERE date >= ? AND date <= ? AND deleted_at IS NULL
+             GROUP BY category
+             ORDER BY total DESC, category",
+        )
+        .bind(start.format("%Y-%m-%d").to_string())
+        .bind(end.format("%Y-%m-%d").to_string())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn top_merchants(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        limit: i64,
+    ) -> Result<Vec<MerchantTotal>, sqlx::Error> {
+        query_as::<_, MerchantTotal>(
+            "SELECT name, SUM(amount) AS total, COUNT(*) AS count
+             FROM expenses
+             WHERE date >= ? AND date <= ? AND deleted_at IS NULL
+             GROUP BY name
+             ORDER BY total DESC, name
+             LIMIT ?",
+        )
+        .bind(start.format("%Y-%m-%d").to_string())
+        .bind(end.format("%Y-%m-%d").to_string())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Spending per day between `start` and `end`. Days without expenses
+    /// are left out.
+    pub async fn daily_totals(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DayTotal>, sqlx::Error> {
+        query_as::<_, DayTotal>(
+            "SELECT date, SUM(amount) AS total
+             FROM expenses
+             WHERE date >= ? AND date <= ? AND deleted_at IS NULL
+             GROUP BY date
+             ORDER BY date",
+        )
+        .bind(start.format("%Y-%m-%d").to_string())
+        .bind(end.format("%Y-%m-%d").to_string())
+        .fetch_all(&self.pool)
+        .await
+    }
 
-        sqlx::query_as::<_, Expense>(
-            "SELECT id, date, name, category, amount FROM expenses WHERE date >= ? AND date <= ?",
+    pub async fn monthly_totals(&self) -> Result<Vec<MonthTotal>, sqlx::Error> {
+        query_as::<_, MonthTotal>(
+            "SELECT substr(date, 1, 7) AS month, SUM(amount) AS total
+             FROM expenses
+             WHERE deleted_at IS NULL
+             GROUP BY month
+             ORDER BY month",
         )
-        .bind(start_date)
-        .bind(end_date)
         .fetch_all(&self.pool)
         .await
     }
 }
 
+/// Writes the expense under its id, replacing the row that has it, or as a
+/// new row when it has none. Returns the id.
+async fn put_row(conn: &mut SqliteConnection, expense: &Expense) -> Result<i64, sqlx::Error> {
+    if expense.id.is_none() {
+        return insert_row(conn, expense).await;
+    }
+    Ok(query(&format!(
+        "INSERT OR REPLACE INTO expenses ({})
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        EXPENSE_COLUMNS
+    ))
+    .bind(expense.id)
+    .bind(&expense.date)
+    .bind(&expense.name)
+    .bind(&expense.category)
+    .bind(expense.amount)
+    .bind(&expense.external_id)
+    .bind(&expense.account)
+    .bind(&expense.notes)
+    .bind(expense.status)
+    .bind(&expense.uid)
+    .bind(&expense.modified_at)
+    .bind(&expense.deleted_at)
+    .bind(&expense.tags)
+    .execute(conn)
+    .await?
+    .last_insert_rowid())
+}
+
+/// Inserts a row without recording it in the audit log. See
+/// [`Database::insert_expense`].
+async fn insert_row(conn: &mut SqliteConnection, expense: &Expense) -> Result<i64, sqlx::Error> {
+    let uid = match expense.uid.as_str() {
+        "" => new_uid(),
+        uid => uid.to_string(),
+    };
+    let modified_at = match expense.modified_at.as_str() {
+        "" => now_timestamp(),
+        modified_at => modified_at.to_string(),
+    };
+    let result = sqlx::query(
+        "INSERT INTO expenses
+         (date, name, category, amount, external_id, account, notes, status, uid, modified_at,
+          deleted_at, tags)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(expense.date.to_string())
+    .bind(&expense.name)
+    .bind(&expense.category)
+    .bind(expense.amount)
+    .bind(&expense.external_id)
+    .bind(&expense.account)
+    .bind(&expense.notes)
+    .bind(expense.status)
+    .bind(uid)
+    .bind(modified_at)
+    .bind(&expense.deleted_at)
+    .bind(&expense.tags)
+    .execute(conn)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// The row with this id, in the trash or not.
+async fn fetch_row(conn: &mut SqliteConnection, id: i64) -> Result<Option<Expense>, sqlx::Error> {
+    query_as::<_, Expense>(&format!(
+        "SELECT {} FROM expenses WHERE id = ?",
+        EXPENSE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(conn)
+    .await
+}
+
+/// Raised when a change would touch a reconciled expense, or reconcile one
+/// outside a reconciliation. It travels as a
+/// `sqlx::Error::Database` so callers can tell it apart with
+/// `try_downcast_ref::<LockedExpense>()`.
+#[derive(Debug)]
+pub struct LockedExpense {
+    message: String,
+}
+
+impl LockedExpense {
+    fn new(id: i64) -> Self {
+        LockedExpense {
+            message: format!("expense {} is reconciled and locked", id),
+        }
+    }
+
+    fn reconciling_only(id: i64) -> Self {
+        LockedExpense {
+            message: format!(
+                "expense {} can only be reconciled by finishing a reconciliation",
+                id
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for LockedExpense {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for LockedExpense {}
+
+impl sqlx::error::DatabaseError for LockedExpense {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> sqlx::error::ErrorKind {
+        sqlx::error::ErrorKind::Other
+    }
+}
+
+fn ensure_unlocked(expense: Option<&Expense>) -> Result<(), sqlx::Error> {
+    match expense {
+        Some(expense) if expense.status == ExpenseStatus::Reconciled => {
+            Err(LockedExpense::new(expense.id.unwrap_or_default()).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// What a data file holds, whichever shape it was written in.
+#[derive(Default)]
+pub struct DataContents {
+    /// Expenses in the trash included.
+    pub expenses: Vec<Expense>,
+    pub budgets: Vec<Budget>,
+    pub audit: Vec<AuditEntry>,
+}
+
+fn parse_data(contents: &[u8]) -> Result<DataContents, serde_json::Error> {
+    let mut data = match serde_json::from_slice(contents)? {
+        DataFile::Full {
+            expenses,
+            budgets,
+            audit,
+        } => DataContents {
+            expenses,
+            budgets,
+            audit,
+        },
+        DataFile::ExpensesOnly(expenses) => DataContents {
+            expenses,
+            budgets: Vec::new(),
+            audit: Vec::new(),
+        },
+    };
+    // Files from before uids get the same ones wherever they're opened
+    let mut occurrences = HashMap::new();
+    for expense in data.expenses.iter_mut().filter(|e| e.uid.is_empty()) {
+        let key = (
+            expense.date.clone(),
+            expense.name.clone(),
+            expense.category.clone(),
+            expense.amount.to_bits(),
+        );
+        let occurrence = occurrences.entry(key).or_insert(0);
+        expense.uid = legacy_uid(expense, *occurrence);
+        *occurrence += 1;
+    }
+    Ok(data)
+}
+
+/// Reads the expenses of a data file without loading it into a database.
+/// Encrypted files are opened with `passphrase`. A missing file reads as
+/// empty, and expenses in its trash are left out.
+pub fn read_expenses_file(
+    filename: &str,
+    passphrase: Option<&str>,
+) -> Result<Vec<Expense>, Box<dyn std::error::Error>> {
+    let Some(data) = read_data_file(filename, passphrase)? else {
+        return Ok(Vec::new());
+    };
+    // Whatever is in the trash counts as deleted
+    Ok(data
+        .expenses
+        .into_iter()
+        .filter(|expense| expense.deleted_at.is_none())
+        .collect())
+}
+
+/// Reads a whole data file, trash, budgets and audit log included, or `None`
+/// when there is no such file. Every copy of a ledger is written with its own
+/// salt, so an encrypted file is opened with the key its own header calls for
+/// rather than ours.
+pub fn read_data_file(
+    filename: &str,
+    passphrase: Option<&str>,
+) -> Result<Option<DataContents>, Box<dyn std::error::Error>> {
+    let contents = match fs::read(filename) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let plaintext = match (crypto::is_encrypted(&contents), passphrase) {
+        (false, _) => contents,
+        (true, Some(passphrase)) => crypto::decrypt(&contents, passphrase)?.0,
+        (true, None) => {
+            return Err(format!("{} is encrypted and needs a passphrase", filename).into())
+        }
+    };
+    Ok(Some(parse_data(&plaintext)?))
+}
+
+pub fn write_atomically(filename: &str, bytes: &[u8]) -> std::io::Result<()> {
+    let dir = match Path::new(filename).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(bytes)?;
+    temp.as_file().sync_all()?;
+    temp.persist(filename).map_err(|err| err.error)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -181,6 +1135,7 @@ mod tests {
         assert_eq!(loaded_expenses[1].name, "Test 2");
     }
 
+    #[tokio::test]
     async fn test_db_operations() {
         let db = Database::new().await.unwrap();
 
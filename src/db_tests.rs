@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use crate::db::Database;
-    use crate::expense::Expense;
+    use crate::audit::AuditAction;
+    use crate::bulk::{self, BulkAction};
+    use crate::db::{Database, ExpenseChange, LockedExpense};
+    use crate::expense::{Expense, ExpenseStatus};
+    use crate::period::Month;
+    use crate::summary::{DashboardData, MonthlySummary, PeriodSummary};
+    use crate::undo::{Change, History};
     use chrono::NaiveDate;
     use std::fs;
 
@@ -204,4 +209,576 @@ mod tests {
         // Clean up the test file
         fs::remove_file(test_file).unwrap();
     }
+
+    #[tokio::test]
+    async fn test_category_totals_by_month() {
+        let db = setup().await;
+        for (month, day, category, amount) in [
+            (7, 1, "Food", 50.0),
+            (7, 15, "Food", 25.0),
+            (7, 20, "Transport", 30.0),
+            (8, 2, "Food", 10.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, month, day).unwrap(),
+                "Expense",
+                category,
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+
+        let totals = db.category_totals_by_month().await.unwrap();
+        assert_eq!(totals.len(), 3);
+        assert_eq!(totals[0].month, "2023-07");
+        assert_eq!(totals[0].category, "Food");
+        assert_eq!(totals[0].total, 75.0);
+        assert_eq!(totals[1].category, "Transport");
+        assert_eq!(totals[2].month, "2023-08");
+        assert_eq!(totals[2].total, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_filter_by_month_uses_real_month_length() {
+        let db = setup().await;
+        for (month, day) in [(2, 28), (2, 29), (3, 1)] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2024, month, day).unwrap(),
+                "Expense",
+                "Food",
+                10.0,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+
+        assert_eq!(db.filter_by_month(2024, 2).await.unwrap().len(), 2);
+        assert_eq!(db.filter_by_month(2024, 3).await.unwrap().len(), 1);
+        assert!(db.filter_by_month(2024, 13).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_monthly_summary() {
+        let db = setup().await;
+        for (month, day, name, category, amount) in [
+            (6, 10, "Rent", "Housing", 800.0),
+            (7, 1, "Rent", "Housing", 900.0),
+            (7, 3, "Tesco", "Food", 40.0),
+            (7, 9, "Tesco", "Food", 20.0),
+            (7, 12, "Bus", "Transport", 5.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, month, day).unwrap(),
+                name,
+                category,
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+
+        let summary = MonthlySummary::load(&db, Month::new(2023, 7).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.total, 965.0);
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.previous_total, 800.0);
+        assert_eq!(summary.categories[0].category, "Housing");
+        assert_eq!(summary.categories[1].category, "Food");
+        assert_eq!(summary.categories[1].count, 2);
+        assert_eq!(summary.top_merchants[0].name, "Rent");
+        assert_eq!(summary.top_merchants[1].name, "Tesco");
+        assert_eq!(summary.top_merchants[1].total, 60.0);
+        assert_eq!(summary.history.len(), 6);
+        assert_eq!(summary.history[0].month, "2023-02");
+        assert_eq!(summary.history[5].month, "2023-07");
+        assert_eq!(summary.history[4].total, 800.0);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_is_all_or_nothing() {
+        let db = setup().await;
+        let expense = Expense::new(
+            NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+            "Rent",
+            "Housing",
+            700.0,
+        )
+        .unwrap();
+        let id = db.insert_expense(&expense).await.unwrap();
+        let mut statement = Expense::new(
+            NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            "Rent",
+            "Housing",
+            700.0,
+        )
+        .unwrap();
+        statement.account = "Bank".to_string();
+        statement.status = ExpenseStatus::Cleared;
+        let locked = db.insert_expense(&statement).await.unwrap();
+        db.finish_reconciliation("Bank", NaiveDate::from_ymd_opt(2023, 6, 1).unwrap())
+            .await
+            .unwrap();
+        assert!(db
+            .set_expense_status(id, ExpenseStatus::Reconciled)
+            .await
+            .is_err());
+
+        let result = db
+            .apply_changes(&[ExpenseChange::Remove(id), ExpenseChange::Remove(locked)])
+            .await;
+        assert!(matches!(
+            result,
+            Err(sqlx::Error::Database(err)) if err.try_downcast_ref::<LockedExpense>().is_some()
+        ));
+        assert_eq!(db.list_expenses().await.unwrap().len(), 2);
+
+        let mut stored = db.get_expense(id).await.unwrap().unwrap();
+        db.apply_changes(&[ExpenseChange::Remove(id)])
+            .await
+            .unwrap();
+        stored.name = "Back again".to_string();
+        db.apply_changes(&[ExpenseChange::Put(Box::new(stored.clone()))])
+            .await
+            .unwrap();
+        assert_eq!(db.get_expense(id).await.unwrap(), Some(stored));
+    }
+
+    #[tokio::test]
+    async fn test_undo_and_redo() {
+        let db = setup().await;
+        let mut history = History::default();
+        let expense = Expense::new(
+            NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+            "Tea",
+            "Food",
+            3.0,
+        )
+        .unwrap();
+        let id = db.insert_expense(&expense).await.unwrap();
+        let before = db.get_expense(id).await.unwrap().unwrap();
+        history.record(Change::Insert(Box::new(before.clone())));
+
+        let mut after = before.clone();
+        after.amount = 4.0;
+        db.update_expense(&after).await.unwrap();
+        let after = db.get_expense(id).await.unwrap().unwrap();
+        history.record(Change::Update {
+            before: Box::new(before.clone()),
+            after: Box::new(after.clone()),
+        });
+
+        db.delete_expense(id).await.unwrap();
+        history.record(Change::Delete(Box::new(after.clone())));
+
+        assert_eq!(
+            history.undo(&db).await.unwrap().as_deref(),
+            Some("deleting 'Tea'")
+        );
+        assert_eq!(db.get_expense(id).await.unwrap(), Some(after.clone()));
+        assert_eq!(
+            history.undo(&db).await.unwrap().as_deref(),
+            Some("editing 'Tea'")
+        );
+        assert_eq!(db.get_expense(id).await.unwrap(), Some(before.clone()));
+        history.undo(&db).await.unwrap();
+        assert!(db.list_expenses().await.unwrap().is_empty());
+        assert_eq!(history.undo(&db).await.unwrap(), None);
+
+        assert_eq!(
+            history.redo(&db).await.unwrap().as_deref(),
+            Some("adding 'Tea'")
+        );
+        assert_eq!(db.get_expense(id).await.unwrap(), Some(before));
+        history.redo(&db).await.unwrap();
+        assert_eq!(db.get_expense(id).await.unwrap(), Some(after));
+
+        // A new change drops what could have been redone
+        history.record(Change::Bulk {
+            description: "nothing much".to_string(),
+            changes: Vec::new(),
+        });
+        assert_eq!(history.redo(&db).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_data() {
+        let db = setup().await;
+        for (month, day, amount) in [(1, 31, 7.0), (2, 1, 10.0), (2, 1, 2.5), (2, 28, 4.0)] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, month, day).unwrap(),
+                "Shop",
+                "Food",
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+
+        let start = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2023, 2, 28).unwrap();
+        let totals = db.daily_totals(start, end).await.unwrap();
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].total, 12.5);
+
+        let data = DashboardData::load(&db, Month::new(2023, 2).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(data.daily.len(), 28);
+        assert_eq!(data.daily[0], 12.5);
+        assert_eq!(data.daily[1], 0.0);
+        assert_eq!(data.cumulative()[27], 16.5);
+        assert_eq!(data.summary.previous_total, 7.0);
+    }
+
+    #[tokio::test]
+    async fn test_budgets_round_trip_through_file() {
+        let db = setup().await;
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+
+        db.set_budget("Food", 200.0).await.unwrap();
+        db.set_budget("Food", 250.0).await.unwrap();
+        db.set_budget("Fun", 50.0).await.unwrap();
+        db.delete_budget("Fun").await.unwrap();
+        db.save_expenses_to_file(file_path).await.unwrap();
+
+        let loaded = setup().await;
+        loaded.load_expenses_from_file(file_path).await.unwrap();
+        let budgets = loaded.list_budgets().await.unwrap();
+        assert_eq!(budgets.len(), 1);
+        assert_eq!(budgets[0].category, "Food");
+        assert_eq!(budgets[0].amount, 250.0);
+    }
+
+    #[tokio::test]
+    async fn test_load_legacy_expense_list() {
+        let db = setup().await;
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            temp_file.path(),
+            r#"[{"id":1,"date":"2024-08-29","name":"Lunch","category":"Food","amount":33.0}]"#,
+        )
+        .unwrap();
+
+        db.load_expenses_from_file(temp_file.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let expenses = db.list_expenses().await.unwrap();
+        assert_eq!(expenses.len(), 1);
+        assert_eq!(expenses[0].name, "Lunch");
+        assert_eq!(expenses[0].uid.len(), 36);
+        assert!(!expenses[0].modified_at.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_uid_survives_save_and_update_bumps_modified_at() {
+        let db = setup().await;
+        let mut expense = Expense::new(
+            NaiveDate::from_ymd_opt(2024, 8, 29).unwrap(),
+            "Lunch",
+            "Food",
+            33.0,
+        )
+        .unwrap();
+        expense.modified_at = "2024-08-29T12:00:00.000Z".to_string();
+        let id = db.insert_expense(&expense).await.unwrap();
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+        db.save_expenses_to_file(file_path).await.unwrap();
+        let reloaded = setup().await;
+        reloaded.load_expenses_from_file(file_path).await.unwrap();
+        let loaded = &reloaded.list_expenses().await.unwrap()[0];
+        assert_eq!(loaded.uid, expense.uid);
+        assert_eq!(loaded.modified_at, expense.modified_at);
+
+        let mut changed = db.get_expense(id).await.unwrap().unwrap();
+        changed.amount = 35.0;
+        db.update_expense(&changed).await.unwrap();
+        let updated = db.get_expense(id).await.unwrap().unwrap();
+        assert_eq!(updated.uid, expense.uid);
+        assert!(updated.modified_at > expense.modified_at);
+    }
+
+    #[tokio::test]
+    async fn test_period_summary() {
+        let db = setup().await;
+        for (month, day, name, amount) in [
+            (6, 30, "June", 100.0),
+            (7, 1, "Big", 300.0),
+            (7, 2, "Small", 10.0),
+            (8, 31, "August", 20.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, month, day).unwrap(),
+                name,
+                "Food",
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+        db.set_budget("Food", 200.0).await.unwrap();
+
+        let july = PeriodSummary::for_month(&db, Month::new(2023, 7).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(july.total, 310.0);
+        assert_eq!(july.daily_average, 10.0);
+        assert_eq!(july.largest[0].name, "Big");
+        assert!(july.budgets[0].is_over());
+
+        let summer = PeriodSummary::load(
+            &db,
+            NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2023, 8, 31).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(summer.count, 4);
+        assert_eq!(summer.budgets[0].limit, 600.0);
+        assert!(!summer.budgets[0].is_over());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_save_and_load() {
+        let db = setup().await;
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+        let expense = Expense::new(
+            NaiveDate::from_ymd_opt(2023, 7, 28).unwrap(),
+            "Salary advance",
+            "Medical",
+            120.0,
+        )
+        .unwrap();
+        db.insert_expense(&expense).await.unwrap();
+
+        let key = crate::crypto::Key::new("hunter2").unwrap();
+        db.save_to_file(file_path, Some(&key)).await.unwrap();
+        let on_disk = fs::read(file_path).unwrap();
+        assert!(!String::from_utf8_lossy(&on_disk).contains("Salary"));
+
+        let locked = setup().await;
+        assert!(locked.load_expenses_from_file(file_path).await.is_err());
+        assert!(locked
+            .load_encrypted_file(file_path, "wrong")
+            .await
+            .is_err());
+        assert!(locked.list_expenses().await.unwrap().is_empty());
+        assert_eq!(fs::read(file_path).unwrap(), on_disk);
+
+        let key = locked
+            .load_encrypted_file(file_path, "hunter2")
+            .await
+            .unwrap();
+        assert_eq!(
+            locked.list_expenses().await.unwrap()[0].name,
+            "Salary advance"
+        );
+
+        // Saving again with the recovered key keeps the same passphrase
+        locked.save_to_file(file_path, Some(&key)).await.unwrap();
+        let reopened = setup().await;
+        reopened
+            .load_encrypted_file(file_path, "hunter2")
+            .await
+            .unwrap();
+        assert_eq!(reopened.list_expenses().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_purged_expense_is_forgotten_by_history() {
+        let db = setup().await;
+        let mut history = History::default();
+        let date = NaiveDate::from_ymd_opt(2023, 7, 10).unwrap();
+        let mut expenses = Vec::new();
+        for name in ["Lunch", "Cinema"] {
+            let id = db
+                .insert_expense(&Expense::new(date, name, "Food", 5.0).unwrap())
+                .await
+                .unwrap();
+            expenses.push(db.get_expense(id).await.unwrap().unwrap());
+        }
+        history.record(Change::Insert(Box::new(expenses[1].clone())));
+        history
+            .apply(
+                &db,
+                Change::Bulk {
+                    description: "deleting 2 expenses".to_string(),
+                    changes: expenses
+                        .iter()
+                        .map(|expense| Change::Delete(Box::new(expense.clone())))
+                        .collect(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let cinema = &expenses[1];
+        db.purge_expense(cinema.id.unwrap()).await.unwrap();
+        history.forget(&cinema.uid);
+        assert_eq!(
+            history.undo(&db).await.unwrap().as_deref(),
+            Some("deleting 2 expenses")
+        );
+        let live = db.list_expenses().await.unwrap();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].name, "Lunch");
+        assert_eq!(history.undo(&db).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_trash_is_left_out_and_can_be_restored() {
+        let db = setup().await;
+        let date = NaiveDate::from_ymd_opt(2023, 7, 10).unwrap();
+        let kept = Expense::new(date, "Lunch", "Food", 12.0).unwrap();
+        let deleted = Expense::new(date, "Cinema", "Fun", 20.0).unwrap();
+        db.insert_expense(&kept).await.unwrap();
+        let id = db.insert_expense(&deleted).await.unwrap();
+        db.delete_expense(id).await.unwrap();
+
+        assert_eq!(db.list_expenses().await.unwrap().len(), 1);
+        assert_eq!(db.get_expense(id).await.unwrap(), None);
+        let totals = db.category_totals(date, date).await.unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].category, "Food");
+        let trash = db.list_trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert!(trash[0].deleted_at.is_some());
+
+        db.restore_expense(id).await.unwrap();
+        assert!(db.list_trash().await.unwrap().is_empty());
+        let restored = db.get_expense(id).await.unwrap().unwrap();
+        assert_eq!(restored.name, "Cinema");
+        assert_eq!(restored.deleted_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_purge_trash() {
+        let db = setup().await;
+        let date = NaiveDate::from_ymd_opt(2023, 7, 10).unwrap();
+        let mut ids = Vec::new();
+        for name in ["Old", "Recent", "Live"] {
+            let expense = Expense::new(date, name, "Food", 5.0).unwrap();
+            ids.push(db.insert_expense(&expense).await.unwrap());
+        }
+        let mut old = db.get_expense(ids[0]).await.unwrap().unwrap();
+        old.deleted_at = Some("2023-01-01T00:00:00.000Z".to_string());
+        db.apply_changes(&[ExpenseChange::Put(Box::new(old))])
+            .await
+            .unwrap();
+        db.delete_expense(ids[1]).await.unwrap();
+
+        // Live expenses can't be purged, only ones already in the trash
+        db.purge_expense(ids[2]).await.unwrap();
+        assert_eq!(db.list_expenses().await.unwrap().len(), 1);
+
+        let purged = db.purge_trash("2023-06-01T00:00:00.000Z").await.unwrap();
+        assert_eq!(purged, 1);
+        let trash = db.list_trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].name, "Recent");
+
+        db.purge_expense(ids[1]).await.unwrap();
+        assert!(db.list_trash().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let db = setup().await;
+        let mut expense = Expense::new(
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            "Rent",
+            "Housing",
+            700.0,
+        )
+        .unwrap();
+        let id = db.insert_expense(&expense).await.unwrap();
+        expense.id = Some(id);
+        expense.amount = 730.0;
+        db.update_expense(&expense).await.unwrap();
+        // Saving the same values again isn't a change
+        db.update_expense(&expense).await.unwrap();
+        db.delete_expense(id).await.unwrap();
+        db.restore_expense(id).await.unwrap();
+
+        let history = db.expense_history(&expense.uid).await.unwrap();
+        let actions: Vec<AuditAction> = history.iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::Restore,
+                AuditAction::Delete,
+                AuditAction::Update,
+                AuditAction::Insert
+            ]
+        );
+        assert!(!history[0].actor.is_empty());
+        assert_eq!(history[3].old_values, None);
+        assert_eq!(history[2].before().unwrap().amount, 700.0);
+        assert_eq!(history[2].after().unwrap().amount, 730.0);
+
+        // The log is saved with the data, and loading it records nothing new
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+        db.save_to_file(file_path, None).await.unwrap();
+        let reopened = setup().await;
+        reopened.load_expenses_from_file(file_path).await.unwrap();
+        assert_eq!(
+            reopened.expense_history(&expense.uid).await.unwrap(),
+            history
+        );
+        reopened.add_audit_entries(&history).await.unwrap();
+        assert_eq!(reopened.list_audit().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_change_is_one_step() {
+        let db = setup().await;
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        for name in ["Lunch", "Dinner", "Snack"] {
+            let mut expense = Expense::new(date, name, "Food", 5.0).unwrap();
+            expense.tags = "work, team".to_string();
+            db.insert_expense(&expense).await.unwrap();
+        }
+        let expenses = db.list_expenses().await.unwrap();
+        let mut history = History::default();
+
+        let change = bulk::plan(BulkAction::AddTag, &expenses, "lunch").unwrap();
+        history.apply(&db, change).await.unwrap();
+        let tagged = db.list_expenses().await.unwrap();
+        assert!(tagged.iter().all(|e| e.tags == "work, team, lunch"));
+        let change = bulk::plan(BulkAction::RemoveTag, &tagged, "team").unwrap();
+        history.apply(&db, change).await.unwrap();
+        assert!(db
+            .list_expenses()
+            .await
+            .unwrap()
+            .iter()
+            .all(|e| e.tags == "work, lunch"));
+        history.undo(&db).await.unwrap();
+        assert_eq!(db.list_expenses().await.unwrap(), tagged);
+        history.undo(&db).await.unwrap();
+        assert_eq!(db.list_expenses().await.unwrap(), expenses);
+
+        let change = bulk::plan(BulkAction::ChangeCategory, &expenses[..2], "Eating out").unwrap();
+        history.apply(&db, change).await.unwrap();
+        let changed = db.list_expenses().await.unwrap();
+        let change = bulk::plan(BulkAction::Delete, &changed[1..], "").unwrap();
+        history.apply(&db, change).await.unwrap();
+        let left = db.list_expenses().await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].category, "Eating out");
+
+        assert_eq!(
+            history.undo(&db).await.unwrap().as_deref(),
+            Some("deleting 2 expenses")
+        );
+        assert_eq!(db.list_expenses().await.unwrap(), changed);
+        history.undo(&db).await.unwrap();
+        assert_eq!(db.list_expenses().await.unwrap(), expenses);
+    }
 }
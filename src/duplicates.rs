@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::fs;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::db::{Database, ExpenseChange};
+use crate::expense::{now_timestamp, Expense, ExpenseStatus};
+
+/// Amounts closer than this are considered equal.
+const AMOUNT_EPSILON: f64 = 0.005;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DuplicateOptions {
+    /// How many days apart two entries may be and still match, to allow for
+    /// the booking date and the transaction date differing.
+    pub date_tolerance_days: i64,
+    /// Minimum name similarity between 0.0 and 1.0.
+    pub name_similarity: f64,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> Self {
+        Self {
+            date_tolerance_days: 2,
+            name_similarity: 0.8,
+        }
+    }
+}
+
+/// A pair of expenses that look like the same transaction. `original` is the
+/// entry that is kept; `duplicate` is the one that would be dropped.
+#[derive(Clone, Debug)]
+pub struct DuplicateMatch {
+    pub original: Expense,
+    pub duplicate: Expense,
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateAction {
+    /// Keep both entries.
+    Keep,
+    /// Fold the duplicate into the original and drop it.
+    Merge,
+}
+
+/// Result of reading an import file before anything is written: entries with
+/// no likely duplicate, and the suspected duplicates waiting for review.
+#[derive(Clone, Debug, Default)]
+pub struct ImportPreview {
+    pub fresh: Vec<Expense>,
+    pub matches: Vec<DuplicateMatch>,
+}
+
+/// Scores how likely it is that two entries are the same transaction, or
+/// `None` if they clearly aren't. Matching external ids win outright and
+/// differing ones rule a match out.
+pub fn match_score(a: &Expense, b: &Expense, options: &DuplicateOptions) -> Option<f64> {
+    if let (Some(x), Some(y)) = (&a.external_id, &b.external_id) {
+        return if x == y { Some(1.0) } else { None };
+    }
+
+    if (a.amount - b.amount).abs() > AMOUNT_EPSILON {
+        return None;
+    }
+
+    let (Ok(date_a), Ok(date_b)) = (
+        NaiveDate::parse_from_str(&a.date, "%Y-%m-%d"),
+        NaiveDate::parse_from_str(&b.date, "%Y-%m-%d"),
+    ) else {
+        return None;
+    };
+    if (date_a - date_b).num_days().abs() > options.date_tolerance_days {
+        return None;
+    }
+
+    let similarity = name_similarity(&a.name, &b.name);
+    if similarity < options.name_similarity {
+        return None;
+    }
+    Some(similarity)
+}
+
+/// Normalised similarity of two names, ignoring case and surrounding spaces.
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    strsim::normalized_levenshtein(&a.trim().to_lowercase(), &b.trim().to_lowercase())
+}
+
+/// Finds likely duplicates among existing expenses. The earlier entry (lower
+/// id) of each pair is treated as the original.
+pub fn find_duplicates(expenses: &[Expense], options: &DuplicateOptions) -> Vec<DuplicateMatch> {
+    let mut sorted: Vec<&Expense> = expenses.iter().collect();
+    sorted.sort_by_key(|e| e.id);
+
+    let mut matches = Vec::new();
+    for (index, original) in sorted.iter().enumerate() {
+        for duplicate in &sorted[index + 1..] {
+            if let Some(score) = match_score(original, duplicate, options) {
+                matches.push(DuplicateMatch {
+                    original: (*original).clone(),
+                    duplicate: (*duplicate).clone(),
+                    score,
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// Splits incoming expenses into ones that can be inserted straight away and
+/// ones that match an existing entry or an earlier entry of the same batch.
+pub fn preview_import(
+    existing: &[Expense],
+    incoming: Vec<Expense>,
+    options: &DuplicateOptions,
+) -> ImportPreview {
+    let mut preview = ImportPreview::default();
+    for mut candidate in incoming {
+        // Imported rows are new entries here, whatever they were elsewhere
+        candidate.id = None;
+        candidate.uid = String::new();
+        candidate.status = ExpenseStatus::Pending;
+        candidate.deleted_at = None;
+        let best = existing
+            .iter()
+            .chain(preview.fresh.iter())
+            .filter_map(|original| {
+                match_score(original, &candidate, options).map(|score| (original, score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((original, score)) => preview.matches.push(DuplicateMatch {
+                original: original.clone(),
+                duplicate: candidate,
+                score,
+            }),
+            None => preview.fresh.push(candidate),
+        }
+    }
+    preview
+}
+
+/// Reads expenses from a JSON file (either a bare list or a saved data file)
+/// and compares them against what is already in the database.
+pub async fn preview_import_file(
+    db: &Database,
+    filename: &str,
+    options: &DuplicateOptions,
+) -> Result<ImportPreview, Box<dyn std::error::Error>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ImportFile {
+        Full { expenses: Vec<Expense> },
+        ExpensesOnly(Vec<Expense>),
+    }
+
+    let contents = fs::read_to_string(filename)?;
+    let incoming = match serde_json::from_str(&contents)? {
+        ImportFile::Full { expenses } => expenses,
+        ImportFile::ExpensesOnly(expenses) => expenses,
+    };
+    let existing = db.list_expenses().await?;
+    Ok(preview_import(&existing, incoming, options))
+}
+
+/// Writes a reviewed import in one transaction: every fresh entry is
+/// inserted, and each match is either inserted anyway or merged into its
+/// original. Returns how many rows were inserted.
+pub async fn commit_import(
+    db: &Database,
+    preview: &ImportPreview,
+    actions: &[DuplicateAction],
+) -> Result<usize, sqlx::Error> {
+    let insert = |expense: &Expense| ExpenseChange::Put(Box::new(expense.clone()));
+    let mut changes: Vec<ExpenseChange> = preview.fresh.iter().map(insert).collect();
+    let mut originals = HashMap::new();
+    for (found, action) in preview.matches.iter().zip(actions) {
+        match action {
+            DuplicateAction::Keep => changes.push(insert(&found.duplicate)),
+            DuplicateAction::Merge => merge_into_original(db, &mut originals, found).await?,
+        }
+    }
+    let inserted = changes.len();
+    changes.extend(
+        originals
+            .into_values()
+            .map(|original| ExpenseChange::Put(Box::new(original))),
+    );
+    db.apply_changes(&changes).await?;
+    Ok(inserted)
+}
+
+/// Applies the review of duplicates found among existing expenses in one
+/// transaction. Returns how many rows were removed.
+pub async fn resolve_duplicates(
+    db: &Database,
+    matches: &[DuplicateMatch],
+    actions: &[DuplicateAction],
+) -> Result<usize, sqlx::Error> {
+    let mut removed = Vec::new();
+    let mut originals = HashMap::new();
+    for (found, action) in matches.iter().zip(actions) {
+        if *action != DuplicateAction::Merge {
+            continue;
+        }
+        let Some(id) = found.duplicate.id else {
+            continue;
+        };
+        // An earlier merge in this batch may already have removed either side
+        let gone = |id: i64| removed.contains(&id);
+        if gone(id) || found.original.id.is_some_and(gone) {
+            continue;
+        }
+        if db.get_expense(id).await?.is_none() {
+            continue;
+        }
+        if let Some(original_id) = found.original.id {
+            if db.get_expense(original_id).await?.is_none() {
+                continue;
+            }
+        }
+        merge_into_original(db, &mut originals, found).await?;
+        originals.remove(&id);
+        removed.push(id);
+    }
+    let mut changes: Vec<ExpenseChange> = originals
+        .into_values()
+        .map(|original| ExpenseChange::Put(Box::new(original)))
+        .collect();
+    changes.extend(removed.iter().map(|id| ExpenseChange::Trash(*id)));
+    db.apply_changes(&changes).await?;
+    Ok(removed.len())
+}
+
+/// Copies the external id over when the original lacks one, so the next
+/// import of the same statement is recognised by id alone. Originals that
+/// change are collected in `originals` by id, to be written all at once.
+async fn merge_into_original(
+    db: &Database,
+    originals: &mut HashMap<i64, Expense>,
+    found: &DuplicateMatch,
+) -> Result<(), sqlx::Error> {
+    let Some(id) = found.original.id else {
+        return Ok(());
+    };
+    // Already given an external id earlier in this batch
+    if found.duplicate.external_id.is_none() || originals.contains_key(&id) {
+        return Ok(());
+    }
+    if let Some(mut original) = db.get_expense(id).await? {
+        if original.external_id.is_none() && original.status != ExpenseStatus::Reconciled {
+            original.external_id = found.duplicate.external_id.clone();
+            original.modified_at = now_timestamp();
+            originals.insert(id, original);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{expense, stored};
+
+    #[test]
+    fn test_match_score() {
+        let options = DuplicateOptions::default();
+        let a = stored(1, "2023-07-10", "TESCO STORES 2041", "Food", 12.5);
+
+        assert!(match_score(
+            &a,
+            &expense("2023-07-11", "Tesco Stores 2041", "Food", 12.5),
+            &options
+        )
+        .is_some());
+        assert!(match_score(
+            &a,
+            &expense("2023-07-14", "Tesco Stores 2041", "Food", 12.5),
+            &options
+        )
+        .is_none());
+        assert!(match_score(
+            &a,
+            &expense("2023-07-10", "Tesco Stores 2041", "Food", 12.6),
+            &options
+        )
+        .is_none());
+        assert!(match_score(
+            &a,
+            &expense("2023-07-10", "Sainsburys", "Food", 12.5),
+            &options
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_external_ids_decide() {
+        let options = DuplicateOptions::default();
+        let mut a = stored(1, "2023-07-10", "Coffee", "Food", 3.0);
+        let mut b = expense("2023-07-10", "Coffee", "Food", 3.0);
+        a.external_id = Some("tx-1".to_string());
+        b.external_id = Some("tx-2".to_string());
+        assert!(match_score(&a, &b, &options).is_none());
+
+        b.external_id = Some("tx-1".to_string());
+        b.name = "Something else".to_string();
+        b.amount = 99.0;
+        assert_eq!(match_score(&a, &b, &options), Some(1.0));
+    }
+
+    #[test]
+    fn test_preview_import() {
+        let options = DuplicateOptions::default();
+        let existing = vec![stored(1, "2023-07-10", "Rent", "Food", 900.0)];
+        let mut incoming = vec![
+            stored(7, "2023-07-10", "Rent", "Food", 900.0),
+            stored(8, "2023-07-12", "Cinema", "Food", 15.0),
+            stored(9, "2023-07-12", "Cinema", "Food", 15.0),
+        ];
+        // Reconciled and trashed where they came from
+        incoming[1].status = ExpenseStatus::Reconciled;
+        incoming[2].deleted_at = Some("2023-07-13T00:00:00.000Z".to_string());
+
+        let preview = preview_import(&existing, incoming, &options);
+        assert_eq!(preview.fresh.len(), 1);
+        assert_eq!(preview.fresh[0].name, "Cinema");
+        assert_eq!(preview.fresh[0].status, ExpenseStatus::Pending);
+        assert_eq!(preview.matches.len(), 2);
+        assert_eq!(preview.matches[0].original.id, Some(1));
+        assert!(preview.matches[0].duplicate.id.is_none());
+        assert!(preview.matches[1].duplicate.deleted_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_duplicates() {
+        let db = Database::new().await.unwrap();
+        let options = DuplicateOptions::default();
+        db.insert_expense(&expense("2023-07-10", "Rent", "Food", 900.0))
+            .await
+            .unwrap();
+        let mut copy = expense("2023-07-11", "rent", "Food", 900.0);
+        copy.external_id = Some("tx-42".to_string());
+        db.insert_expense(&copy).await.unwrap();
+        db.insert_expense(&expense("2023-07-11", "Lunch", "Food", 9.0))
+            .await
+            .unwrap();
+
+        let matches = find_duplicates(&db.list_expenses().await.unwrap(), &options);
+        assert_eq!(matches.len(), 1);
+
+        let removed = resolve_duplicates(&db, &matches, &[DuplicateAction::Merge])
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+
+        let remaining = db.list_expenses().await.unwrap();
+        assert_eq!(remaining.len(), 2);
+        let rent = remaining.iter().find(|e| e.name == "Rent").unwrap();
+        assert_eq!(rent.external_id.as_deref(), Some("tx-42"));
+    }
+
+    #[tokio::test]
+    async fn test_commit_import() {
+        let db = Database::new().await.unwrap();
+        let options = DuplicateOptions::default();
+        db.insert_expense(&expense("2023-07-10", "Rent", "Food", 900.0))
+            .await
+            .unwrap();
+
+        let incoming = vec![
+            expense("2023-07-10", "Rent", "Food", 900.0),
+            expense("2023-07-10", "Rent", "Food", 900.0),
+            expense("2023-07-12", "Cinema", "Food", 15.0),
+        ];
+        let existing = db.list_expenses().await.unwrap();
+        let preview = preview_import(&existing, incoming, &options);
+        assert_eq!(preview.matches.len(), 2);
+
+        let inserted = commit_import(
+            &db,
+            &preview,
+            &[DuplicateAction::Merge, DuplicateAction::Keep],
+        )
+        .await
+        .unwrap();
+        assert_eq!(inserted, 2);
+        assert_eq!(db.list_expenses().await.unwrap().len(), 3);
+
+        // The import either goes in whole or not at all
+        let mut clash = expense("2023-08-01", "Taxi", "Travel", 20.0);
+        clash.uid = db.list_expenses().await.unwrap()[0].uid.clone();
+        let preview = ImportPreview {
+            fresh: vec![expense("2023-08-01", "Bus", "Travel", 3.0), clash],
+            matches: Vec::new(),
+        };
+        assert!(commit_import(&db, &preview, &[]).await.is_err());
+        assert_eq!(db.list_expenses().await.unwrap().len(), 3);
+    }
+}
@@ -2,13 +2,88 @@ use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Clone, Debug, FromRow, Serialize, Deserialize)]
+/// Where an expense stands against the bank statement.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ExpenseStatus {
+    /// Recorded but not yet seen on a statement.
+    #[default]
+    Pending,
+    /// Ticked off against a statement that is still being reconciled.
+    Cleared,
+    /// Part of a finished reconciliation. Reconciled expenses are locked.
+    Reconciled,
+}
+
+impl ExpenseStatus {
+    /// Name as used in the data file and on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpenseStatus::Pending => "pending",
+            ExpenseStatus::Cleared => "cleared",
+            ExpenseStatus::Reconciled => "reconciled",
+        }
+    }
+
+    /// Single character marker used in the expense list.
+    pub fn marker(&self) -> char {
+        match self {
+            ExpenseStatus::Pending => ' ',
+            ExpenseStatus::Cleared => 'c',
+            ExpenseStatus::Reconciled => 'R',
+        }
+    }
+}
+
+impl std::str::FromStr for ExpenseStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pending" => Ok(ExpenseStatus::Pending),
+            "cleared" => Ok(ExpenseStatus::Cleared),
+            "reconciled" => Ok(ExpenseStatus::Reconciled),
+            other => Err(format!(
+                "unknown status '{}', expected pending, cleared or reconciled",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct Expense {
     pub id: Option<i64>,
     pub date: String,
     pub name: String,
     pub category: String,
     pub amount: f64,
+    /// Identifier assigned by the bank or the source of an import, used to
+    /// recognise the same transaction when it's imported twice.
+    #[serde(default)]
+    pub external_id: Option<String>,
+    /// Bank account or card the expense was paid from.
+    #[serde(default)]
+    pub account: String,
+    /// Free-form remarks.
+    #[serde(default)]
+    pub notes: String,
+    /// Comma separated labels, e.g. `holiday, kids`.
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub status: ExpenseStatus,
+    /// Globally unique id that stays the same when the ledger is copied to
+    /// another machine, so diverged copies can be merged entry by entry.
+    #[serde(default)]
+    pub uid: String,
+    /// When the expense was last changed, as an RFC 3339 UTC timestamp.
+    #[serde(default)]
+    pub modified_at: String,
+    /// When the expense was moved to the trash, `None` while it isn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
 }
 
 impl Expense {
@@ -22,6 +97,77 @@ impl Expense {
             name: name.to_string(),
             category: category.to_string(),
             amount,
+            external_id: None,
+            account: String::new(),
+            notes: String::new(),
+            tags: String::new(),
+            status: ExpenseStatus::Pending,
+            uid: new_uid(),
+            modified_at: now_timestamp(),
+            deleted_at: None,
         })
     }
 }
+
+impl Expense {
+    /// The tags one by one, without surrounding spaces.
+    pub fn tag_list(&self) -> Vec<&str> {
+        self.tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tag_list()
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(tag.trim()))
+    }
+
+    /// Adds a tag unless it's already there, ignoring case. Returns whether
+    /// anything changed.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        if tag.is_empty() || self.has_tag(tag) {
+            return false;
+        }
+        let mut tags = self.tag_list();
+        tags.push(tag);
+        self.tags = tags.join(", ");
+        true
+    }
+
+    /// Removes a tag, ignoring case. Returns whether anything changed.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        if !self.has_tag(tag) {
+            return false;
+        }
+        let tags: Vec<&str> = self
+            .tag_list()
+            .into_iter()
+            .filter(|t| !t.eq_ignore_ascii_case(tag.trim()))
+            .collect();
+        self.tags = tags.join(", ");
+        true
+    }
+}
+
+pub fn new_uid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// The uid of an expense saved before expenses had uids. It's made from the
+/// expense itself so every machine that migrates the same file ends up with
+/// the same uids; `occurrence` tells identical expenses apart.
+pub fn legacy_uid(expense: &Expense, occurrence: usize) -> String {
+    let content = format!(
+        "{}\u{1f}{}\u{1f}{}\u{1f}{}\u{1f}{}",
+        expense.date, expense.name, expense.category, expense.amount, occurrence
+    );
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, content.as_bytes()).to_string()
+}
+
+pub fn now_timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
@@ -0,0 +1,147 @@
+use chrono::NaiveDate;
+
+use crate::expense::{Expense, ExpenseStatus};
+
+/// Criteria for narrowing down the expense list. Unset fields match
+/// everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpenseFilter {
+    /// Text that must appear in the name, category or notes, ignoring case.
+    pub text: Option<String>,
+    pub category: Option<String>,
+    /// Categories of which the expense must have one, if any are given.
+    pub categories: Vec<String>,
+    /// First day to include.
+    pub start: Option<NaiveDate>,
+    /// Last day to include.
+    pub end: Option<NaiveDate>,
+    pub account: Option<String>,
+    pub status: Option<ExpenseStatus>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
+
+impl ExpenseFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, expense: &Expense) -> bool {
+        if let Some(text) = &self.text {
+            let text = text.to_lowercase();
+            if ![
+                &expense.name,
+                &expense.category,
+                &expense.notes,
+                &expense.tags,
+            ]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&text))
+            {
+                return false;
+            }
+        }
+        if !self.categories.is_empty()
+            && !self
+                .categories
+                .iter()
+                .any(|category| expense.category.eq_ignore_ascii_case(category))
+        {
+            return false;
+        }
+        if self
+            .category
+            .as_ref()
+            .is_some_and(|category| !expense.category.eq_ignore_ascii_case(category))
+        {
+            return false;
+        }
+        if self
+            .account
+            .as_ref()
+            .is_some_and(|account| !expense.account.eq_ignore_ascii_case(account))
+        {
+            return false;
+        }
+        if self.status.is_some_and(|status| expense.status != status) {
+            return false;
+        }
+        if self.min_amount.is_some_and(|min| expense.amount < min)
+            || self.max_amount.is_some_and(|max| expense.amount > max)
+        {
+            return false;
+        }
+        if self.start.is_some() || self.end.is_some() {
+            let Ok(date) = NaiveDate::parse_from_str(&expense.date, "%Y-%m-%d") else {
+                return false;
+            };
+            if self.start.is_some_and(|start| date < start)
+                || self.end.is_some_and(|end| date > end)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn apply(&self, mut expenses: Vec<Expense>) -> Vec<Expense> {
+        expenses.retain(|expense| self.matches(expense));
+        expenses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::expense;
+
+    #[test]
+    fn test_filter() {
+        let expenses = vec![
+            expense("2024-05-01", "Shop", "Food", 10.0),
+            expense("2024-05-10", "Shop", "food", 50.0),
+            expense("2024-05-20", "Shop", "Travel", 30.0),
+        ];
+        assert!(ExpenseFilter::default().is_empty());
+        assert_eq!(ExpenseFilter::default().apply(expenses.clone()).len(), 3);
+
+        let filter = ExpenseFilter {
+            category: Some("Food".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(expenses.clone()).len(), 2);
+
+        let filter = ExpenseFilter {
+            start: NaiveDate::from_ymd_opt(2024, 5, 5),
+            end: NaiveDate::from_ymd_opt(2024, 5, 20),
+            max_amount: Some(40.0),
+            ..Default::default()
+        };
+        let matched = filter.apply(expenses.clone());
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].category, "Travel");
+
+        let filter = ExpenseFilter {
+            categories: vec!["travel".to_string(), "Rent".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(expenses.clone()).len(), 1);
+    }
+
+    #[test]
+    fn test_text_search() {
+        let mut expenses = vec![
+            expense("2024-05-01", "Shop", "Food", 10.0),
+            expense("2024-05-02", "Shop", "Travel", 30.0),
+        ];
+        expenses[1].notes = "Train to the SHOPping centre".to_string();
+        let search = |text: &str| ExpenseFilter {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search("shop").apply(expenses.clone()).len(), 2);
+        assert_eq!(search("centre").apply(expenses.clone()).len(), 1);
+        assert_eq!(search("FOOD").apply(expenses.clone()).len(), 1);
+        assert!(search("bus").apply(expenses).is_empty());
+    }
+}
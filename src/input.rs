@@ -0,0 +1,287 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A single-line text field with a cursor and an optional selection.
+/// Positions count characters, not bytes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextInput {
+    value: String,
+    cursor: usize,
+    /// Where the selection started; the cursor is its other end.
+    anchor: Option<usize>,
+}
+
+impl TextInput {
+    /// A field holding `value` with the cursor at the end.
+    pub fn new(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            cursor: value.chars().count(),
+            anchor: None,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn len(&self) -> usize {
+        self.value.chars().count()
+    }
+
+    fn byte_index(&self, position: usize) -> usize {
+        self.value
+            .char_indices()
+            .nth(position)
+            .map_or(self.value.len(), |(index, _)| index)
+    }
+
+    /// The selected character range, if anything is selected.
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = self.anchor?;
+        match anchor.cmp(&self.cursor) {
+            std::cmp::Ordering::Less => Some((anchor, self.cursor)),
+            std::cmp::Ordering::Greater => Some((self.cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        let (from, to) = (self.byte_index(start), self.byte_index(end));
+        self.value.replace_range(from..to, "");
+        self.cursor = start;
+        self.anchor = None;
+    }
+
+    /// Deletes the selection; returns false when there was none.
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((start, end)) => {
+                self.delete_range(start, end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts text at the cursor, replacing the selection. Line breaks and
+    /// tabs become spaces since the field is a single line.
+    pub fn insert_str(&mut self, text: &str) {
+        self.delete_selection();
+        let text: String = text
+            .trim_end_matches(['\r', '\n'])
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        let index = self.byte_index(self.cursor);
+        self.value.insert_str(index, &text);
+        self.cursor += text.chars().count();
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.insert_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn delete_backward(&mut self) {
+        if !self.delete_selection() && self.cursor > 0 {
+            self.delete_range(self.cursor - 1, self.cursor);
+        }
+    }
+
+    pub fn delete_forward(&mut self) {
+        if !self.delete_selection() && self.cursor < self.len() {
+            self.delete_range(self.cursor, self.cursor + 1);
+        }
+    }
+
+    pub fn delete_word_backward(&mut self) {
+        if !self.delete_selection() {
+            let start = self.word_start();
+            self.delete_range(start, self.cursor);
+        }
+    }
+
+    pub fn delete_word_forward(&mut self) {
+        if !self.delete_selection() {
+            let cursor = self.cursor;
+            let end = self.word_end();
+            self.delete_range(cursor, end);
+        }
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn delete_to_start(&mut self) {
+        let cursor = self.cursor;
+        self.delete_range(0, cursor);
+    }
+
+    /// Start of the word before the cursor, skipping spaces first.
+    fn word_start(&self) -> usize {
+        let chars: Vec<char> = self.value.chars().collect();
+        let mut position = self.cursor;
+        while position > 0 && chars[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        while position > 0 && !chars[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        position
+    }
+
+    /// End of the word after the cursor, skipping spaces first.
+    fn word_end(&self) -> usize {
+        let chars: Vec<char> = self.value.chars().collect();
+        let mut position = self.cursor;
+        while position < chars.len() && chars[position].is_whitespace() {
+            position += 1;
+        }
+        while position < chars.len() && !chars[position].is_whitespace() {
+            position += 1;
+        }
+        position
+    }
+
+    /// Moves the cursor, extending the selection when `select` is set and
+    /// dropping it otherwise.
+    pub fn move_to(&mut self, position: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = position.min(self.len());
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.len();
+    }
+
+    /// Handles an editing key. Returns false for keys the field doesn't use,
+    /// so the caller can treat them as something else.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.select_all(),
+            KeyCode::Char('w') if ctrl => self.delete_word_backward(),
+            KeyCode::Char('u') if ctrl => self.delete_to_start(),
+            KeyCode::Char('d') if alt => self.delete_word_forward(),
+            KeyCode::Char('b') if alt => self.move_to(self.word_start(), shift),
+            KeyCode::Char('f') if alt => self.move_to(self.word_end(), shift),
+            KeyCode::Char(_) if ctrl || alt => return false,
+            KeyCode::Char(c) => self.insert_char(c),
+            KeyCode::Backspace if ctrl || alt => self.delete_word_backward(),
+            KeyCode::Backspace => self.delete_backward(),
+            KeyCode::Delete if ctrl || alt => self.delete_word_forward(),
+            KeyCode::Delete => self.delete_forward(),
+            KeyCode::Left if ctrl || alt => self.move_to(self.word_start(), shift),
+            KeyCode::Right if ctrl || alt => self.move_to(self.word_end(), shift),
+            KeyCode::Left => {
+                // Without shift, Left on a selection collapses it to its start
+                let target = match (self.selection(), shift) {
+                    (Some((start, _)), false) => start,
+                    _ => self.cursor.saturating_sub(1),
+                };
+                self.move_to(target, shift);
+            }
+            KeyCode::Right => {
+                let target = match (self.selection(), shift) {
+                    (Some((_, end)), false) => end,
+                    _ => self.cursor + 1,
+                };
+                self.move_to(target, shift);
+            }
+            KeyCode::Home => self.move_to(0, shift),
+            KeyCode::End => self.move_to(self.len(), shift),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn type_text(input: &mut TextInput, text: &str) {
+        for c in text.chars() {
+            input.handle_key(key(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn test_insert_anywhere() {
+        let mut input = TextInput::new("cofee");
+        input.handle_key(key(KeyCode::Home, KeyModifiers::NONE));
+        input.handle_key(key(KeyCode::Right, KeyModifiers::NONE));
+        input.handle_key(key(KeyCode::Right, KeyModifiers::NONE));
+        type_text(&mut input, "f");
+        assert_eq!(input.value(), "coffee");
+        assert_eq!(input.cursor(), 3);
+
+        input.handle_key(key(KeyCode::End, KeyModifiers::NONE));
+        type_text(&mut input, " ☕");
+        input.handle_key(key(KeyCode::Backspace, KeyModifiers::NONE));
+        assert_eq!(input.value(), "coffee ");
+
+        // Keys the field doesn't use are left to the caller
+        assert!(!input.handle_key(key(KeyCode::Char('s'), KeyModifiers::CONTROL)));
+        assert!(!input.handle_key(key(KeyCode::Enter, KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_amount_keeps_raw_text() {
+        let mut input = TextInput::default();
+        type_text(&mut input, "0.05");
+        assert_eq!(input.value(), "0.05");
+        type_text(&mut input, "0");
+        input.handle_key(key(KeyCode::Backspace, KeyModifiers::NONE));
+        assert_eq!(input.value(), "0.05");
+    }
+
+    #[test]
+    fn test_word_deletion() {
+        let mut input = TextInput::new("corner shop  ");
+        input.handle_key(key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(input.value(), "corner ");
+        input.handle_key(key(KeyCode::Home, KeyModifiers::NONE));
+        input.handle_key(key(KeyCode::Delete, KeyModifiers::CONTROL));
+        assert_eq!(input.value(), " ");
+        input.handle_key(key(KeyCode::Left, KeyModifiers::CONTROL));
+        assert_eq!(input.cursor(), 0);
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut input = TextInput::new("big lunch");
+        input.handle_key(key(
+            KeyCode::Left,
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        ));
+        assert_eq!(input.selection(), Some((4, 9)));
+        type_text(&mut input, "dinner");
+        assert_eq!(input.value(), "big dinner");
+        assert_eq!(input.selection(), None);
+
+        input.handle_key(key(KeyCode::Char('a'), KeyModifiers::CONTROL));
+        input.handle_key(key(KeyCode::Backspace, KeyModifiers::NONE));
+        assert_eq!(input.value(), "");
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut input = TextInput::new("Fish");
+        input.insert_str(" and\tchips\n");
+        assert_eq!(input.value(), "Fish and chips");
+        assert_eq!(input.cursor(), 14);
+    }
+}
@@ -0,0 +1,640 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+/// Something the user can ask for with a key press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    AddExpense,
+    EditExpense,
+    Delete,
+    SelectPrevious,
+    SelectNext,
+    Search,
+    FilterPanel,
+    ClearFilter,
+    SortNextColumn,
+    ReverseSort,
+    ExportXlsx,
+    HtmlReport,
+    MarkdownReport,
+    Import,
+    FindDuplicates,
+    Reconcile,
+    ChangePassphrase,
+    SwitchLedger,
+    Sync,
+    Dashboard,
+    Calendar,
+    NextScreen,
+    PreviousScreen,
+    Back,
+    Undo,
+    Redo,
+    History,
+    ToggleMark,
+    MarkUp,
+    MarkDown,
+    MarkAll,
+    BulkActions,
+    Restore,
+    Purge,
+    PreviousDay,
+    NextDay,
+    PreviousWeek,
+    NextWeek,
+    PreviousMonth,
+    NextMonth,
+    Submit,
+    Cancel,
+    NextField,
+    PreviousField,
+}
+
+impl Action {
+    /// Short description for the footer.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::AddExpense => "add expense",
+            Action::EditExpense => "edit",
+            Action::Delete => "delete",
+            Action::SelectPrevious => "up",
+            Action::SelectNext => "down",
+            Action::Search => "search",
+            Action::FilterPanel => "filter",
+            Action::ClearFilter => "clear filter",
+            Action::SortNextColumn => "sort by next column",
+            Action::ReverseSort => "reverse sort",
+            Action::ExportXlsx => "export xlsx",
+            Action::HtmlReport => "monthly report",
+            Action::MarkdownReport => "markdown report",
+            Action::Import => "import",
+            Action::FindDuplicates => "find duplicates",
+            Action::Reconcile => "reconcile",
+            Action::ChangePassphrase => "set passphrase",
+            Action::SwitchLedger => "switch ledger",
+            Action::Sync => "sync",
+            Action::Dashboard => "dashboard",
+            Action::Calendar => "calendar",
+            Action::NextScreen => "next screen",
+            Action::PreviousScreen => "previous screen",
+            Action::Back => "back",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::History => "history",
+            Action::ToggleMark => "mark",
+            Action::MarkUp => "mark up",
+            Action::MarkDown => "mark down",
+            Action::MarkAll => "mark all",
+            Action::BulkActions => "bulk actions",
+            Action::Restore => "restore",
+            Action::Purge => "delete for good",
+            Action::PreviousDay => "previous day",
+            Action::NextDay => "next day",
+            Action::PreviousWeek => "previous week",
+            Action::NextWeek => "next week",
+            Action::PreviousMonth => "previous month",
+            Action::NextMonth => "next month",
+            Action::Submit => "save",
+            Action::Cancel => "cancel",
+            Action::NextField => "next field",
+            Action::PreviousField => "previous field",
+        }
+    }
+}
+
+/// Which set of bindings is active.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Browsing the expense list and the other screens.
+    List,
+    /// Typing into the expense form, the search bar or the filter panel.
+    /// Keys without a binding are typed.
+    Form,
+    /// The Trash screen.
+    Trash,
+    /// The dashboard.
+    Dashboard,
+    /// The calendar, where the arrows pick a day.
+    Calendar,
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::List => "list",
+            Mode::Form => "form",
+            Mode::Trash => "trash",
+            Mode::Dashboard => "dashboard",
+            Mode::Calendar => "calendar",
+        }
+    }
+
+    fn actions(&self) -> &'static [Action] {
+        match self {
+            Mode::List => &[
+                Action::Quit,
+                Action::AddExpense,
+                Action::EditExpense,
+                Action::Delete,
+                Action::SelectPrevious,
+                Action::SelectNext,
+                Action::Search,
+                Action::FilterPanel,
+                Action::ClearFilter,
+                Action::SortNextColumn,
+                Action::ReverseSort,
+                Action::ExportXlsx,
+                Action::HtmlReport,
+                Action::MarkdownReport,
+                Action::Import,
+                Action::FindDuplicates,
+                Action::Reconcile,
+                Action::ChangePassphrase,
+                Action::SwitchLedger,
+                Action::Sync,
+                Action::Dashboard,
+                Action::Calendar,
+                Action::NextScreen,
+                Action::PreviousScreen,
+                Action::Back,
+                Action::Undo,
+                Action::Redo,
+                Action::History,
+                Action::ToggleMark,
+                Action::MarkUp,
+                Action::MarkDown,
+                Action::MarkAll,
+                Action::BulkActions,
+            ],
+            Mode::Form => &[
+                Action::Submit,
+                Action::Cancel,
+                Action::NextField,
+                Action::PreviousField,
+            ],
+            Mode::Trash => &[Action::Restore, Action::Purge],
+            Mode::Dashboard => &[Action::PreviousMonth, Action::NextMonth],
+            Mode::Calendar => &[
+                Action::PreviousDay,
+                Action::NextDay,
+                Action::PreviousWeek,
+                Action::NextWeek,
+                Action::PreviousMonth,
+                Action::NextMonth,
+            ],
+        }
+    }
+
+    fn default_keys(&self, action: Action) -> &'static [&'static str] {
+        match (self, action) {
+            (Mode::Calendar, Action::PreviousMonth) => return &["PageUp", "["],
+            (Mode::Calendar, Action::NextMonth) => return &["PageDown", "]"],
+            _ => {}
+        }
+        match action {
+            Action::Quit => &["q"],
+            Action::AddExpense => &["a"],
+            Action::EditExpense => &["e"],
+            Action::Delete => &["d"],
+            Action::SelectPrevious => &["Up", "k"],
+            Action::SelectNext => &["Down", "j"],
+            Action::Search => &["/"],
+            Action::FilterPanel => &["f"],
+            Action::ClearFilter => &["Esc"],
+            Action::SortNextColumn => &["o"],
+            Action::ReverseSort => &["O"],
+            Action::ExportXlsx => &["x"],
+            Action::HtmlReport => &["r"],
+            Action::MarkdownReport => &["m"],
+            Action::Import => &["i"],
+            Action::FindDuplicates => &["D"],
+            Action::Reconcile => &["R"],
+            Action::ChangePassphrase => &["P"],
+            Action::SwitchLedger => &["L"],
+            Action::Sync => &["S"],
+            Action::Dashboard => &["g"],
+            Action::Calendar => &["c"],
+            Action::NextScreen => &["Tab"],
+            Action::PreviousScreen => &["BackTab"],
+            Action::Back => &["Backspace"],
+            Action::Undo => &["u"],
+            Action::Redo => &["Ctrl-r"],
+            Action::History => &["H"],
+            Action::ToggleMark => &["Space"],
+            Action::MarkUp => &["Shift-Up", "K"],
+            Action::MarkDown => &["Shift-Down", "J"],
+            Action::MarkAll => &["Ctrl-a"],
+            Action::BulkActions => &["b"],
+            Action::Restore => &["r"],
+            Action::Purge => &["p", "Delete"],
+            Action::PreviousDay => &["Left", "h"],
+            Action::NextDay => &["Right", "l"],
+            Action::PreviousWeek => &["Up", "k"],
+            Action::NextWeek => &["Down", "j"],
+            Action::PreviousMonth => &["Left", "h"],
+            Action::NextMonth => &["Right", "l"],
+            Action::Submit => &["Enter"],
+            Action::Cancel => &["Esc"],
+            Action::NextField => &["Tab"],
+            Action::PreviousField => &["BackTab"],
+        }
+    }
+}
+
+/// A key with its modifiers, written like `q`, `Ctrl-r`, `Alt-Enter` or `F2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Shift is part of the key itself for characters (`D` vs `d`) and
+    /// BackTab, so it is ignored there.
+    fn normalized(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        *self == Self::normalized(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A trailing "-" after a separator is the minus key itself
+        let (prefix, key) = match s.strip_suffix("--") {
+            Some(prefix) => (Some(prefix), "-"),
+            None if s == "-" => (None, "-"),
+            None => match s.rsplit_once('-') {
+                Some((prefix, key)) => (Some(prefix), key),
+                None => (None, s),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.into_iter().flat_map(|prefix| prefix.split('-')) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, s)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", s)),
+                },
+            },
+        };
+        // Shift-Tab arrives as BackTab
+        if code == KeyCode::Tab && modifiers.contains(KeyModifiers::SHIFT) {
+            return Ok(Self::normalized(KeyCode::BackTab, modifiers));
+        }
+        Ok(Self::normalized(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::BackTab => write!(f, "Shift-Tab"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Keys for one action in the config, either a single key or a list.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySpec {
+    fn keys(&self) -> Vec<&str> {
+        match self {
+            KeySpec::One(key) => vec![key.as_str()],
+            KeySpec::Many(keys) => keys.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// The `[keys.<mode>]` config tables, e.g. `[keys.list]`, mapping actions to
+/// the keys that replace their defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    pub list: BTreeMap<Action, KeySpec>,
+    pub form: BTreeMap<Action, KeySpec>,
+    pub trash: BTreeMap<Action, KeySpec>,
+    pub dashboard: BTreeMap<Action, KeySpec>,
+    pub calendar: BTreeMap<Action, KeySpec>,
+}
+
+/// A key binding problem in the config: the offending key and what's wrong.
+#[derive(Debug, PartialEq)]
+pub struct KeymapError {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid `{}`: {}", self.key, self.message)
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    list: Vec<(KeyBinding, Action)>,
+    form: Vec<(KeyBinding, Action)>,
+    trash: Vec<(KeyBinding, Action)>,
+    dashboard: Vec<(KeyBinding, Action)>,
+    calendar: Vec<(KeyBinding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&KeysConfig::default()).expect("default bindings are valid")
+    }
+}
+
+impl Keymap {
+    /// Default bindings with the configured ones applied on top. A key taken
+    /// by a configured action is removed from whatever default had it.
+    pub fn from_config(config: &KeysConfig) -> Result<Self, KeymapError> {
+        Ok(Self {
+            list: Self::build(Mode::List, &config.list)?,
+            form: Self::build(Mode::Form, &config.form)?,
+            trash: Self::build(Mode::Trash, &config.trash)?,
+            dashboard: Self::build(Mode::Dashboard, &config.dashboard)?,
+            calendar: Self::build(Mode::Calendar, &config.calendar)?,
+        })
+    }
+
+    fn build(
+        mode: Mode,
+        overrides: &BTreeMap<Action, KeySpec>,
+    ) -> Result<Vec<(KeyBinding, Action)>, KeymapError> {
+        let mut configured: Vec<(KeyBinding, Action)> = Vec::new();
+        for (action, spec) in overrides {
+            let key = format!("keys.{}.{}", mode.name(), action_name(*action));
+            if !mode.actions().contains(action) {
+                return Err(KeymapError {
+                    key,
+                    message: format!("not available in {} mode", mode.name()),
+                });
+            }
+            for text in spec.keys() {
+                let binding = text.parse::<KeyBinding>().map_err(|message| KeymapError {
+                    key: key.clone(),
+                    message,
+                })?;
+                if let Some((_, other)) = configured.iter().find(|(b, _)| *b == binding) {
+                    return Err(KeymapError {
+                        key,
+                        message: format!(
+                            "'{}' is already bound to {}",
+                            binding,
+                            action_name(*other)
+                        ),
+                    });
+                }
+                configured.push((binding, *action));
+            }
+        }
+
+        let mut bindings = Vec::new();
+        for action in mode.actions() {
+            if overrides.contains_key(action) {
+                bindings.extend(configured.iter().filter(|(_, a)| a == action).copied());
+                continue;
+            }
+            for text in mode.default_keys(*action) {
+                let binding: KeyBinding = text.parse().expect("default bindings are valid");
+                if !configured.iter().any(|(b, _)| *b == binding) {
+                    bindings.push((binding, *action));
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn bindings(&self, mode: Mode) -> &[(KeyBinding, Action)] {
+        match mode {
+            Mode::List => &self.list,
+            Mode::Form => &self.form,
+            Mode::Trash => &self.trash,
+            Mode::Dashboard => &self.dashboard,
+            Mode::Calendar => &self.calendar,
+        }
+    }
+
+    pub fn action(&self, mode: Mode, event: &KeyEvent) -> Option<Action> {
+        self.bindings(mode)
+            .iter()
+            .find(|(binding, _)| binding.matches(event))
+            .map(|(_, action)| *action)
+    }
+
+    /// Footer text listing every bound action of the mode, e.g.
+    /// `'q' quit, 'Up/k' up`.
+    pub fn help(&self, mode: Mode) -> String {
+        mode.actions()
+            .iter()
+            .filter_map(|action| {
+                let keys = self.help_for(mode, *action);
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some(format!("'{}' {}", keys, action.describe()))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The keys bound to `action`, e.g. `Up/k`, empty when it has none.
+    pub fn help_for(&self, mode: Mode, action: Action) -> String {
+        self.bindings(mode)
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(binding, _)| binding.to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn action_name(action: Action) -> String {
+    // The snake_case name used in the config
+    let debug = format!("{:?}", action);
+    let mut name = String::new();
+    for (index, c) in debug.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        let binding: KeyBinding = "Ctrl-r".parse().unwrap();
+        assert_eq!(binding.code, KeyCode::Char('r'));
+        assert_eq!(binding.modifiers, KeyModifiers::CONTROL);
+        assert_eq!(binding.to_string(), "Ctrl-r");
+
+        assert_eq!(
+            "Alt--".parse::<KeyBinding>().unwrap().code,
+            KeyCode::Char('-')
+        );
+        assert_eq!(
+            "Shift-Tab".parse::<KeyBinding>().unwrap(),
+            "BackTab".parse().unwrap()
+        );
+        assert_eq!("F5".parse::<KeyBinding>().unwrap().code, KeyCode::F(5));
+        assert!("Hyper-x".parse::<KeyBinding>().is_err());
+        assert!("Enterprise".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn test_form_mode_types_letters() {
+        let keymap = Keymap::default();
+        let q = press(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::List, &q), Some(Action::Quit));
+        assert_eq!(keymap.action(Mode::Form, &q), None);
+        let shift_d = press(KeyCode::Char('D'), KeyModifiers::SHIFT);
+        assert_eq!(
+            keymap.action(Mode::List, &shift_d),
+            Some(Action::FindDuplicates)
+        );
+        let enter = press(KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::Form, &enter), Some(Action::Submit));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config: KeysConfig = toml::from_str(
+            r#"
+            [list]
+            quit = ["Ctrl-q", "Ctrl-c"]
+            delete = "q"
+            "#,
+        )
+        .unwrap();
+        let keymap = Keymap::from_config(&config).unwrap();
+        let q = press(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::List, &q), Some(Action::Delete));
+        let ctrl_c = press(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.action(Mode::List, &ctrl_c), Some(Action::Quit));
+        // "d" was only the default for delete
+        let d = press(KeyCode::Char('d'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::List, &d), None);
+        assert!(keymap.help(Mode::List).starts_with("'Ctrl-q/Ctrl-c' quit"));
+    }
+
+    #[test]
+    fn test_override_errors() {
+        let config: KeysConfig = toml::from_str("[list]\nsubmit = \"Enter\"\n").unwrap();
+        let err = Keymap::from_config(&config).unwrap_err();
+        assert_eq!(err.key, "keys.list.submit");
+
+        let config: KeysConfig = toml::from_str("[list]\nquit = \"x\"\nimport = \"x\"\n").unwrap();
+        let err = Keymap::from_config(&config).unwrap_err();
+        assert_eq!(err.key, "keys.list.import");
+        assert!(err.message.contains("quit"));
+
+        assert!(toml::from_str::<KeysConfig>("[list]\nfly = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn test_screen_modes() {
+        let keymap = Keymap::default();
+        let h = press(KeyCode::Char('h'), KeyModifiers::NONE);
+        assert_eq!(
+            keymap.action(Mode::Dashboard, &h),
+            Some(Action::PreviousMonth)
+        );
+        // The arrows and hjkl pick a day on the calendar
+        assert_eq!(keymap.action(Mode::Calendar, &h), Some(Action::PreviousDay));
+        assert!(keymap
+            .help(Mode::Calendar)
+            .ends_with("'PageUp/[' previous month, 'PageDown/]' next month"));
+        let config: KeysConfig = toml::from_str("[calendar]\nnext_week = \"n\"\n").unwrap();
+        let keymap = Keymap::from_config(&config).unwrap();
+        let n = press(KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::Calendar, &n), Some(Action::NextWeek));
+        let delete = press(KeyCode::Delete, KeyModifiers::NONE);
+        assert_eq!(keymap.action(Mode::Trash, &delete), Some(Action::Purge));
+
+        let config: KeysConfig = toml::from_str("[trash]\nrestore = \"u\"\n").unwrap();
+        let keymap = Keymap::from_config(&config).unwrap();
+        assert_eq!(keymap.help_for(Mode::Trash, Action::Restore), "u");
+        let config: KeysConfig = toml::from_str("[trash]\nnext_month = \"x\"\n").unwrap();
+        assert_eq!(
+            Keymap::from_config(&config).unwrap_err().key,
+            "keys.trash.next_month"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto;
+
+/// Directory holding one sub-directory per ledger.
+pub const DEFAULT_ROOT: &str = "ledgers";
+/// Where the data lived before ledgers existed.
+pub const LEGACY_DATA_FILE: &str = "expenses.json";
+pub const DEFAULT_LEDGER: &str = "default";
+
+const DATA_FILE: &str = "expenses.json";
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Settings stored alongside each ledger's data.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LedgerSettings {
+    #[serde(default)]
+    pub description: String,
+    /// Currency symbol shown next to amounts in this ledger.
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// A named, separate set of expenses such as "household" or "rental".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ledger {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl Ledger {
+    pub fn data_file(&self) -> String {
+        self.dir.join(DATA_FILE).to_string_lossy().into_owned()
+    }
+
+    /// State of the ledger at the last sync with `other_file`, the common
+    /// ancestor for the next merge with it. Each file the ledger is synced
+    /// with has its own.
+    pub fn sync_base_file(&self, other_file: &str) -> String {
+        // The same whether or not the file exists yet
+        let other = std::path::absolute(other_file).unwrap_or_else(|_| PathBuf::from(other_file));
+        let id = uuid::Uuid::new_v5(
+            &uuid::Uuid::NAMESPACE_URL,
+            other.to_string_lossy().as_bytes(),
+        );
+        self.dir
+            .join(format!("sync-base-{}.json", id.simple()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn settings_file(&self) -> PathBuf {
+        self.dir.join(SETTINGS_FILE)
+    }
+
+    /// Settings of the ledger, or the defaults if none have been saved yet.
+    pub fn load_settings(&self) -> Result<LedgerSettings, Box<dyn std::error::Error>> {
+        match fs::read_to_string(self.settings_file()) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(LedgerSettings::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save_settings(
+        &self,
+        settings: &LedgerSettings,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(
+            self.settings_file(),
+            serde_json::to_string_pretty(settings)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        fs::read(self.data_file()).is_ok_and(|contents| crypto::is_encrypted(&contents))
+    }
+}
+
+pub struct LedgerStore {
+    root: PathBuf,
+}
+
+impl LedgerStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// All ledgers, sorted by name.
+    pub fn list(&self) -> std::io::Result<Vec<Ledger>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut ledgers = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                ledgers.push(Ledger {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    dir: entry.path(),
+                });
+            }
+        }
+        ledgers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ledgers)
+    }
+
+    /// Whether a ledger with this name exists.
+    pub fn contains(&self, name: &str) -> bool {
+        validate_name(name).is_ok() && self.root.join(name).is_dir()
+    }
+
+    pub fn open(&self, name: &str) -> Result<Ledger, Box<dyn std::error::Error>> {
+        validate_name(name)?;
+        let dir = self.root.join(name);
+        if !dir.is_dir() {
+            return Err(format!("ledger '{}' doesn't exist", name).into());
+        }
+        Ok(Ledger {
+            name: name.to_string(),
+            dir,
+        })
+    }
+
+    pub fn create(&self, name: &str) -> Result<Ledger, Box<dyn std::error::Error>> {
+        validate_name(name)?;
+        let dir = self.root.join(name);
+        if dir.exists() {
+            return Err(format!("ledger '{}' already exists", name).into());
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(Ledger {
+            name: name.to_string(),
+            dir,
+        })
+    }
+
+    pub fn open_or_create(&self, name: &str) -> Result<Ledger, Box<dyn std::error::Error>> {
+        match self.open(name) {
+            Ok(ledger) => Ok(ledger),
+            Err(_) => self.create(name),
+        }
+    }
+
+    /// Copies a data file from before ledgers existed into the default
+    /// ledger, the first time the ledger directory is set up. The original
+    /// file is left where it was.
+    pub fn migrate_legacy(&self, legacy: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.root.exists() || !legacy.is_file() {
+            return Ok(false);
+        }
+        let ledger = self.create(DEFAULT_LEDGER)?;
+        fs::copy(legacy, ledger.data_file())?;
+        Ok(true)
+    }
+}
+
+/// Ledger names become directory names, so keep them to a safe set of
+/// characters.
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == ' ');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid ledger name '{}': use letters, digits, spaces, '-' and '_'",
+            name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_create_list_and_open() {
+        let root = TempDir::new().unwrap();
+        let store = LedgerStore::new(root.path());
+        assert!(store.list().unwrap().is_empty());
+
+        store.create("rental").unwrap();
+        store.create("household").unwrap();
+        assert!(store.create("household").is_err());
+        assert!(store.create("../escape").is_err());
+        assert!(store.open("missing").is_err());
+        assert!(!store.contains("missing"));
+        assert!(store.contains("rental"));
+        assert!(!store.contains("../escape"));
+
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|l| l.name).collect();
+        assert_eq!(names, ["household", "rental"]);
+
+        let ledger = store.open_or_create("side business").unwrap();
+        assert!(ledger.data_file().ends_with("expenses.json"));
+        assert_eq!(store.list().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_sync_base_per_file() {
+        let root = TempDir::new().unwrap();
+        let ledger = LedgerStore::new(root.path()).create("household").unwrap();
+        let laptop = root
+            .path()
+            .join("laptop.json")
+            .to_string_lossy()
+            .into_owned();
+
+        let base = ledger.sync_base_file(&laptop);
+        assert!(base.starts_with(&*ledger.dir.to_string_lossy()));
+        fs::write(&laptop, "[]").unwrap();
+        assert_eq!(base, ledger.sync_base_file(&laptop));
+        assert_ne!(base, ledger.sync_base_file("phone.json"));
+    }
+
+    #[test]
+    fn test_settings() {
+        let root = TempDir::new().unwrap();
+        let ledger = LedgerStore::new(root.path()).create("household").unwrap();
+        assert_eq!(ledger.load_settings().unwrap(), LedgerSettings::default());
+
+        let settings = LedgerSettings {
+            description: "Family".to_string(),
+            currency: Some("£".to_string()),
+        };
+        ledger.save_settings(&settings).unwrap();
+        assert_eq!(ledger.load_settings().unwrap(), settings);
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("expenses.json");
+        fs::write(&legacy, "[]").unwrap();
+        let store = LedgerStore::new(dir.path().join("ledgers"));
+
+        assert!(store.migrate_legacy(&legacy).unwrap());
+        let ledger = store.open(DEFAULT_LEDGER).unwrap();
+        assert_eq!(fs::read_to_string(ledger.data_file()).unwrap(), "[]");
+        assert!(legacy.exists());
+
+        // Only ever done once
+        assert!(!store.migrate_legacy(&legacy).unwrap());
+    }
+}
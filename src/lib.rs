@@ -1,5 +1,29 @@
 pub mod app;
+pub mod audit;
+pub mod budget;
+pub mod bulk;
+pub mod cli;
+pub mod config;
+pub mod crypto;
 pub mod db;
 pub mod db_tests;
+pub mod duplicates;
 pub mod expense;
+pub mod filter;
+pub mod input;
+pub mod keymap;
+pub mod ledger;
+pub mod output;
+pub mod period;
+pub mod reconcile;
+pub mod recurring;
+pub mod report;
+pub mod server;
+pub mod summary;
+pub mod sync;
+pub mod table;
+#[cfg(test)]
+mod test_support;
+pub mod theme;
 pub mod ui;
+pub mod undo;
@@ -1,33 +1,145 @@
-mod app;
-mod db;
-mod expense;
-mod ui;
-
-use crate::app::{App, InputMode};
-use crate::db::Database;
-use crate::ui::ui;
+use chrono::{Datelike, NaiveDate};
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use expense::Expense;
+use expense_tracker::app::{
+    App, BulkMenu, Calendar, DuplicateReview, ExpenseHistory, FilterPanel, LedgerPicker,
+    MergeReview, PassphraseChange, PendingDelete, ReconcileSetup, ReviewKind, Screen, SettingsForm,
+    SyncPassphrase, REPORTS,
+};
+use expense_tracker::bulk::{self, BulkAction};
+use expense_tracker::cli;
+use expense_tracker::config::Config;
+use expense_tracker::crypto::Key;
+use expense_tracker::db::{Database, ExpenseChange};
+use expense_tracker::duplicates::{
+    commit_import, find_duplicates, preview_import_file, resolve_duplicates, DuplicateOptions,
+    ImportPreview,
+};
+use expense_tracker::expense::Expense;
+use expense_tracker::filter::ExpenseFilter;
+use expense_tracker::keymap::{Action, Keymap, Mode};
+use expense_tracker::ledger::{Ledger, LedgerStore, DEFAULT_LEDGER, LEGACY_DATA_FILE};
+use expense_tracker::output::{self, OutputFormat};
+use expense_tracker::period::{month_bounds, month_key, next_month, previous_month, Month};
+use expense_tracker::reconcile::Reconciliation;
+use expense_tracker::recurring::find_recurring;
+use expense_tracker::report;
+use expense_tracker::report::text::TextStyle;
+use expense_tracker::server::{self, ServerState};
+use expense_tracker::summary::{self, DashboardData, PeriodSummary};
+use expense_tracker::sync::{self, MergeOutcome, Resolution};
+use expense_tracker::table;
+use expense_tracker::theme::{self, Theme};
+use expense_tracker::ui::ui;
+use expense_tracker::undo::Change;
 use ratatui::backend::Backend;
+use ratatui::layout::Position;
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "A TUI expense tracker",
+    long_about = "A TUI expense tracker. Without a command the TUI is opened.\n\n\
+                  Commands exit with 0 on success, 1 on failure, 2 for invalid input \
+                  and 3 when the ledger, expense or budget doesn't exist."
+)]
+struct Cli {
+    /// Ledger to open, skipping the picker. Created if it doesn't exist.
+    #[arg(long, global = true)]
+    ledger: Option<String>,
+    /// Config file to use instead of the one in the user's config directory
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Ledger(cli::Command),
+    /// Serve the ledger as a JSON API instead of opening the TUI. Encrypted
+    /// ledgers are unlocked with EXPENSE_TRACKER_PASSPHRASE.
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Clients must send this as `Authorization: Bearer <token>`.
+        #[arg(long, env = "EXPENSE_TRACKER_TOKEN", hide_env_values = true)]
+        token: String,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        }
+    };
+    let store = LedgerStore::new(&config.data.dir);
+    store.migrate_legacy(Path::new(LEGACY_DATA_FILE))?;
+    let name = cli.ledger.as_deref().unwrap_or(DEFAULT_LEDGER);
+    match cli.command {
+        Some(Command::Serve { addr, token }) => return serve(&store, name, addr, token).await,
+        Some(Command::Ledger(command)) => {
+            let code = match run_command(&store, name, &command).await {
+                Ok(()) => 0,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    err.exit_code()
+                }
+            };
+            std::process::exit(code);
+        }
+        None => {}
+    }
+    //INFO: Resolve the ledger before touching the terminal so errors print normally
+    let ledger = match &cli.ledger {
+        Some(name) => Some(store.open_or_create(name)?),
+        None => None,
+    };
+
     //INFO: Set up terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     //INFO: Create a DB connection
-    let db = Database::new().await?;
-    db.load_expenses_from_file("expenses.json").await.unwrap();
+    let mut db = Database::new().await?;
+    let mut app = App::new();
+    app.keymap = Keymap::from_config(&config.keys)?;
+    app.theme = Theme::load(
+        &config.display.theme,
+        &config.themes,
+        theme::no_color_requested(),
+    )?;
+    app.config = config;
+    match ledger {
+        Some(ledger) => open_ledger(&mut app, &mut db, ledger).await?,
+        None => app.ledger_picker = Some(LedgerPicker::new(store.list()?)),
+    }
 
     //INFO: Add a test expense
     // let test_expense = Expense::new(
@@ -41,16 +153,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // println!("Added test expense");
     //
     //INFO: Create app and run it
-    let mut app = App::new();
-    app.expenses = db.list_expenses().await.unwrap();
-    let res = run_app::<CrosstermBackend<io::Stdout>>(&mut terminal, app, db).await;
+    let res = run_app::<CrosstermBackend<io::Stdout>>(&mut terminal, app, db, store).await;
 
     //INFO: Restore terminal
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
@@ -61,94 +172,305 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Loads a ledger outside the TUI. Encrypted ledgers are unlocked with the
+/// passphrase in EXPENSE_TRACKER_PASSPHRASE. A missing ledger is only created
+/// when `create` is set, so a mistyped name doesn't leave one behind.
+async fn load_headless(
+    store: &LedgerStore,
+    name: &str,
+    create: bool,
+) -> Result<(Ledger, Database, Option<Key>), Box<dyn std::error::Error>> {
+    let ledger = if create {
+        store.open_or_create(name)?
+    } else {
+        store.open(name)?
+    };
+    let db = Database::new().await?;
+    let data_file = ledger.data_file();
+    let key = if ledger.is_encrypted() {
+        let passphrase = std::env::var("EXPENSE_TRACKER_PASSPHRASE")
+            .map_err(|_| "ledger is encrypted, set EXPENSE_TRACKER_PASSPHRASE")?;
+        Some(db.load_encrypted_file(&data_file, &passphrase).await?)
+    } else {
+        db.load_expenses_from_file(&data_file).await?;
+        None
+    };
+    Ok((ledger, db, key))
+}
+
+async fn run_command(
+    store: &LedgerStore,
+    name: &str,
+    command: &cli::Command,
+) -> Result<(), cli::CliError> {
+    // Only adding an expense starts a new ledger
+    let create = matches!(command, cli::Command::Add(_));
+    if !create && !store.contains(name) {
+        return Err(cli::CliError::NotFound(format!(
+            "ledger '{}' doesn't exist",
+            name
+        )));
+    }
+    let (ledger, db, key) = load_headless(store, name, create).await?;
+    let today = chrono::Local::now().date_naive();
+    let changed = cli::run(command, &db, key.as_ref(), today, &mut io::stdout()).await?;
+    if changed {
+        db.save_to_file(&ledger.data_file(), key.as_ref()).await?;
+    }
+    Ok(())
+}
+
+async fn serve(
+    store: &LedgerStore,
+    name: &str,
+    addr: SocketAddr,
+    token: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (ledger, db, key) = load_headless(store, name, false).await?;
+    println!("Serving ledger '{}' on http://{}", ledger.name, addr);
+    let state = ServerState::new(db, token, Some(ledger.data_file()), key);
+    server::serve(addr, Arc::new(state)).await?;
+    Ok(())
+}
+
 async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
-    db: Database,
+    mut db: Database,
+    store: LedgerStore,
 ) -> io::Result<()> {
     loop {
-        terminal.draw(|f| ui::<B>(f, &app))?;
+        terminal.draw(|f| ui(f, &app))?;
 
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('q') => {
-                    db.save_expenses_to_file("expenses.json").await.unwrap();
+        let event = event::read()?;
+        if let Event::Paste(text) = &event {
+            handle_paste(&mut app, text);
+        }
+        if let Event::Mouse(mouse) = &event {
+            handle_mouse(&mut app, mouse);
+        }
+        if let Event::Key(key) = event {
+            app.status_message = None;
+            if app.ledger_picker.is_some() {
+                if !handle_ledger_picker(&mut app, &mut db, &store, key.code).await {
+                    return Ok(());
+                }
+                continue;
+            }
+            if app.unlock_prompt.is_some() {
+                handle_unlock_prompt(&mut app, &db, &store, key.code).await;
+                continue;
+            }
+            if app.passphrase_change.is_some() {
+                handle_passphrase_change(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.import_path.is_some() {
+                handle_import_prompt(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.sync_path.is_some() {
+                handle_sync_prompt(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.sync_passphrase.is_some() {
+                handle_sync_passphrase(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.merge_review.is_some() {
+                handle_merge_review(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.duplicate_review.is_some() {
+                handle_duplicate_review(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.reconcile_setup.is_some() {
+                handle_reconcile_setup(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.settings_form.is_some() {
+                handle_settings_form(&mut app, key.code);
+                continue;
+            }
+            if app.reconciliation.is_some() {
+                handle_reconciliation(&mut app, &db, key.code).await;
+                continue;
+            }
+            if app.pending_delete.is_some() {
+                handle_delete_confirmation(&mut app, &db, key).await;
+                continue;
+            }
+            if app.expense_history.is_some() {
+                handle_expense_history(&mut app, &db, key).await;
+                continue;
+            }
+            if app.bulk_menu.is_some() {
+                handle_bulk_menu(&mut app, &db, key).await;
+                continue;
+            }
+            if app.search.is_some() {
+                handle_search(&mut app, key);
+                continue;
+            }
+            if app.filter_panel.is_some() {
+                handle_filter_panel(&mut app, key);
+                continue;
+            }
+            if app.adding_expense {
+                match app.keymap.action(Mode::Form, &key) {
+                    Some(Action::Submit) => save_form(&mut app, &db).await,
+                    Some(Action::Cancel) => app.close_form(),
+                    Some(Action::NextField) => app.input_mode = app.input_mode.next(),
+                    Some(Action::PreviousField) => app.input_mode = app.input_mode.previous(),
+                    Some(_) => {}
+                    None => {
+                        app.form.field_mut(app.input_mode).handle_key(key);
+                    }
+                }
+                continue;
+            }
+            let action = app.keymap.action(Mode::List, &key);
+            // Quitting and moving between screens work on every screen
+            match action {
+                Some(Action::Quit) => {
+                    save_ledger(&app, &db)
+                        .await
+                        .map_err(|err| io::Error::other(err.to_string()))?;
                     return Ok(());
                 }
-                KeyCode::Char('a') => {
-                    app.adding_expense = true;
-                    app.new_expense =
-                        Expense::new(chrono::Local::now().date_naive(), "", "", 0.0).unwrap();
+                Some(Action::NextScreen) => {
+                    let screen = app.screen.next();
+                    show_screen(&mut app, &db, screen).await;
+                    continue;
                 }
-                KeyCode::Char('d') => {
-                    if let Some(selected) = app.selected_index {
-                        if let Some(expense) = app.expenses.get(selected) {
-                            if let Some(id) = expense.id {
-                                db.delete_expense(id).await.unwrap();
-                                app.expenses = db.list_expenses().await.unwrap();
-                            }
-                        }
+                Some(Action::PreviousScreen) => {
+                    let screen = app.screen.previous();
+                    show_screen(&mut app, &db, screen).await;
+                    continue;
+                }
+                Some(Action::Dashboard) => {
+                    show_screen(&mut app, &db, Screen::Dashboard).await;
+                    continue;
+                }
+                Some(Action::Calendar) => {
+                    show_screen(&mut app, &db, Screen::Calendar).await;
+                    continue;
+                }
+                Some(Action::Undo) => {
+                    undo(&mut app, &db, false).await;
+                    continue;
+                }
+                Some(Action::Redo) => {
+                    undo(&mut app, &db, true).await;
+                    continue;
+                }
+                Some(Action::Back) => {
+                    if !app.go_back() {
+                        app.status_message = Some("Nothing to go back to".to_string());
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if app.screen != Screen::Transactions {
+                handle_screen(&mut app, &db, key).await;
+                continue;
+            }
+            let Some(action) = action else {
+                continue;
+            };
+            match action {
+                Action::SwitchLedger => match store.list() {
+                    Ok(ledgers) => app.ledger_picker = Some(LedgerPicker::new(ledgers)),
+                    Err(err) => app.status_message = Some(format!("Can't list ledgers: {}", err)),
+                },
+                Action::AddExpense => app.start_adding(chrono::Local::now().date_naive()),
+                Action::EditExpense => {
+                    let opened = app.start_editing();
+                    if !opened {
+                        app.status_message = Some("Select an expense to edit".to_string());
                     }
                 }
-                KeyCode::Enter => {
-                    if app.adding_expense {
-                        db.insert_expense(&app.new_expense).await.unwrap();
-                        app.adding_expense = false;
-                        app.expenses = db.list_expenses().await.unwrap();
+                Action::ToggleMark => app.toggle_mark(),
+                Action::MarkUp => app.extend_marks(false),
+                Action::MarkDown => app.extend_marks(true),
+                Action::MarkAll => app.mark_all(),
+                Action::BulkActions => {
+                    if app.marked.is_empty() {
+                        app.status_message = Some(format!(
+                            "Mark expenses with '{}' first",
+                            app.keymap.help_for(Mode::List, Action::ToggleMark)
+                        ));
+                    } else {
+                        app.bulk_menu = Some(BulkMenu::default());
                     }
                 }
-                KeyCode::Char(c) => {
-                    if app.adding_expense {
-                        match app.input_mode {
-                            InputMode::Date => app.new_expense.date.push(c),
-                            InputMode::Name => app.new_expense.name.push(c),
-                            InputMode::Category => app.new_expense.category.push(c),
-                            InputMode::Amount => {
-                                if c.is_ascii_digit() || c == '.' {
-                                    let mut amount_str = app.new_expense.amount.to_string();
-                                    amount_str.push(c);
-                                    if let Ok(amount) = amount_str.parse() {
-                                        app.new_expense.amount = amount;
-                                    }
-                                }
-                            }
-                        }
+                Action::History => {
+                    let selected = app.selected_index.and_then(|i| app.expenses.get(i));
+                    if let Some(expense) = selected.cloned() {
+                        show_expense_history(&mut app, &db, expense).await;
                     }
                 }
-                KeyCode::Backspace => {
-                    if app.adding_expense {
-                        match app.input_mode {
-                            InputMode::Date => {
-                                app.new_expense.date.pop();
-                            }
-                            InputMode::Name => {
-                                app.new_expense.name.pop();
-                            }
-                            InputMode::Category => {
-                                app.new_expense.category.pop();
-                            }
-                            InputMode::Amount => {
-                                let mut amount_str = app.new_expense.amount.to_string();
-                                amount_str.pop();
-                                app.new_expense.amount = amount_str.parse().unwrap_or(0.0);
-                            }
+                Action::Delete => {
+                    let selected = app.selected_index.and_then(|i| app.expenses.get(i));
+                    if let Some(expense) = selected.cloned() {
+                        if app.config.behavior.confirm_delete {
+                            app.pending_delete = Some(PendingDelete {
+                                expense,
+                                purge: false,
+                            });
+                        } else {
+                            delete_expense(&mut app, &db, &expense).await;
                         }
                     }
                 }
-                KeyCode::Tab => {
-                    if app.adding_expense {
-                        app.input_mode = match app.input_mode {
-                            InputMode::Date => InputMode::Name,
-                            InputMode::Name => InputMode::Category,
-                            InputMode::Category => InputMode::Amount,
-                            InputMode::Amount => InputMode::Date,
+                Action::ExportXlsx | Action::HtmlReport | Action::MarkdownReport => {
+                    run_report(&mut app, &db, action).await;
+                }
+                Action::ChangePassphrase => {
+                    app.passphrase_change = Some(PassphraseChange::default());
+                }
+                Action::Import => {
+                    app.import_path = Some(String::new());
+                }
+                Action::Sync => {
+                    app.sync_path = Some(String::new());
+                }
+                Action::FindDuplicates => {
+                    let expenses = match db.list_expenses().await {
+                        Ok(expenses) => expenses,
+                        Err(err) => {
+                            app.status_message = Some(format!("Can't find duplicates: {}", err));
+                            continue;
+                        }
+                    };
+                    let matches = find_duplicates(&expenses, &DuplicateOptions::default());
+                    if matches.is_empty() {
+                        app.status_message = Some("No duplicates found".to_string());
+                    } else {
+                        let preview = ImportPreview {
+                            fresh: Vec::new(),
+                            matches,
                         };
+                        app.duplicate_review =
+                            Some(DuplicateReview::new(ReviewKind::Existing, preview));
                     }
                 }
-                KeyCode::Esc => {
-                    app.adding_expense = false;
+                Action::Reconcile => {
+                    app.reconcile_setup = Some(ReconcileSetup::default());
+                }
+                Action::Search => app.start_search(),
+                Action::FilterPanel => {
+                    app.filter_panel = Some(FilterPanel::new(&app.filter, &app.config));
                 }
-                KeyCode::Up => {
+                Action::ClearFilter => app.clear_filter(),
+                Action::SortNextColumn => app.sort_next_column(),
+                Action::ReverseSort => {
+                    let sort = app.sort.toggled(app.sort.column);
+                    app.set_sort(sort);
+                }
+                Action::SelectPrevious => {
                     if let Some(selected) = app.selected_index {
                         if selected > 0 {
                             app.selected_index = Some(selected - 1);
@@ -157,7 +479,7 @@ async fn run_app<B: Backend>(
                         app.selected_index = Some(0);
                     }
                 }
-                KeyCode::Down => {
+                Action::SelectNext => {
                     if let Some(selected) = app.selected_index {
                         if selected < app.expenses.len().saturating_sub(1) {
                             app.selected_index = Some(selected + 1);
@@ -171,3 +493,1150 @@ async fn run_app<B: Backend>(
         }
     }
 }
+
+/// Inserts or updates the expense in the form. The form stays open when the
+/// input doesn't validate or the database refuses the change.
+async fn save_form(app: &mut App, db: &Database) {
+    let expense = match app.form_expense() {
+        Ok(expense) => expense,
+        Err(message) => {
+            app.status_message = Some(message);
+            return;
+        }
+    };
+    let result = match app.editing {
+        Some(id) => match db.get_expense(id).await {
+            Ok(before) => db.update_expense(&expense).await.map(|()| (id, before)),
+            Err(err) => Err(err),
+        },
+        None => db.insert_expense(&expense).await.map(|id| (id, None)),
+    };
+    match result {
+        Ok((id, before)) => {
+            if let Ok(Some(after)) = db.get_expense(id).await {
+                app.history.record(match before {
+                    Some(before) => Change::Update {
+                        before: Box::new(before),
+                        after: Box::new(after),
+                    },
+                    None => Change::Insert(Box::new(after)),
+                });
+            }
+            app.close_form();
+            refresh(app, db).await;
+            app.select_id(id);
+        }
+        Err(err) => app.status_message = Some(format!("Can't save: {}", err)),
+    }
+}
+
+/// Deletes the expense, remembering it so the delete can be undone.
+async fn delete_expense(app: &mut App, db: &Database, expense: &Expense) {
+    let Some(id) = expense.id else {
+        return;
+    };
+    let result = match db.get_expense(id).await {
+        Ok(stored) => db.delete_expense(id).await.map(|()| stored),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(stored) => {
+            if let Some(stored) = stored {
+                app.history.record(Change::Delete(Box::new(stored)));
+            }
+            app.status_message = Some(format!(
+                "Moved '{}' to the trash, '{}' to undo",
+                expense.name,
+                app.keymap.help_for(Mode::List, Action::Undo)
+            ));
+        }
+        Err(err) => app.status_message = Some(format!("Can't delete: {}", err)),
+    }
+    refresh(app, db).await;
+}
+
+/// Bulk action keys: up and down pick an action, submitting runs it or asks
+/// for what it needs first.
+async fn handle_bulk_menu(app: &mut App, db: &Database, key: KeyEvent) {
+    let Some(menu) = app.bulk_menu.as_mut() else {
+        return;
+    };
+    let form_action = app.keymap.action(Mode::Form, &key);
+    if let Some(input) = menu.input.as_mut() {
+        match key.code {
+            _ if form_action == Some(Action::Cancel) => menu.input = None,
+            _ if form_action == Some(Action::Submit) => {
+                let input = input.clone();
+                let action = menu.action();
+                app.bulk_menu = None;
+                run_bulk_action(app, db, action, &input).await;
+            }
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            _ => {}
+        }
+        return;
+    }
+    match app.keymap.action(Mode::List, &key) {
+        Some(Action::SelectPrevious) => menu.selected = menu.selected.saturating_sub(1),
+        Some(Action::SelectNext) => {
+            menu.selected = (menu.selected + 1).min(BulkAction::ALL.len() - 1)
+        }
+        _ if form_action == Some(Action::Cancel) => app.bulk_menu = None,
+        _ if form_action == Some(Action::Submit) => {
+            let action = menu.action();
+            if action.prompt().is_some() {
+                menu.input = Some(action.default_input().to_string());
+            } else {
+                app.bulk_menu = None;
+                run_bulk_action(app, db, action, "").await;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Runs a bulk action on the marked expenses. Changes to the ledger are made
+/// in one transaction and undone as one step.
+async fn run_bulk_action(app: &mut App, db: &Database, action: BulkAction, input: &str) {
+    let expenses = app.marked_expenses();
+    if action == BulkAction::Export {
+        let mut csv = Vec::new();
+        let result = output::write_records(&mut csv, OutputFormat::Csv, &expenses)
+            .map_err(|err| err.into())
+            .and_then(|()| report::write_report(input.trim(), &csv, app.encryption_key.as_ref()));
+        app.status_message = Some(match result {
+            Ok(written) => format!("Exported {} expenses to {}", expenses.len(), written),
+            Err(err) => format!("Export failed: {}", err),
+        });
+        return;
+    }
+    let change = match bulk::plan(action, &expenses, input) {
+        Ok(change) => change,
+        Err(message) => {
+            app.status_message = Some(message);
+            return;
+        }
+    };
+    if matches!(&change, Change::Bulk { changes, .. } if changes.is_empty()) {
+        app.status_message = Some("Nothing to change".to_string());
+        return;
+    }
+    let description = change.describe();
+    app.status_message = Some(match app.history.apply(db, change).await {
+        Ok(()) => format!(
+            "Done {}, '{}' to undo",
+            description,
+            app.keymap.help_for(Mode::List, Action::Undo)
+        ),
+        Err(err) => format!("Can't finish {}: {}", description, err),
+    });
+    refresh(app, db).await;
+}
+
+/// Opens the recorded changes to an expense.
+async fn show_expense_history(app: &mut App, db: &Database, expense: Expense) {
+    match db.expense_history(&expense.uid).await {
+        Ok(entries) if entries.is_empty() => {
+            app.status_message = Some(format!("No changes recorded for '{}'", expense.name))
+        }
+        Ok(entries) => app.expense_history = Some(ExpenseHistory::new(expense, entries)),
+        Err(err) => app.status_message = Some(format!("Can't load the history: {}", err)),
+    }
+}
+
+/// History keys: up and down pick a change, submitting reverts the expense
+/// to how that change left it.
+async fn handle_expense_history(app: &mut App, db: &Database, key: KeyEvent) {
+    let Some(history) = app.expense_history.as_mut() else {
+        return;
+    };
+    match app.keymap.action(Mode::List, &key) {
+        Some(Action::SelectPrevious) => history.selected = history.selected.saturating_sub(1),
+        Some(Action::SelectNext) => {
+            history.selected = (history.selected + 1).min(history.entries.len().saturating_sub(1))
+        }
+        _ => match app.keymap.action(Mode::Form, &key) {
+            Some(Action::Cancel) => app.expense_history = None,
+            Some(Action::Submit) => {
+                let Some(history) = app.expense_history.take() else {
+                    return;
+                };
+                let Some(reverted) = history.reverted() else {
+                    app.status_message = Some("It already looks like that".to_string());
+                    return;
+                };
+                let put = ExpenseChange::Put(Box::new(reverted.clone()));
+                app.status_message = Some(match db.apply_changes(&[put]).await {
+                    Ok(()) => {
+                        let name = reverted.name.clone();
+                        app.history.record(Change::Update {
+                            before: Box::new(history.expense),
+                            after: Box::new(reverted),
+                        });
+                        format!("Reverted '{}'", name)
+                    }
+                    Err(err) => format!("Can't revert: {}", err),
+                });
+                refresh(app, db).await;
+            }
+            _ => {}
+        },
+    }
+}
+
+/// Takes an expense back out of the trash.
+async fn restore_expense(app: &mut App, db: &Database, expense: &Expense) {
+    let Some(id) = expense.id else {
+        return;
+    };
+    match db.restore_expense(id).await {
+        Ok(()) => {
+            app.history
+                .record(Change::Restore(Box::new(expense.clone())));
+            app.status_message = Some(format!("Restored '{}'", expense.name));
+        }
+        Err(err) => app.status_message = Some(format!("Can't restore: {}", err)),
+    }
+    refresh(app, db).await;
+}
+
+/// Deletes an expense in the trash for good. This can't be undone.
+async fn purge_expense(app: &mut App, db: &Database, expense: &Expense) {
+    let Some(id) = expense.id else {
+        return;
+    };
+    app.status_message = Some(match db.purge_expense(id).await {
+        Ok(()) => {
+            app.history.forget(&expense.uid);
+            format!("Deleted '{}' for good", expense.name)
+        }
+        Err(err) => format!("Can't delete: {}", err),
+    });
+    refresh(app, db).await;
+}
+
+/// Purges whatever has been in the trash longer than the configured
+/// retention.
+async fn purge_old_trash(app: &mut App, db: &Database) {
+    let days = app.config.behavior.trash_retention_days;
+    if days == 0 {
+        return;
+    }
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(i64::from(days)))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    match db.purge_trash(&cutoff).await {
+        Ok(0) => {}
+        Ok(purged) => {
+            app.status_message = Some(format!(
+                "Emptied {} expense(s) older than {} days from the trash",
+                purged, days
+            ))
+        }
+        Err(err) => app.status_message = Some(format!("Can't empty the trash: {}", err)),
+    }
+}
+
+/// The delete confirmation: 'y' or submitting deletes, 'n' or cancelling
+/// keeps the expense.
+async fn handle_delete_confirmation(app: &mut App, db: &Database, key: KeyEvent) {
+    let action = app.keymap.action(Mode::Form, &key);
+    let confirmed = match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') => true,
+        KeyCode::Char('n') | KeyCode::Char('N') => false,
+        _ if action == Some(Action::Submit) => true,
+        _ if action == Some(Action::Cancel) => false,
+        _ => return,
+    };
+    let Some(pending) = app.pending_delete.take() else {
+        return;
+    };
+    if !confirmed {
+        return;
+    }
+    if pending.purge {
+        purge_expense(app, db, &pending.expense).await;
+    } else {
+        delete_expense(app, db, &pending.expense).await;
+    }
+}
+
+/// Undoes the last change, or redoes the last undone one, and says which.
+async fn undo(app: &mut App, db: &Database, redo: bool) {
+    let result = if redo {
+        app.history.redo(db).await
+    } else {
+        app.history.undo(db).await
+    };
+    let verb = if redo { "redo" } else { "undo" };
+    app.status_message = Some(match result {
+        Ok(Some(description)) => {
+            refresh(app, db).await;
+            let done = if redo { "Redid" } else { "Undid" };
+            format!("{} {}", done, description)
+        }
+        Ok(None) => format!("Nothing to {}", verb),
+        Err(err) => format!("Can't {}: {}", verb, err),
+    });
+}
+
+/// Writes one of the reports offered on the Reports screen.
+async fn run_report(app: &mut App, db: &Database, report: Action) {
+    let today = chrono::Local::now().date_naive();
+    let key = app.encryption_key.as_ref();
+    app.status_message = Some(match report {
+        Action::ExportXlsx => match report::xlsx::export_workbook(db, "expenses.xlsx", key).await {
+            Ok(written) => format!("Exported {}", written),
+            Err(err) => format!("Export failed: {}", err),
+        },
+        Action::HtmlReport => {
+            let filename = format!("report-{}.html", today.format("%Y-%m"));
+            match report::html::export_monthly_report(
+                db,
+                today.year(),
+                today.month(),
+                &filename,
+                key,
+            )
+            .await
+            {
+                Ok(written) => format!("Wrote {}", written),
+                Err(err) => format!("Report failed: {}", err),
+            }
+        }
+        Action::MarkdownReport => {
+            let filename = format!("report-{}.md", today.format("%Y-%m"));
+            match export_month_markdown(db, today, &filename, key).await {
+                Ok(written) => format!("Wrote {}", written),
+                Err(err) => format!("Report failed: {}", err),
+            }
+        }
+        _ => return,
+    });
+}
+
+/// Switches to `screen`, loading its data the first time it's shown. After
+/// that `refresh` keeps it current.
+async fn show_screen(app: &mut App, db: &Database, screen: Screen) {
+    app.screen = screen;
+    let today = chrono::Local::now().date_naive();
+    match screen {
+        Screen::Dashboard if app.dashboard.is_none() => {
+            show_dashboard(app, db, today.year(), today.month()).await;
+        }
+        Screen::Calendar if app.calendar.is_none() => {
+            app.calendar = Some(Calendar::new(today, Vec::new()));
+            load_calendar_month(app, db).await;
+        }
+        _ => {}
+    }
+}
+
+/// Leaves a screen for wherever it was drilled into from, or for the
+/// transactions.
+fn leave_screen(app: &mut App) {
+    if !app.go_back() {
+        app.screen = Screen::Transactions;
+    }
+}
+
+/// Keys for the screens other than the transactions.
+async fn handle_screen(app: &mut App, db: &Database, key: KeyEvent) {
+    match app.screen {
+        Screen::Transactions => {}
+        Screen::Dashboard => handle_dashboard(app, db, key).await,
+        Screen::Calendar => handle_calendar(app, db, key).await,
+        Screen::Budgets | Screen::Categories | Screen::Recurring | Screen::Reports => {
+            handle_list_screen(app, db, key).await
+        }
+        Screen::Trash => handle_trash(app, db, key).await,
+        Screen::Settings => match app.keymap.action(Mode::Form, &key) {
+            Some(Action::Cancel) => leave_screen(app),
+            Some(Action::Submit) if app.ledger.is_some() => {
+                app.settings_form = Some(SettingsForm::new(&app.ledger_settings));
+            }
+            _ => {}
+        },
+    }
+}
+
+/// Trash keys: up and down select, submitting or restore takes the expense
+/// back out, purge deletes it for good.
+async fn handle_trash(app: &mut App, db: &Database, key: KeyEvent) {
+    if app.keymap.action(Mode::Form, &key) == Some(Action::Cancel) {
+        leave_screen(app);
+        return;
+    }
+    let len = app.trash.len();
+    match app.keymap.action(Mode::List, &key) {
+        Some(Action::SelectPrevious) => {
+            app.move_screen_row(false, len);
+            return;
+        }
+        Some(Action::SelectNext) => {
+            app.move_screen_row(true, len);
+            return;
+        }
+        _ => {}
+    }
+    let Some(expense) = app.trash.get(app.screen_row(Screen::Trash)).cloned() else {
+        return;
+    };
+    match app.keymap.action(Mode::Trash, &key) {
+        Some(Action::Restore) => restore_expense(app, db, &expense).await,
+        Some(Action::Purge) => {
+            if app.config.behavior.confirm_delete {
+                app.pending_delete = Some(PendingDelete {
+                    expense,
+                    purge: true,
+                });
+            } else {
+                purge_expense(app, db, &expense).await;
+            }
+        }
+        _ if app.keymap.action(Mode::Form, &key) == Some(Action::Submit) => {
+            restore_expense(app, db, &expense).await
+        }
+        _ => {}
+    }
+}
+
+/// The list screens: up and down select a row, submitting drills into the
+/// row's transactions or writes the selected report.
+async fn handle_list_screen(app: &mut App, db: &Database, key: KeyEvent) {
+    let this_month = app
+        .summary
+        .as_ref()
+        .map(|summary| (summary.start, summary.end));
+    let drill_downs: Vec<ExpenseFilter> = match app.screen {
+        Screen::Budgets => app
+            .summary
+            .iter()
+            .flat_map(|summary| &summary.budgets)
+            .map(|budget| ExpenseFilter {
+                categories: vec![budget.category.clone()],
+                start: this_month.map(|(start, _)| start),
+                end: this_month.map(|(_, end)| end),
+                ..ExpenseFilter::default()
+            })
+            .collect(),
+        Screen::Categories => app
+            .category_totals()
+            .into_iter()
+            .map(|total| ExpenseFilter {
+                categories: vec![total.category],
+                ..ExpenseFilter::default()
+            })
+            .collect(),
+        Screen::Recurring => find_recurring(&app.all_expenses)
+            .into_iter()
+            .map(|recurring| ExpenseFilter {
+                text: Some(recurring.name),
+                categories: vec![recurring.category],
+                ..ExpenseFilter::default()
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let len = if app.screen == Screen::Reports {
+        REPORTS.len()
+    } else {
+        drill_downs.len()
+    };
+    if app.keymap.action(Mode::Form, &key) == Some(Action::Cancel) {
+        leave_screen(app);
+        return;
+    }
+    match app.keymap.action(Mode::List, &key) {
+        Some(Action::SelectPrevious) => app.move_screen_row(false, len),
+        Some(Action::SelectNext) => app.move_screen_row(true, len),
+        _ if app.keymap.action(Mode::Form, &key) == Some(Action::Submit) => {
+            let row = app.screen_row(app.screen);
+            if app.screen == Screen::Reports {
+                run_report(app, db, REPORTS[row.min(len - 1)]).await;
+            } else if let Some(filter) = drill_downs.into_iter().nth(row) {
+                app.drill_down(filter);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Opens the dashboard on the given month, or moves it there when it's
+/// already open.
+async fn show_dashboard(app: &mut App, db: &Database, year: i32, month: u32) {
+    let Some(month) = Month::new(year, month) else {
+        app.status_message = Some(format!("There's no month {}", month_key(year, month)));
+        return;
+    };
+    match DashboardData::load(db, month).await {
+        Ok(data) => app.dashboard = Some(data),
+        Err(err) => app.status_message = Some(format!("Can't load the dashboard: {}", err)),
+    }
+}
+
+/// Dashboard keys: the month actions step through the months, cancel leaves
+/// the dashboard.
+async fn handle_dashboard(app: &mut App, db: &Database, key: KeyEvent) {
+    let Some(data) = app.dashboard.as_ref() else {
+        return;
+    };
+    let (year, month) = (data.summary.year, data.summary.month);
+    match app.keymap.action(Mode::Dashboard, &key) {
+        Some(Action::PreviousMonth) => {
+            let (year, month) = previous_month(year, month);
+            show_dashboard(app, db, year, month).await;
+        }
+        Some(Action::NextMonth) => {
+            let (year, month) = next_month(year, month);
+            show_dashboard(app, db, year, month).await;
+        }
+        _ => {
+            if app.keymap.action(Mode::Form, &key) == Some(Action::Cancel) {
+                leave_screen(app);
+            }
+        }
+    }
+}
+
+/// Loads the daily totals for the calendar's month.
+async fn load_calendar_month(app: &mut App, db: &Database) {
+    let Some(calendar) = app.calendar.as_mut() else {
+        return;
+    };
+    let Some(month) = Month::new(calendar.year(), calendar.month()) else {
+        return;
+    };
+    match summary::daily_spending(db, month).await {
+        Ok(daily) => calendar.daily = daily,
+        Err(err) => app.status_message = Some(format!("Can't load the calendar: {}", err)),
+    }
+}
+
+/// Calendar keys: the day and week actions move the selection, the month
+/// actions move between months, submitting lists the selected day's expenses.
+async fn handle_calendar(app: &mut App, db: &Database, key: KeyEvent) {
+    let Some(calendar) = app.calendar.as_mut() else {
+        return;
+    };
+    let month_changed = match app.keymap.action(Mode::Calendar, &key) {
+        Some(Action::PreviousDay) => calendar.move_by(-1),
+        Some(Action::NextDay) => calendar.move_by(1),
+        Some(Action::PreviousWeek) => calendar.move_by(-7),
+        Some(Action::NextWeek) => calendar.move_by(7),
+        Some(Action::PreviousMonth) => {
+            calendar.move_month(false);
+            true
+        }
+        Some(Action::NextMonth) => {
+            calendar.move_month(true);
+            true
+        }
+        _ => {
+            let selected = calendar.selected;
+            match app.keymap.action(Mode::Form, &key) {
+                Some(Action::Submit) => app.show_day(selected),
+                Some(Action::Cancel) => leave_screen(app),
+                _ => {}
+            }
+            false
+        }
+    };
+    if month_changed {
+        load_calendar_month(app, db).await;
+    }
+}
+
+/// Search bar keys: typing filters the list straight away, submitting keeps
+/// the search and cancelling drops it.
+fn handle_search(app: &mut App, key: KeyEvent) {
+    match app.keymap.action(Mode::Form, &key) {
+        Some(Action::Submit) => app.search = None,
+        Some(Action::Cancel) => {
+            app.filter.text = None;
+            app.search = None;
+            app.apply_filter();
+        }
+        Some(_) => {}
+        None => {
+            if let Some(search) = app.search.as_mut() {
+                if search.handle_key(key) {
+                    app.update_search();
+                }
+            }
+        }
+    }
+}
+
+fn handle_filter_panel(app: &mut App, key: KeyEvent) {
+    let Some(panel) = app.filter_panel.as_mut() else {
+        return;
+    };
+    match app.keymap.action(Mode::Form, &key) {
+        Some(Action::Submit) => match panel.apply_to(&app.filter, &app.config) {
+            Ok(filter) => {
+                app.filter = filter;
+                app.filter_panel = None;
+                app.apply_filter();
+            }
+            Err(message) => app.status_message = Some(message),
+        },
+        Some(Action::Cancel) => app.filter_panel = None,
+        Some(Action::NextField) => panel.field = panel.field.next(),
+        Some(Action::PreviousField) => panel.field = panel.field.previous(),
+        Some(_) => {}
+        None => {
+            panel.field_mut(panel.field).handle_key(key);
+        }
+    }
+}
+
+/// A left click on a column header sorts by that column, or reverses the
+/// order when it already is the sort column.
+fn handle_mouse(app: &mut App, mouse: &MouseEvent) {
+    if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
+        return;
+    }
+    let Some(header) = app.table_header.get() else {
+        return;
+    };
+    let position = Position::new(mouse.column, mouse.row);
+    if !header.contains(position) {
+        return;
+    }
+    let columns = &app.config.display.columns;
+    let widths = table::column_widths(columns, &app.config.display.column_widths);
+    if let Some(column) = table::column_at(columns, &widths, header.x, mouse.column) {
+        let sort = app.sort.toggled(column);
+        app.set_sort(sort);
+    }
+}
+
+/// Bracketed paste goes into whichever text field is open.
+fn handle_paste(app: &mut App, text: &str) {
+    app.status_message = None;
+    if let Some(search) = app.search.as_mut() {
+        search.insert_str(text);
+        app.update_search();
+    } else if let Some(panel) = app.filter_panel.as_mut() {
+        panel.field_mut(panel.field).insert_str(text);
+    } else if app.adding_expense {
+        app.form.field_mut(app.input_mode).insert_str(text);
+    } else if let Some(path) = app.import_path.as_mut().or(app.sync_path.as_mut()) {
+        path.push_str(text.trim());
+    }
+}
+
+/// Reloads the expense list and the current month's summary shown above it,
+/// and saves straight away when autosave is on.
+async fn refresh(app: &mut App, db: &Database) {
+    app.set_expenses(db.list_expenses().await.unwrap());
+    app.trash = db.list_trash().await.unwrap_or_default();
+    let last = app.trash.len().saturating_sub(1);
+    if app.screen_row(Screen::Trash) > last {
+        app.screen_rows.insert(Screen::Trash, last);
+    }
+    let today = chrono::Local::now().date_naive();
+    app.summary = match Month::new(today.year(), today.month()) {
+        Some(month) => PeriodSummary::for_month(db, month).await.ok(),
+        None => None,
+    };
+    load_calendar_month(app, db).await;
+    if let Some(data) = &app.dashboard {
+        let (year, month) = (data.summary.year, data.summary.month);
+        show_dashboard(app, db, year, month).await;
+    }
+    if app.config.behavior.autosave {
+        if let Err(err) = save_ledger(app, db).await {
+            app.status_message = Some(format!("Autosave failed: {}", err));
+        }
+    }
+}
+
+async fn export_month_markdown(
+    db: &Database,
+    today: NaiveDate,
+    filename: &str,
+    key: Option<&Key>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (start, end) = month_bounds(today.year(), today.month()).ok_or("invalid month")?;
+    report::text::export_period_report(db, start, end, TextStyle::Markdown, filename, key).await
+}
+
+async fn handle_import_prompt(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(path) = app.import_path.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => path.push(c),
+        KeyCode::Backspace => {
+            path.pop();
+        }
+        KeyCode::Esc => app.import_path = None,
+        KeyCode::Enter => {
+            let path = app.import_path.take().unwrap_or_default();
+            match preview_import_file(db, &path, &DuplicateOptions::default()).await {
+                Ok(preview) if preview.matches.is_empty() => {
+                    app.status_message = Some(match commit_import(db, &preview, &[]).await {
+                        Ok(inserted) => format!("Imported {} expenses", inserted),
+                        Err(err) => format!("Import failed: {}", err),
+                    });
+                    refresh(app, db).await;
+                }
+                Ok(preview) => {
+                    app.duplicate_review = Some(DuplicateReview::new(ReviewKind::Import, preview));
+                }
+                Err(err) => app.status_message = Some(format!("Import failed: {}", err)),
+            }
+        }
+        _ => {}
+    }
+}
+
+async fn handle_sync_prompt(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(path) = app.sync_path.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => path.push(c),
+        KeyCode::Backspace => {
+            path.pop();
+        }
+        KeyCode::Esc => app.sync_path = None,
+        KeyCode::Enter => {
+            let path = app.sync_path.take().unwrap_or_default();
+            if app.encryption_key.is_some() {
+                app.sync_passphrase = Some(SyncPassphrase {
+                    other_file: path,
+                    input: String::new(),
+                });
+            } else {
+                start_sync(app, db, path, None).await;
+            }
+        }
+        _ => {}
+    }
+}
+
+async fn handle_sync_passphrase(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(prompt) = app.sync_passphrase.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => prompt.input.push(c),
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Esc => app.sync_passphrase = None,
+        KeyCode::Enter => {
+            let Some(prompt) = app.sync_passphrase.take() else {
+                return;
+            };
+            start_sync(app, db, prompt.other_file, Some(&prompt.input)).await;
+        }
+        _ => {}
+    }
+}
+
+/// Merges with the other copy, straight away when nothing conflicts and
+/// through the review otherwise.
+async fn start_sync(app: &mut App, db: &Database, path: String, passphrase: Option<&str>) {
+    let Some(ledger) = &app.ledger else {
+        return;
+    };
+    let base_file = ledger.sync_base_file(&path);
+    match sync::prepare(db, &base_file, &path, passphrase).await {
+        Ok(outcome) if outcome.conflicts.is_empty() => {
+            finish_sync(app, db, &outcome, &[], &path).await;
+        }
+        Ok(outcome) => app.merge_review = Some(MergeReview::new(outcome, path)),
+        Err(err) => app.status_message = Some(format!("Sync failed: {}", err)),
+    }
+}
+
+async fn handle_merge_review(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(review) = app.merge_review.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Up => review.selected = review.selected.saturating_sub(1),
+        KeyCode::Down if review.selected + 1 < review.resolutions.len() => review.selected += 1,
+        KeyCode::Char('o') => review.set_selected(Resolution::Ours),
+        KeyCode::Char('t') => review.set_selected(Resolution::Theirs),
+        KeyCode::Esc => {
+            app.merge_review = None;
+            app.status_message = Some("Sync cancelled, nothing changed".to_string());
+        }
+        KeyCode::Enter => {
+            let Some(review) = app.merge_review.take() else {
+                return;
+            };
+            finish_sync(
+                app,
+                db,
+                &review.outcome,
+                &review.resolutions,
+                &review.other_file,
+            )
+            .await;
+        }
+        _ => {}
+    }
+}
+
+/// Stores the merge result and writes it to the other copy and the open
+/// ledger's sync base.
+async fn finish_sync(
+    app: &mut App,
+    db: &Database,
+    outcome: &MergeOutcome,
+    resolutions: &[Resolution],
+    other_file: &str,
+) {
+    let Some(ledger) = &app.ledger else {
+        return;
+    };
+    let key = app.encryption_key.as_ref();
+    let base_file = ledger.sync_base_file(other_file);
+    app.status_message = Some(
+        match sync::apply(db, outcome, resolutions, &base_file, other_file, key).await {
+            Ok(()) => {
+                // Undoing older changes would overwrite what the merge brought in
+                app.history.clear();
+                let synced = outcome.resolve(resolutions).len();
+                format!("Synced {} expenses with {}", synced, other_file)
+            }
+            Err(err) => format!("Sync failed: {}", err),
+        },
+    );
+    app.selected_index = None;
+    refresh(app, db).await;
+}
+
+async fn handle_duplicate_review(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(review) = app.duplicate_review.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Up => review.selected = review.selected.saturating_sub(1),
+        KeyCode::Down if review.selected + 1 < review.actions.len() => review.selected += 1,
+        KeyCode::Char(' ') => review.toggle_selected(),
+        KeyCode::Esc => app.duplicate_review = None,
+        KeyCode::Enter => {
+            let Some(review) = app.duplicate_review.take() else {
+                return;
+            };
+            app.status_message = Some(match review.kind {
+                ReviewKind::Import => {
+                    match commit_import(db, &review.preview, &review.actions).await {
+                        Ok(inserted) => {
+                            // Undoing an edit would put back the row a merge updated
+                            app.history.clear();
+                            format!("Imported {} expenses", inserted)
+                        }
+                        Err(err) => format!("Import failed: {}", err),
+                    }
+                }
+                ReviewKind::Existing => {
+                    match resolve_duplicates(db, &review.preview.matches, &review.actions).await {
+                        Ok(removed) => {
+                            app.history.clear();
+                            format!("Merged {} duplicates", removed)
+                        }
+                        Err(err) => format!("Can't merge the duplicates: {}", err),
+                    }
+                }
+            });
+            refresh(app, db).await;
+        }
+        _ => {}
+    }
+}
+
+async fn handle_reconcile_setup(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(setup) = app.reconcile_setup.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => setup.field_mut().push(c),
+        KeyCode::Backspace => {
+            setup.field_mut().pop();
+        }
+        KeyCode::Tab => setup.next_field(),
+        KeyCode::Esc => app.reconcile_setup = None,
+        KeyCode::Enter => {
+            let Ok(statement_date) = NaiveDate::parse_from_str(&setup.statement_date, "%Y-%m-%d")
+            else {
+                app.status_message = Some("Statement date must be YYYY-MM-DD".to_string());
+                return;
+            };
+            let Ok(closing_balance) = setup.closing_balance.trim().parse::<f64>() else {
+                app.status_message = Some("Closing balance must be a number".to_string());
+                return;
+            };
+            let account = setup.account.trim().to_string();
+            match Reconciliation::start(db, &account, statement_date, closing_balance).await {
+                Ok(reconciliation) => {
+                    app.reconcile_setup = None;
+                    app.reconciliation = Some(reconciliation);
+                }
+                Err(err) => app.status_message = Some(format!("Can't start reconciling: {}", err)),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Edits the open ledger's description and currency, saved to its settings
+/// file on enter.
+fn handle_settings_form(app: &mut App, code: KeyCode) {
+    let Some(form) = app.settings_form.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => form.field_mut().push(c),
+        KeyCode::Backspace => {
+            form.field_mut().pop();
+        }
+        KeyCode::Tab => form.next_field(),
+        KeyCode::Esc => app.settings_form = None,
+        KeyCode::Enter => {
+            let Some(ledger) = &app.ledger else {
+                app.settings_form = None;
+                return;
+            };
+            let settings = form.settings();
+            match ledger.save_settings(&settings) {
+                Ok(()) => {
+                    app.status_message = Some(format!("Saved the settings of '{}'", ledger.name));
+                    app.ledger_settings = settings;
+                    app.settings_form = None;
+                }
+                Err(err) => app.status_message = Some(format!("Can't save the settings: {}", err)),
+            }
+        }
+        _ => {}
+    }
+}
+
+async fn handle_reconciliation(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(reconciliation) = app.reconciliation.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Up => reconciliation.selected = reconciliation.selected.saturating_sub(1),
+        KeyCode::Down if reconciliation.selected + 1 < reconciliation.entries.len() => {
+            reconciliation.selected += 1
+        }
+        KeyCode::Char(' ') => {
+            if let Err(err) = reconciliation.toggle_selected(db).await {
+                app.status_message = Some(format!("Can't change the status: {}", err));
+            }
+        }
+        KeyCode::Esc => {
+            app.reconciliation = None;
+            // Undoing an edit would put back the status from before the ticks
+            app.history.clear();
+            app.status_message =
+                Some("Reconciliation paused, ticked entries stay cleared".to_string());
+            refresh(app, db).await;
+        }
+        KeyCode::Enter => match reconciliation.finish(db).await {
+            Ok(count) => {
+                app.reconciliation = None;
+                app.history.clear();
+                app.status_message = Some(format!("Reconciled {} expenses", count));
+                refresh(app, db).await;
+            }
+            Err(err) => app.status_message = Some(format!("Can't finish: {}", err)),
+        },
+        _ => {}
+    }
+}
+
+async fn handle_unlock_prompt(app: &mut App, db: &Database, store: &LedgerStore, code: KeyCode) {
+    let (Some(input), Some(ledger)) = (app.unlock_prompt.as_mut(), app.ledger.as_ref()) else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        KeyCode::Esc => {
+            //INFO: Back to the picker without saving so the locked file stays untouched
+            app.unlock_prompt = None;
+            app.ledger = None;
+            app.ledger_picker = Some(LedgerPicker::new(store.list().unwrap_or_default()));
+        }
+        KeyCode::Enter => match db.load_encrypted_file(&ledger.data_file(), input).await {
+            Ok(key) => {
+                app.unlock_prompt = None;
+                app.encryption_key = Some(key);
+                purge_old_trash(app, db).await;
+                refresh(app, db).await;
+            }
+            Err(err) => {
+                input.clear();
+                app.status_message = Some(format!("Can't unlock: {}", err));
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Returns `false` when the user quits from the picker.
+async fn handle_ledger_picker(
+    app: &mut App,
+    db: &mut Database,
+    store: &LedgerStore,
+    code: KeyCode,
+) -> bool {
+    let Some(picker) = app.ledger_picker.as_mut() else {
+        return true;
+    };
+
+    if let Some(name) = picker.new_name.as_mut() {
+        match code {
+            KeyCode::Char(c) => name.push(c),
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Esc => picker.new_name = None,
+            KeyCode::Enter => match store.create(name.trim()) {
+                Ok(ledger) => switch_ledger(app, db, ledger).await,
+                Err(err) => app.status_message = Some(format!("Can't create ledger: {}", err)),
+            },
+            _ => {}
+        }
+        return true;
+    }
+
+    match code {
+        KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+        KeyCode::Down if picker.selected + 1 < picker.ledgers.len() => picker.selected += 1,
+        KeyCode::Char('n') => picker.new_name = Some(String::new()),
+        KeyCode::Enter => {
+            if let Some(ledger) = picker.ledgers.get(picker.selected).cloned() {
+                switch_ledger(app, db, ledger).await;
+            }
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            //INFO: Without an open ledger there's nothing to go back to
+            if app.ledger.is_none() {
+                return false;
+            }
+            app.ledger_picker = None;
+        }
+        _ => {}
+    }
+    true
+}
+
+/// Saves the open ledger and opens another one in its place.
+async fn switch_ledger(app: &mut App, db: &mut Database, ledger: Ledger) {
+    if let Err(err) = save_ledger(app, db).await {
+        app.status_message = Some(format!("Save failed, staying on this ledger: {}", err));
+        return;
+    }
+    match open_ledger(app, db, ledger).await {
+        Ok(()) => app.ledger_picker = None,
+        Err(err) => {
+            app.ledger = None;
+            app.status_message = Some(format!("Can't open ledger: {}", err));
+        }
+    }
+}
+
+/// Loads a ledger into a fresh database. Encrypted ledgers are loaded once
+/// the passphrase has been entered.
+async fn open_ledger(
+    app: &mut App,
+    db: &mut Database,
+    ledger: Ledger,
+) -> Result<(), Box<dyn std::error::Error>> {
+    *db = Database::new().await?;
+    app.history.clear();
+    app.encryption_key = None;
+    app.selected_index = None;
+    app.marked.clear();
+    app.bulk_menu = None;
+    app.nav_stack.clear();
+    app.clear_filter();
+    app.ledger_settings = ledger.load_settings()?;
+    if ledger.is_encrypted() {
+        app.unlock_prompt = Some(String::new());
+    } else {
+        db.load_expenses_from_file(&ledger.data_file()).await?;
+        purge_old_trash(app, db).await;
+    }
+    app.ledger = Some(ledger);
+    refresh(app, db).await;
+    Ok(())
+}
+
+/// Writes the open ledger back to disk. Does nothing while no ledger is open
+/// or an encrypted one is still locked, so its file is never overwritten.
+async fn save_ledger(app: &App, db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    match &app.ledger {
+        Some(ledger) if app.unlock_prompt.is_none() => {
+            db.save_to_file(&ledger.data_file(), app.encryption_key.as_ref())
+                .await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn handle_passphrase_change(app: &mut App, db: &Database, code: KeyCode) {
+    let Some(change) = app.passphrase_change.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => change.input.push(c),
+        KeyCode::Backspace => {
+            change.input.pop();
+        }
+        KeyCode::Esc => app.passphrase_change = None,
+        KeyCode::Enter => {
+            let Some(first) = change.first.take() else {
+                change.first = Some(std::mem::take(&mut change.input));
+                return;
+            };
+            let confirmation = std::mem::take(&mut change.input);
+            app.passphrase_change = None;
+            if first != confirmation {
+                app.status_message = Some("Passphrases didn't match, nothing changed".to_string());
+                return;
+            }
+
+            let key = if first.is_empty() {
+                None
+            } else {
+                match Key::new(&first) {
+                    Ok(key) => Some(key),
+                    Err(err) => {
+                        app.status_message = Some(format!("Can't change passphrase: {}", err));
+                        return;
+                    }
+                }
+            };
+            let Some(ledger) = &app.ledger else {
+                return;
+            };
+            //INFO: Rewrite the file straight away so it never lags behind the key
+            match db.save_to_file(&ledger.data_file(), key.as_ref()).await {
+                Ok(()) => {
+                    app.status_message = Some(match key {
+                        Some(_) => "Passphrase changed, data re-encrypted".to_string(),
+                        None => "Encryption disabled".to_string(),
+                    });
+                    app.encryption_key = key;
+                }
+                Err(err) => app.status_message = Some(format!("Save failed: {}", err)),
+            }
+        }
+        _ => {}
+    }
+}
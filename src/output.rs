@@ -0,0 +1,290 @@
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+use crate::budget::{Budget, BudgetStatus};
+use crate::expense::Expense;
+use crate::summary::CategoryTotal;
+
+/// How read commands print their results. Everything but `table` is meant
+/// for other programs and uses the serde field names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    Csv,
+    Tsv,
+}
+
+/// A row type that can be written in every output format. `FIELDS` lists the
+/// serialized field names in column order, so the CSV header is the same
+/// even when there are no rows.
+pub trait Record: Serialize {
+    const FIELDS: &'static [&'static str];
+}
+
+impl Record for Expense {
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "date",
+        "name",
+        "category",
+        "amount",
+        "external_id",
+        "account",
+        "notes",
+        "tags",
+        "status",
+        "uid",
+        "modified_at",
+        "deleted_at",
+    ];
+}
+
+impl Record for CategoryTotal {
+    const FIELDS: &'static [&'static str] = &["category", "total", "count"];
+}
+
+impl Record for Budget {
+    const FIELDS: &'static [&'static str] = &["category", "amount"];
+}
+
+impl Record for BudgetStatus {
+    const FIELDS: &'static [&'static str] = &["category", "limit", "spent"];
+}
+
+/// Writes rows in the given format. `Table` gives plain aligned columns; the
+/// commands that have a nicer table of their own handle it themselves.
+pub fn write_records<T: Record>(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    records: &[T],
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)
+        }
+        OutputFormat::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => write_delimited(out, records, ',', csv_field),
+        OutputFormat::Tsv => write_delimited(out, records, '\t', tsv_field),
+        OutputFormat::Table => write_table(out, records),
+    }
+}
+
+fn cells<T: Record>(record: &T, number: fn(&Number) -> String) -> io::Result<Vec<String>> {
+    let value = serde_json::to_value(record)?;
+    Ok(T::FIELDS
+        .iter()
+        .map(|field| match value.get(field) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Number(n)) => number(n),
+            Some(other) => other.to_string(),
+        })
+        .collect())
+}
+
+/// Amounts in the table get two decimals; ids and counts stay whole.
+fn table_number(number: &Number) -> String {
+    match number.as_f64() {
+        Some(value) if number.is_f64() => format!("{:.2}", value),
+        _ => number.to_string(),
+    }
+}
+
+fn write_delimited<T: Record>(
+    out: &mut dyn Write,
+    records: &[T],
+    separator: char,
+    escape: fn(&str) -> String,
+) -> io::Result<()> {
+    let line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .map(|cell| escape(cell))
+            .collect::<Vec<_>>()
+            .join(&separator.to_string())
+    };
+    writeln!(
+        out,
+        "{}",
+        line(T::FIELDS.iter().map(|f| f.to_string()).collect())
+    )?;
+    for record in records {
+        writeln!(out, "{}", line(cells(record, Number::to_string)?))?;
+    }
+    Ok(())
+}
+
+/// RFC 4180 quoting, only where needed.
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// TSV has no quoting, so separators inside a value become spaces.
+fn tsv_field(cell: &str) -> String {
+    cell.replace(['\t', '\n', '\r'], " ")
+}
+
+fn write_table<T: Record>(out: &mut dyn Write, records: &[T]) -> io::Result<()> {
+    let rows = records
+        .iter()
+        .map(|record| cells(record, table_number))
+        .collect::<io::Result<Vec<_>>>()?;
+    let widths: Vec<usize> = T::FIELDS
+        .iter()
+        .enumerate()
+        .map(|(column, field)| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(field.len())
+        })
+        .collect();
+    let header: Vec<String> = T::FIELDS.iter().map(|f| f.to_uppercase()).collect();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::stored;
+
+    fn render<T: Record>(format: OutputFormat, records: &[T]) -> String {
+        let mut out = Vec::new();
+        write_records(&mut out, format, records).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn keys<T: Record>(record: &T) -> Vec<String> {
+        match serde_json::to_value(record).unwrap() {
+            Value::Object(map) => {
+                let mut keys: Vec<String> = map.keys().cloned().collect();
+                keys.sort();
+                keys
+            }
+            _ => panic!("not an object"),
+        }
+    }
+
+    fn sorted(fields: &[&str]) -> Vec<String> {
+        let mut fields: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+        fields.sort();
+        fields
+    }
+
+    fn expense() -> Expense {
+        stored(3, "2024-05-01", "Fish, \"chips\"", "Food", 8.5)
+    }
+
+    #[test]
+    fn test_fields_match_serde() {
+        let trashed = Expense {
+            deleted_at: Some("2024-05-02T00:00:00Z".to_string()),
+            ..expense()
+        };
+        assert_eq!(keys(&trashed), sorted(Expense::FIELDS));
+        let total = CategoryTotal {
+            category: "Food".to_string(),
+            total: 1.0,
+            count: 1,
+        };
+        assert_eq!(keys(&total), sorted(CategoryTotal::FIELDS));
+        let budget = Budget {
+            category: "Food".to_string(),
+            amount: 1.0,
+        };
+        assert_eq!(keys(&budget), sorted(Budget::FIELDS));
+        let status = BudgetStatus {
+            category: "Food".to_string(),
+            limit: 1.0,
+            spent: 0.5,
+        };
+        assert_eq!(keys(&status), sorted(BudgetStatus::FIELDS));
+    }
+
+    #[test]
+    fn test_csv_and_tsv() {
+        let csv = render(OutputFormat::Csv, &[expense()]);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "id,date,name,category,amount,external_id,account,notes,tags,status,uid,modified_at,\
+             deleted_at"
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("3,2024-05-01,\"Fish, \"\"chips\"\"\",Food,8.5,,,,,pending,"));
+
+        let tsv = render(OutputFormat::Tsv, &[expense()]);
+        assert!(tsv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("3\t2024-05-01\tFish, \"chips\"\tFood"));
+
+        // The header is there even without rows
+        assert_eq!(
+            render::<Budget>(OutputFormat::Csv, &[]),
+            "category,amount\n"
+        );
+    }
+
+    #[test]
+    fn test_json_and_jsonl() {
+        let json = render(OutputFormat::Json, &[expense(), expense()]);
+        let parsed: Vec<Expense> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 2);
+
+        let jsonl = render(OutputFormat::Jsonl, &[expense(), expense()]);
+        assert_eq!(jsonl.lines().count(), 2);
+        for line in jsonl.lines() {
+            let parsed: Expense = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed.name, "Fish, \"chips\"");
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        let budgets = vec![
+            Budget {
+                category: "Food".to_string(),
+                amount: 200.0,
+            },
+            Budget {
+                category: "Entertainment".to_string(),
+                amount: 50.0,
+            },
+        ];
+        assert_eq!(
+            render(OutputFormat::Table, &budgets),
+            "CATEGORY       AMOUNT\nFood           200.00\nEntertainment  50.00\n"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+
+/// First and last day of the given calendar month, or `None` if the month is
+/// out of range.
+pub fn month_bounds(year: i32, month: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let (next_year, next_month) = next_month(year, month);
+    let last = NaiveDate::from_ymd_opt(next_year, next_month, 1)?.pred_opt()?;
+    Some((first, last))
+}
+
+/// A calendar month known to be in range, so its bounds always exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Month {
+    year: i32,
+    month: u32,
+}
+
+impl Month {
+    /// `None` if the month is out of range.
+    pub fn new(year: i32, month: u32) -> Option<Self> {
+        month_bounds(year, month).map(|_| Month { year, month })
+    }
+
+    pub fn year(self) -> i32 {
+        self.year
+    }
+
+    pub fn month(self) -> u32 {
+        self.month
+    }
+
+    /// First and last day of the month.
+    pub fn bounds(self) -> (NaiveDate, NaiveDate) {
+        month_bounds(self.year, self.month).expect("checked when the month was made")
+    }
+}
+
+pub fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month >= 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+pub fn previous_month(year: i32, month: u32) -> (i32, u32) {
+    if month <= 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    month_bounds(year, month).map_or(0, |(_, last)| last.day())
+}
+
+pub fn month_key(year: i32, month: u32) -> String {
+    format!("{:04}-{:02}", year, month)
+}
+
+/// The weeks of a month as calendar rows starting on `week_start`. Days
+/// outside the month are `None`.
+pub fn calendar_weeks(year: i32, month: u32, week_start: Weekday) -> Vec<[Option<NaiveDate>; 7]> {
+    let Some((first, last)) = month_bounds(year, month) else {
+        return Vec::new();
+    };
+    let offset = first.weekday().days_since(week_start) as usize;
+    let mut weeks = Vec::new();
+    let mut week = [None; 7];
+    for (index, day) in first.iter_days().take_while(|day| *day <= last).enumerate() {
+        let column = (index + offset) % 7;
+        week[column] = Some(day);
+        if column == 6 {
+            weeks.push(week);
+            week = [None; 7];
+        }
+    }
+    if week.iter().any(Option::is_some) {
+        weeks.push(week);
+    }
+    weeks
+}
+
+/// Parses a `YYYY-MM` month key into year and month.
+pub fn parse_month(key: &str) -> Option<(i32, u32)> {
+    let (year, month) = key.trim().split_once('-')?;
+    let (year, month) = (year.parse().ok()?, month.parse().ok()?);
+    month_bounds(year, month).map(|_| (year, month))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_bounds() {
+        let (first, last) = month_bounds(2024, 2).unwrap();
+        assert_eq!(first, NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert_eq!(last, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+
+        let (_, last) = month_bounds(2023, 12).unwrap();
+        assert_eq!(last, NaiveDate::from_ymd_opt(2023, 12, 31).unwrap());
+
+        assert!(month_bounds(2023, 13).is_none());
+
+        let month = Month::new(2024, 2).unwrap();
+        assert_eq!(month.bounds(), month_bounds(2024, 2).unwrap());
+        assert!(Month::new(2023, 13).is_none());
+        assert!(Month::new(2023, 0).is_none());
+    }
+
+    #[test]
+    fn test_month_navigation() {
+        assert_eq!(next_month(2023, 12), (2024, 1));
+        assert_eq!(previous_month(2024, 1), (2023, 12));
+        assert_eq!(days_in_month(2023, 4), 30);
+        assert_eq!(month_key(2023, 4), "2023-04");
+        assert_eq!(parse_month("2023-04"), Some((2023, 4)));
+        assert_eq!(parse_month("2023-13"), None);
+        assert_eq!(parse_month("April"), None);
+    }
+
+    #[test]
+    fn test_calendar_weeks() {
+        // 1 February 2024 was a Thursday
+        let weeks = calendar_weeks(2024, 2, Weekday::Mon);
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(weeks[0][2], None);
+        assert_eq!(weeks[0][3].map(|d| d.day()), Some(1));
+        assert_eq!(weeks[4][3].map(|d| d.day()), Some(29));
+        assert_eq!(weeks[4][4], None);
+
+        let weeks = calendar_weeks(2024, 2, Weekday::Sun);
+        assert_eq!(weeks[0][4].map(|d| d.day()), Some(1));
+        let days: usize = weeks.iter().flatten().flatten().count();
+        assert_eq!(days, 29);
+
+        assert!(calendar_weeks(2024, 13, Weekday::Mon).is_empty());
+    }
+}
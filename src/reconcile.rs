@@ -0,0 +1,150 @@
+use chrono::NaiveDate;
+
+use crate::db::Database;
+use crate::expense::{Expense, ExpenseStatus};
+
+/// Differences smaller than this count as balanced.
+const BALANCE_EPSILON: f64 = 0.005;
+
+/// A statement being reconciled against one account.
+///
+/// Balances are cumulative spending recorded against the account: the
+/// statement's closing balance should equal everything reconciled so far plus
+/// the entries ticked off in this session.
+pub struct Reconciliation {
+    pub account: String,
+    pub statement_date: NaiveDate,
+    pub closing_balance: f64,
+    pub opening_balance: f64,
+    pub entries: Vec<Expense>,
+    pub selected: usize,
+}
+
+impl Reconciliation {
+    pub async fn start(
+        db: &Database,
+        account: &str,
+        statement_date: NaiveDate,
+        closing_balance: f64,
+    ) -> Result<Self, sqlx::Error> {
+        Ok(Reconciliation {
+            account: account.to_string(),
+            statement_date,
+            closing_balance,
+            opening_balance: db.reconciled_balance(account).await?,
+            entries: db
+                .reconciliation_candidates(account, statement_date)
+                .await?,
+            selected: 0,
+        })
+    }
+
+    pub fn cleared_total(&self) -> f64 {
+        self.entries
+            .iter()
+            .filter(|e| e.status == ExpenseStatus::Cleared)
+            .map(|e| e.amount)
+            .sum()
+    }
+
+    /// What's left to account for; zero once the ticked entries match the
+    /// statement.
+    pub fn difference(&self) -> f64 {
+        self.closing_balance - self.opening_balance - self.cleared_total()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.difference().abs() < BALANCE_EPSILON
+    }
+
+    /// Ticks or unticks the selected entry, saving the new status straight
+    /// away so an interrupted reconciliation keeps its progress.
+    pub async fn toggle_selected(&mut self, db: &Database) -> Result<(), sqlx::Error> {
+        let Some(entry) = self.entries.get_mut(self.selected) else {
+            return Ok(());
+        };
+        let status = match entry.status {
+            ExpenseStatus::Cleared => ExpenseStatus::Pending,
+            _ => ExpenseStatus::Cleared,
+        };
+        if let Some(id) = entry.id {
+            db.set_expense_status(id, status).await?;
+        }
+        entry.status = status;
+        Ok(())
+    }
+
+    /// Locks the ticked entries as reconciled. Refuses while the difference
+    /// isn't zero.
+    pub async fn finish(&self, db: &Database) -> Result<u64, String> {
+        if !self.is_balanced() {
+            return Err(format!(
+                "difference of {:.2} still to reconcile",
+                self.difference()
+            ));
+        }
+        db.finish_reconciliation(&self.account, self.statement_date)
+            .await
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seeded_db() -> Database {
+        let db = Database::new().await.unwrap();
+        for (day, name, amount) in [(1, "Rent", 900.0), (5, "Tesco", 40.0), (28, "Cinema", 15.0)] {
+            let mut expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, 7, day).unwrap(),
+                name,
+                "Misc",
+                amount,
+            )
+            .unwrap();
+            expense.account = "Current".to_string();
+            db.insert_expense(&expense).await.unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_reconciliation() {
+        let db = seeded_db().await;
+        let statement_date = NaiveDate::from_ymd_opt(2023, 7, 20).unwrap();
+        let mut reconciliation = Reconciliation::start(&db, "Current", statement_date, 900.0)
+            .await
+            .unwrap();
+        assert_eq!(reconciliation.entries.len(), 2);
+        assert_eq!(reconciliation.difference(), 900.0);
+
+        reconciliation.toggle_selected(&db).await.unwrap();
+        assert!(reconciliation.is_balanced());
+
+        reconciliation.selected = 1;
+        reconciliation.toggle_selected(&db).await.unwrap();
+        assert_eq!(reconciliation.difference(), -40.0);
+        assert!(reconciliation.finish(&db).await.is_err());
+
+        reconciliation.toggle_selected(&db).await.unwrap();
+        assert_eq!(reconciliation.finish(&db).await.unwrap(), 1);
+
+        let rent_id = reconciliation.entries[0].id.unwrap();
+        let rent = db.get_expense(rent_id).await.unwrap().unwrap();
+        assert_eq!(rent.status, ExpenseStatus::Reconciled);
+        assert!(db.delete_expense(rent_id).await.is_err());
+        assert!(db.update_expense(&rent).await.is_err());
+        assert!(db
+            .set_expense_status(rent_id, ExpenseStatus::Pending)
+            .await
+            .is_err());
+
+        let next = Reconciliation::start(&db, "Current", statement_date, 940.0)
+            .await
+            .unwrap();
+        assert_eq!(next.opening_balance, 900.0);
+        assert_eq!(next.entries.len(), 1);
+        assert_eq!(next.difference(), 40.0);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use crate::expense::Expense;
+
+/// How many different months an expense has to turn up in before it counts
+/// as recurring.
+pub const MIN_MONTHS: usize = 3;
+
+/// An expense that comes back month after month, like rent or a
+/// subscription. Expenses are grouped by name and category, ignoring case.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecurringExpense {
+    pub name: String,
+    pub category: String,
+    /// How many times it was paid.
+    pub count: usize,
+    /// How many different months it was paid in.
+    pub months: usize,
+    pub average: f64,
+    /// The latest payment, `YYYY-MM-DD`.
+    pub last_date: String,
+}
+
+/// Expenses paid in at least `MIN_MONTHS` different months, the biggest
+/// average first.
+pub fn find_recurring(expenses: &[Expense]) -> Vec<RecurringExpense> {
+    let mut groups: BTreeMap<(String, String), Vec<&Expense>> = BTreeMap::new();
+    for expense in expenses {
+        let key = (expense.name.to_lowercase(), expense.category.to_lowercase());
+        groups.entry(key).or_default().push(expense);
+    }
+    let mut recurring: Vec<RecurringExpense> = groups
+        .into_values()
+        .filter_map(|group| {
+            let mut months: Vec<&str> = group.iter().filter_map(|e| e.date.get(..7)).collect();
+            months.sort_unstable();
+            months.dedup();
+            if months.len() < MIN_MONTHS {
+                return None;
+            }
+            let latest = group.iter().max_by(|a, b| a.date.cmp(&b.date))?;
+            let total: f64 = group.iter().map(|e| e.amount).sum();
+            Some(RecurringExpense {
+                name: latest.name.clone(),
+                category: latest.category.clone(),
+                count: group.len(),
+                months: months.len(),
+                average: total / group.len() as f64,
+                last_date: latest.date.clone(),
+            })
+        })
+        .collect();
+    recurring.sort_by(|a, b| b.average.total_cmp(&a.average).then(a.name.cmp(&b.name)));
+    recurring
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::expense;
+
+    #[test]
+    fn test_find_recurring() {
+        let expenses = vec![
+            expense("2024-01-01", "Rent", "Bills", 700.0),
+            expense("2024-02-01", "rent", "Bills", 700.0),
+            expense("2024-03-01", "Rent", "Bills", 730.0),
+            expense("2024-01-05", "Streaming", "Bills", 10.0),
+            expense("2024-02-05", "Streaming", "Bills", 10.0),
+            expense("2024-03-05", "Streaming", "Bills", 10.0),
+            expense("2024-03-20", "Streaming", "Bills", 10.0),
+            // Twice in one month isn't a pattern yet
+            expense("2024-01-08", "Cinema", "Bills", 12.0),
+            expense("2024-01-09", "Cinema", "Bills", 12.0),
+            expense("2024-02-09", "Cinema", "Bills", 12.0),
+        ];
+        let recurring = find_recurring(&expenses);
+        assert_eq!(recurring.len(), 2);
+        assert_eq!(recurring[0].name, "Rent");
+        assert_eq!(recurring[0].average, 710.0);
+        assert_eq!(recurring[0].last_date, "2024-03-01");
+        assert_eq!(recurring[1].count, 4);
+        assert_eq!(recurring[1].months, 3);
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+
+use super::write_report;
+use crate::crypto::Key;
+use crate::db::Database;
+use crate::expense::Expense;
+use crate::period::Month;
+use crate::summary::{CategoryTotal, MonthTotal, MonthlySummary};
+
+const STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; margin: 2rem auto; max-width: 60rem; color: #222; padding: 0 1rem; }
+h1 { margin-bottom: 0.2rem; }
+h2 { border-bottom: 1px solid #ddd; padding-bottom: 0.3rem; margin-top: 2rem; }
+.muted { color: #777; }
+.cards { display: flex; gap: 1rem; flex-wrap: wrap; }
+.card { flex: 1; min-width: 10rem; border: 1px solid #ddd; border-radius: 6px; padding: 0.8rem 1rem; }
+.card .value { font-size: 1.6rem; font-weight: bold; }
+.up { color: #b3261e; }
+.down { color: #1b7f3b; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.3rem 0.6rem; border-bottom: 1px solid #eee; }
+td.amount, th.amount { text-align: right; font-variant-numeric: tabular-nums; }
+svg text { font-size: 12px; fill: #333; }
+";
+
+const BAR_COLOR: &str = "#3b7dd8";
+const CURRENT_MONTH_COLOR: &str = "#e08a1e";
+
+/// Writes a self-contained HTML report for one month: no scripts, fonts or
+/// images are loaded from anywhere else, so the file can be mailed around.
+pub async fn export_monthly_report(
+    db: &Database,
+    year: i32,
+    month: u32,
+    filename: &str,
+    key: Option<&Key>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let month_of = Month::new(year, month).ok_or("invalid month")?;
+    let summary = MonthlySummary::load(db, month_of).await?;
+    let expenses = db.filter_by_month(year, month).await?;
+    let html = render_monthly_report(&summary, &expenses);
+    write_report(filename, html.as_bytes(), key)
+}
+
+pub fn render_monthly_report(summary: &MonthlySummary, expenses: &[Expense]) -> String {
+    let title = match NaiveDate::from_ymd_opt(summary.year, summary.month, 1) {
+        Some(first) => first.format("%B %Y").to_string(),
+        None => summary.month_key(),
+    };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(html, "<title>Expenses — {}</title>", escape(&title));
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(html, "<h1>Expenses — {}</h1>", escape(&title));
+    let _ = writeln!(
+        html,
+        "<p class=\"muted\">{} transactions</p>",
+        summary.count
+    );
+
+    html.push_str("<div class=\"cards\">\n");
+    card(&mut html, "Total spent", &format_amount(summary.total), "");
+    card(
+        &mut html,
+        "Previous month",
+        &format_amount(summary.previous_total),
+        "",
+    );
+    match summary.change_from_previous() {
+        Some(change) => {
+            let class = if change > 0.0 { "up" } else { "down" };
+            card(&mut html, "Change", &format!("{:+.1}%", change), class);
+        }
+        None => card(&mut html, "Change", "n/a", "muted"),
+    }
+    html.push_str("</div>\n");
+
+    html.push_str("<h2>By category</h2>\n");
+    if summary.categories.is_empty() {
+        html.push_str("<p class=\"muted\">No expenses this month.</p>\n");
+    } else {
+        html.push_str(&category_chart(&summary.categories));
+        html.push_str("<table>\n<tr><th>Category</th><th class=\"amount\">Count</th><th class=\"amount\">Total</th><th class=\"amount\">Share</th></tr>\n");
+        for category in &summary.categories {
+            let share = if summary.total > 0.0 {
+                category.total / summary.total * 100.0
+            } else {
+                0.0
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td><td class=\"amount\">{:.1}%</td></tr>",
+                escape(&category.category),
+                category.count,
+                format_amount(category.total),
+                share
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Top merchants</h2>\n");
+    if summary.top_merchants.is_empty() {
+        html.push_str("<p class=\"muted\">No expenses this month.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Name</th><th class=\"amount\">Count</th><th class=\"amount\">Total</th></tr>\n");
+        for merchant in &summary.top_merchants {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>",
+                escape(&merchant.name),
+                merchant.count,
+                format_amount(merchant.total)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Month over month</h2>\n");
+    html.push_str(&history_chart(&summary.history));
+
+    html.push_str("<h2>Transactions</h2>\n");
+    let mut sorted: Vec<&Expense> = expenses.iter().collect();
+    sorted.sort_by(|a, b| a.date.cmp(&b.date).then(a.name.cmp(&b.name)));
+    html.push_str("<table>\n<tr><th>Date</th><th>Name</th><th>Category</th><th class=\"amount\">Amount</th></tr>\n");
+    for expense in sorted {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"amount\">{}</td></tr>",
+            escape(&expense.date),
+            escape(&expense.name),
+            escape(&expense.category),
+            format_amount(expense.amount)
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn card(html: &mut String, label: &str, value: &str, class: &str) {
+    let _ = writeln!(
+        html,
+        "<div class=\"card\"><div class=\"muted\">{}</div><div class=\"value {}\">{}</div></div>",
+        escape(label),
+        class,
+        escape(value)
+    );
+}
+
+/// Horizontal bar per category, scaled against the largest category.
+fn category_chart(categories: &[CategoryTotal]) -> String {
+    const ROW: usize = 24;
+    const LABEL: usize = 140;
+    const WIDTH: usize = 640;
+    let max = categories.iter().map(|c| c.total).fold(0.0, f64::max);
+    let height = categories.len() * ROW + 8;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">\n",
+        WIDTH, height
+    );
+    for (index, category) in categories.iter().enumerate() {
+        let y = index * ROW + 4;
+        let bar = scale(category.total, max, WIDTH - LABEL - 90);
+        let _ = writeln!(
+            svg,
+            "<text x=\"0\" y=\"{}\">{}</text><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text>",
+            y + 15,
+            escape(&category.category),
+            LABEL,
+            y + 3,
+            bar,
+            ROW - 8,
+            BAR_COLOR,
+            LABEL + bar + 6,
+            y + 15,
+            format_amount(category.total)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Column per month with the summarised (last) month highlighted.
+fn history_chart(history: &[MonthTotal]) -> String {
+    const WIDTH: usize = 640;
+    const HEIGHT: usize = 200;
+    const AXIS: usize = 20;
+    const VALUE: usize = 16;
+    let max = history.iter().map(|m| m.total).fold(0.0, f64::max);
+    let slot = WIDTH / history.len().max(1);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">\n",
+        WIDTH, HEIGHT
+    );
+    for (index, month) in history.iter().enumerate() {
+        let bar = scale(month.total, max, HEIGHT - AXIS - VALUE);
+        let x = index * slot + slot / 6;
+        let y = HEIGHT - AXIS - bar;
+        let color = if index + 1 == history.len() {
+            CURRENT_MONTH_COLOR
+        } else {
+            BAR_COLOR
+        };
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text><text x=\"{}\" y=\"{}\">{}</text>",
+            x,
+            y,
+            slot * 2 / 3,
+            bar,
+            color,
+            x,
+            y.saturating_sub(4),
+            format_amount(month.total),
+            x,
+            HEIGHT - 4,
+            escape(&month.month)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn scale(value: f64, max: f64, span: usize) -> usize {
+    if max <= 0.0 {
+        0
+    } else {
+        (value / max * span as f64).round() as usize
+    }
+}
+
+fn format_amount(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seeded_db() -> Database {
+        let db = Database::new().await.unwrap();
+        for (month, day, name, category, amount) in [
+            (6, 10, "Rent", "Housing", 900.0),
+            (7, 1, "Rent", "Housing", 900.0),
+            (7, 3, "Tesco", "Food", 45.5),
+            (7, 9, "Fish & <Chips>", "Food", 12.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, month, day).unwrap(),
+                name,
+                category,
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_render_monthly_report() {
+        let db = seeded_db().await;
+        let summary = MonthlySummary::load(&db, Month::new(2023, 7).unwrap())
+            .await
+            .unwrap();
+        let expenses = db.filter_by_month(2023, 7).await.unwrap();
+
+        let html = render_monthly_report(&summary, &expenses);
+        assert!(html.contains("<h1>Expenses — July 2023</h1>"));
+        assert!(html.contains("957.50"));
+        assert!(html.contains("Fish &amp; &lt;Chips&gt;"));
+        assert!(html.contains("<svg"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("src="));
+        assert!(!html.contains("<link"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&#39;");
+    }
+}
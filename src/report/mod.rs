@@ -0,0 +1,27 @@
+use crate::crypto::{self, Key};
+use crate::db::write_atomically;
+
+pub mod html;
+pub mod text;
+pub mod xlsx;
+
+/// Writes a finished report. With a key the report is encrypted in memory and
+/// saved with an `.enc` suffix, so the plaintext never reaches the disk.
+/// Returns the name of the file that was written.
+pub fn write_report(
+    filename: &str,
+    bytes: &[u8],
+    key: Option<&Key>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match key {
+        Some(key) => {
+            let filename = format!("{}.enc", filename);
+            write_atomically(&filename, &crypto::encrypt(key, bytes)?)?;
+            Ok(filename)
+        }
+        None => {
+            write_atomically(filename, bytes)?;
+            Ok(filename.to_string())
+        }
+    }
+}
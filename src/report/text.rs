@@ -0,0 +1,271 @@
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+
+use super::write_report;
+use crate::crypto::Key;
+use crate::db::Database;
+use crate::summary::PeriodSummary;
+
+/// Output flavour for the plain-text reports. Markdown renders nicely in chat
+/// apps and notes; ASCII is for places that show the raw text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextStyle {
+    Markdown,
+    Ascii,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+}
+
+pub async fn export_period_report(
+    db: &Database,
+    start: NaiveDate,
+    end: NaiveDate,
+    style: TextStyle,
+    filename: &str,
+    key: Option<&Key>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let summary = PeriodSummary::load(db, start, end).await?;
+    write_report(filename, render_summary(&summary, style).as_bytes(), key)
+}
+
+pub fn render_summary(summary: &PeriodSummary, style: TextStyle) -> String {
+    let mut out = String::new();
+    let title = format!("Expenses {} to {}", summary.start, summary.end);
+    match style {
+        TextStyle::Markdown => {
+            let _ = writeln!(out, "# {}\n", title);
+        }
+        TextStyle::Ascii => {
+            let _ = writeln!(out, "{}\n{}\n", title, "=".repeat(title.chars().count()));
+        }
+    }
+
+    let bullet = match style {
+        TextStyle::Markdown => "- ",
+        TextStyle::Ascii => "  ",
+    };
+    let _ = writeln!(out, "{}Total: {:.2}", bullet, summary.total);
+    let _ = writeln!(out, "{}Transactions: {}", bullet, summary.count);
+    let _ = writeln!(out, "{}Daily average: {:.2}", bullet, summary.daily_average);
+
+    heading(&mut out, style, "By category");
+    if summary.categories.is_empty() {
+        out.push_str("No expenses in this period.\n");
+    } else {
+        let mut rows: Vec<Vec<String>> = summary
+            .categories
+            .iter()
+            .map(|c| {
+                vec![
+                    c.category.clone(),
+                    c.count.to_string(),
+                    format!("{:.2}", c.total),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            "Total".to_string(),
+            summary.count.to_string(),
+            format!("{:.2}", summary.total),
+        ]);
+        table(
+            &mut out,
+            style,
+            &["Category", "Count", "Total"],
+            &[Align::Left, Align::Right, Align::Right],
+            &rows,
+        );
+    }
+
+    if !summary.budgets.is_empty() {
+        heading(&mut out, style, "Budgets");
+        let rows: Vec<Vec<String>> = summary
+            .budgets
+            .iter()
+            .map(|b| {
+                vec![
+                    b.category.clone(),
+                    format!("{:.2}", b.limit),
+                    format!("{:.2}", b.spent),
+                    format!("{:.2}", b.remaining()),
+                    if b.is_over() {
+                        format!("OVER ({:.0}%)", b.used_percent())
+                    } else {
+                        format!("{:.0}%", b.used_percent())
+                    },
+                ]
+            })
+            .collect();
+        table(
+            &mut out,
+            style,
+            &["Category", "Budget", "Spent", "Remaining", "Used"],
+            &[
+                Align::Left,
+                Align::Right,
+                Align::Right,
+                Align::Right,
+                Align::Right,
+            ],
+            &rows,
+        );
+    }
+
+    heading(&mut out, style, "Largest expenses");
+    if summary.largest.is_empty() {
+        out.push_str("No expenses in this period.\n");
+    } else {
+        let rows: Vec<Vec<String>> = summary
+            .largest
+            .iter()
+            .map(|e| {
+                vec![
+                    e.date.clone(),
+                    e.name.clone(),
+                    e.category.clone(),
+                    format!("{:.2}", e.amount),
+                ]
+            })
+            .collect();
+        table(
+            &mut out,
+            style,
+            &["Date", "Name", "Category", "Amount"],
+            &[Align::Left, Align::Left, Align::Left, Align::Right],
+            &rows,
+        );
+    }
+    out
+}
+
+fn heading(out: &mut String, style: TextStyle, text: &str) {
+    match style {
+        TextStyle::Markdown => {
+            let _ = writeln!(out, "\n## {}\n", text);
+        }
+        TextStyle::Ascii => {
+            let _ = writeln!(out, "\n{}\n{}", text, "-".repeat(text.chars().count()));
+        }
+    }
+}
+
+fn table(
+    out: &mut String,
+    style: TextStyle,
+    headers: &[&str],
+    align: &[Align],
+    rows: &[Vec<String>],
+) {
+    let rows: Vec<Vec<String>> = match style {
+        TextStyle::Markdown => rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.replace('|', "\\|")).collect())
+            .collect(),
+        TextStyle::Ascii => rows.to_vec(),
+    };
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    match style {
+        TextStyle::Markdown => {
+            write_row(out, &headers, &widths, align);
+            let separator: Vec<String> = widths
+                .iter()
+                .zip(align)
+                .map(|(width, align)| match align {
+                    Align::Left => "-".repeat(*width),
+                    Align::Right => format!("{}:", "-".repeat(width.saturating_sub(1))),
+                })
+                .collect();
+            let _ = writeln!(out, "| {} |", separator.join(" | "));
+            for row in &rows {
+                write_row(out, row, &widths, align);
+            }
+        }
+        TextStyle::Ascii => {
+            let border: Vec<String> = widths.iter().map(|w| "-".repeat(w + 2)).collect();
+            let border = format!("+{}+", border.join("+"));
+            let _ = writeln!(out, "{}", border);
+            write_row(out, &headers, &widths, align);
+            let _ = writeln!(out, "{}", border);
+            for row in &rows {
+                write_row(out, row, &widths, align);
+            }
+            let _ = writeln!(out, "{}", border);
+        }
+    }
+}
+
+fn write_row(out: &mut String, cells: &[String], widths: &[usize], align: &[Align]) {
+    let _ = writeln!(out, "| {} |", padded(cells, widths, align).join(" | "));
+}
+
+fn padded(cells: &[String], widths: &[usize], align: &[Align]) -> Vec<String> {
+    cells
+        .iter()
+        .zip(widths)
+        .zip(align)
+        .map(|((cell, width), align)| match align {
+            Align::Left => format!("{:<width$}", cell, width = width),
+            Align::Right => format!("{:>width$}", cell, width = width),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expense::Expense;
+    use crate::period::Month;
+
+    async fn summary() -> PeriodSummary {
+        let db = Database::new().await.unwrap();
+        for (day, name, category, amount) in [
+            (1, "Rent", "Housing", 900.0),
+            (3, "Tesco", "Food", 45.5),
+            (9, "Chips | Peas", "Food", 12.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, 6, day).unwrap(),
+                name,
+                category,
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+        db.set_budget("Food", 50.0).await.unwrap();
+        PeriodSummary::for_month(&db, Month::new(2023, 6).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_render_markdown() {
+        let text = render_summary(&summary().await, TextStyle::Markdown);
+        assert!(text.starts_with("# Expenses 2023-06-01 to 2023-06-30"));
+        assert!(text.contains("- Daily average: 31.92"));
+        assert!(text.contains("| Housing  |     1 | 900.00 |"));
+        assert!(text.contains("| Food     |  50.00 | 57.50 |     -7.50 | OVER (115%) |"));
+        assert!(text.contains("Chips \\| Peas"));
+    }
+
+    #[tokio::test]
+    async fn test_render_ascii() {
+        let text = render_summary(&summary().await, TextStyle::Ascii);
+        assert!(text.contains("By category\n-----------"));
+        assert!(text.contains("+----------+-------+--------+"));
+        assert!(text.contains("| Total    |     3 | 957.50 |"));
+        assert!(!text.contains("##"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rust_xlsxwriter::{column_number_to_name, Format, Formula, Workbook, Worksheet, XlsxError};
+
+use super::write_report;
+use crate::crypto::Key;
+use crate::db::Database;
+use crate::expense::Expense;
+use crate::summary::CategoryMonthTotal;
+
+const TRANSACTIONS_SHEET: &str = "Transactions";
+const SUMMARY_SHEET: &str = "Summary";
+const CURRENCY_FORMAT: &str = "#,##0.00;[Red]-#,##0.00";
+const DATE_FORMAT: &str = "yyyy-mm-dd";
+const MONTH_FORMAT: &str = "mmm yyyy";
+
+/// Writes every expense in the database to an `.xlsx` workbook with a raw
+/// `Transactions` sheet and a category-by-month `Summary` sheet built from
+/// `SUMIFS` formulas over the transactions. Returns the name of the file
+/// written, see [`write_report`].
+pub async fn export_workbook(
+    db: &Database,
+    filename: &str,
+    key: Option<&Key>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let expenses = db.list_expenses().await?;
+    let totals = db.category_totals_by_month().await?;
+
+    let mut workbook = build_workbook(&expenses, &totals)?;
+    write_report(filename, &workbook.save_to_buffer()?, key)
+}
+
+fn build_workbook(
+    expenses: &[Expense],
+    totals: &[CategoryMonthTotal],
+) -> Result<Workbook, XlsxError> {
+    let mut workbook = Workbook::new();
+
+    let header = Format::new().set_bold();
+    let currency = Format::new().set_num_format(CURRENCY_FORMAT);
+    let bold_currency = Format::new().set_bold().set_num_format(CURRENCY_FORMAT);
+    let date = Format::new().set_num_format(DATE_FORMAT);
+    let month = Format::new().set_bold().set_num_format(MONTH_FORMAT);
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(TRANSACTIONS_SHEET)?;
+    write_transactions(sheet, expenses, &header, &date, &currency)?;
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(SUMMARY_SHEET)?;
+    write_summary(sheet, totals, &header, &month, &currency, &bold_currency)?;
+
+    Ok(workbook)
+}
+
+fn write_transactions(
+    sheet: &mut Worksheet,
+    expenses: &[Expense],
+    header: &Format,
+    date: &Format,
+    currency: &Format,
+) -> Result<(), XlsxError> {
+    for (col, title) in ["Id", "Date", "Name", "Category", "Amount"]
+        .iter()
+        .enumerate()
+    {
+        sheet.write_string_with_format(0, col as u16, *title, header)?;
+    }
+
+    for (index, expense) in expenses.iter().enumerate() {
+        let row = index as u32 + 1;
+        if let Some(id) = expense.id {
+            sheet.write_number(row, 0, id as f64)?;
+        }
+        match NaiveDate::parse_from_str(&expense.date, "%Y-%m-%d") {
+            Ok(parsed) => sheet.write_datetime_with_format(row, 1, parsed, date)?,
+            Err(_) => sheet.write_string(row, 1, &expense.date)?,
+        };
+        sheet.write_string(row, 2, &expense.name)?;
+        sheet.write_string(row, 3, &expense.category)?;
+        sheet.write_number_with_format(row, 4, expense.amount, currency)?;
+    }
+
+    sheet.set_column_width(1, 12)?;
+    sheet.set_column_width(2, 30)?;
+    sheet.set_column_width(3, 18)?;
+    sheet.set_column_width(4, 14)?;
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Lays out one row per category and one column per month. Each cell is a
+/// `SUMIFS` over the transactions sheet, with the aggregate from the database
+/// stored as the cached result so viewers that don't recalculate still show
+/// the right numbers.
+fn write_summary(
+    sheet: &mut Worksheet,
+    totals: &[CategoryMonthTotal],
+    header: &Format,
+    month: &Format,
+    currency: &Format,
+    bold_currency: &Format,
+) -> Result<(), XlsxError> {
+    let mut months: Vec<&str> = totals.iter().map(|t| t.month.as_str()).collect();
+    months.sort();
+    months.dedup();
+
+    let mut grid: BTreeMap<&str, BTreeMap<&str, f64>> = BTreeMap::new();
+    for total in totals {
+        grid.entry(total.category.as_str())
+            .or_default()
+            .insert(total.month.as_str(), total.total);
+    }
+
+    let total_col = months.len() as u16 + 1;
+    let total_row = grid.len() as u32 + 1;
+
+    sheet.write_string_with_format(0, 0, "Category", header)?;
+    for (index, name) in months.iter().enumerate() {
+        let col = index as u16 + 1;
+        match NaiveDate::parse_from_str(&format!("{}-01", name), "%Y-%m-%d") {
+            Ok(first) => sheet.write_datetime_with_format(0, col, first, month)?,
+            Err(_) => sheet.write_string_with_format(0, col, *name, header)?,
+        };
+    }
+    sheet.write_string_with_format(0, total_col, "Total", header)?;
+
+    for (index, (category, by_month)) in grid.iter().enumerate() {
+        let row = index as u32 + 1;
+        let excel_row = row + 1;
+        sheet.write_string(row, 0, *category)?;
+
+        for (index, name) in months.iter().enumerate() {
+            let col = index as u16 + 1;
+            let letter = column_number_to_name(col);
+            let value = by_month.get(name).copied().unwrap_or(0.0);
+            let formula = Formula::new(format!(
+                "=SUMIFS({t}!$E:$E,{t}!$D:$D,$A{r},{t}!$B:$B,\">=\"&{c}$1,{t}!$B:$B,\"<\"&EDATE({c}$1,1))",
+                t = TRANSACTIONS_SHEET,
+                r = excel_row,
+                c = letter,
+            ))
+            .set_result(value.to_string());
+            sheet.write_formula_with_format(row, col, formula, currency)?;
+        }
+
+        let row_total: f64 = by_month.values().sum();
+        let formula = Formula::new(format!(
+            "=SUM(B{r}:{last}{r})",
+            r = excel_row,
+            last = column_number_to_name(total_col - 1),
+        ))
+        .set_result(row_total.to_string());
+        sheet.write_formula_with_format(row, total_col, formula, bold_currency)?;
+    }
+
+    sheet.write_string_with_format(total_row, 0, "Total", header)?;
+    for col in 1..=total_col {
+        let letter = column_number_to_name(col);
+        let column_total: f64 = if col == total_col {
+            totals.iter().map(|t| t.total).sum()
+        } else {
+            let name = months[col as usize - 1];
+            totals
+                .iter()
+                .filter(|t| t.month == name)
+                .map(|t| t.total)
+                .sum()
+        };
+        let formula = Formula::new(format!("=SUM({c}2:{c}{end})", c = letter, end = total_row))
+            .set_result(column_total.to_string());
+        sheet.write_formula_with_format(total_row, col, formula, bold_currency)?;
+    }
+
+    sheet.set_column_width(0, 18)?;
+    for col in 1..=total_col {
+        sheet.set_column_width(col, 12)?;
+    }
+    sheet.set_freeze_panes(1, 1)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_export_workbook() {
+        let db = Database::new().await.unwrap();
+        for (day, name, category, amount) in [
+            (1, "Groceries", "Food", 42.5),
+            (3, "Bus pass", "Transport", 60.0),
+            (20, "Dinner", "Food", 31.0),
+        ] {
+            let expense = Expense::new(
+                NaiveDate::from_ymd_opt(2023, 7, day).unwrap(),
+                name,
+                category,
+                amount,
+            )
+            .unwrap();
+            db.insert_expense(&expense).await.unwrap();
+        }
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path().to_str().unwrap();
+        export_workbook(&db, file_path, None).await.unwrap();
+
+        // An .xlsx file is a zip archive
+        let bytes = std::fs::read(file_path).unwrap();
+        assert!(bytes.starts_with(b"PK"));
+    }
+
+    #[test]
+    fn test_build_workbook_without_expenses() {
+        let mut workbook = build_workbook(&[], &[]).unwrap();
+        assert!(!workbook.save_to_buffer().unwrap().is_empty());
+    }
+}
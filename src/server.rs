@@ -0,0 +1,744 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+
+use crate::budget::Budget;
+use crate::crypto::Key;
+use crate::db::{DataContents, Database, LockedExpense};
+use crate::expense::Expense;
+use crate::period::{month_bounds, parse_month as parse_month_key};
+use crate::summary::PeriodSummary;
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// Shared by every request. Changes are written back to `data_file` straight
+/// away, one at a time, and rolled back when that fails, so the file always
+/// matches what the API returned.
+pub struct ServerState {
+    pub db: Database,
+    pub token: String,
+    /// Where the ledger is saved after each change; `None` keeps it in memory.
+    pub data_file: Option<String>,
+    pub key: Option<Key>,
+    write_lock: Mutex<()>,
+}
+
+impl ServerState {
+    pub fn new(db: Database, token: String, data_file: Option<String>, key: Option<Key>) -> Self {
+        Self {
+            db,
+            token,
+            data_file,
+            key,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// What to go back to if saving the next change fails, `None` when
+    /// nothing is saved.
+    async fn snapshot(&self) -> ApiResult<Option<DataContents>> {
+        match &self.data_file {
+            Some(_) => Ok(Some(self.db.snapshot().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves the change made since `before` was taken, or undoes it when it
+    /// can't be saved.
+    async fn save(&self, before: Option<DataContents>) -> ApiResult<()> {
+        let (Some(file), Some(before)) = (&self.data_file, before) else {
+            return Ok(());
+        };
+        let error = match self.db.save_to_file(file, self.key.as_ref()).await {
+            Ok(()) => return Ok(()),
+            Err(err) => format!("can't save {}: {}", file, err),
+        };
+        match self.db.restore_snapshot(&before).await {
+            Ok(()) => Err(ApiError::Internal(error)),
+            Err(err) => Err(ApiError::Internal(format!(
+                "{}, and undoing the change failed: {}",
+                error, err
+            ))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    Unauthorized,
+    NotFound,
+    BadRequest(String),
+    /// The request clashes with the stored data, e.g. a reconciled expense.
+    Conflict(String),
+    /// A request the extractors turned down, e.g. a body that isn't valid
+    /// JSON, with the status axum gives it.
+    Rejected(StatusCode, String),
+    Internal(String),
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => ApiError::NotFound,
+            sqlx::Error::Database(err) if err.try_downcast_ref::<LockedExpense>().is_some() => {
+                ApiError::Conflict(err.message().to_string())
+            }
+            sqlx::Error::Database(err)
+                if err.is_unique_violation() || err.is_foreign_key_violation() =>
+            {
+                ApiError::Conflict(err.message().to_string())
+            }
+            sqlx::Error::Database(err) if err.is_check_violation() => {
+                ApiError::BadRequest(err.message().to_string())
+            }
+            err => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "missing or wrong token".into()),
+            ApiError::NotFound => (StatusCode::NOT_FOUND, "not found".into()),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Conflict(message) => (StatusCode::CONFLICT, message),
+            ApiError::Rejected(status, message) => (status, message),
+            ApiError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        };
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+// Handlers take their extractors as `Result`s and turn the rejections into
+// `ApiError`s, so a malformed request gets a JSON error like any other.
+type JsonInput<T> = Result<Json<T>, JsonRejection>;
+type PathInput<T> = Result<Path<T>, PathRejection>;
+type QueryInput<T> = Result<Query<T>, QueryRejection>;
+
+/// Fields a client sends to create or replace an expense.
+#[derive(Debug, Deserialize)]
+pub struct ExpenseInput {
+    pub date: NaiveDate,
+    pub name: String,
+    pub category: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+impl ExpenseInput {
+    fn into_expense(self) -> ApiResult<Expense> {
+        let mut expense = Expense::new(self.date, &self.name, &self.category, self.amount)
+            .map_err(ApiError::BadRequest)?;
+        expense.account = self.account;
+        expense.notes = self.notes;
+        expense.tags = self.tags;
+        expense.external_id = self.external_id;
+        Ok(expense)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub category: Option<String>,
+    /// `YYYY-MM`
+    pub month: Option<String>,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of matching items across all pages.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SummaryQuery {
+    pub month: Option<String>,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetInput {
+    pub amount: f64,
+}
+
+pub fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/expenses", get(list_expenses).post(create_expense))
+        .route(
+            "/expenses/:id",
+            get(get_expense).put(update_expense).delete(delete_expense),
+        )
+        .route("/summary", get(summary))
+        .route("/budgets", get(list_budgets))
+        .route("/budgets/:category", put(set_budget).delete(delete_budget))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .with_state(state)
+}
+
+/// Serves the API until the process is stopped. Refuses to start without a
+/// token, as that would let anyone in.
+pub async fn serve(addr: std::net::SocketAddr, state: Arc<ServerState>) -> std::io::Result<()> {
+    if state.token.trim().is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the API token must not be empty",
+        ));
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    serve_on(listener, state).await
+}
+
+async fn serve_on(
+    listener: tokio::net::TcpListener,
+    state: Arc<ServerState>,
+) -> std::io::Result<()> {
+    axum::serve(listener, router(state)).await
+}
+
+/// Every request needs `Authorization: Bearer <token>`.
+async fn authenticate(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| {
+            !state.token.is_empty() && bool::from(token.as_bytes().ct_eq(state.token.as_bytes()))
+        });
+    if authorized {
+        next.run(request).await
+    } else {
+        ApiError::Unauthorized.into_response()
+    }
+}
+
+fn parse_month(month: &str) -> ApiResult<(NaiveDate, NaiveDate)> {
+    parse_month_key(month)
+        .and_then(|(year, month)| month_bounds(year, month))
+        .ok_or_else(|| ApiError::BadRequest(format!("invalid month '{}', use YYYY-MM", month)))
+}
+
+async fn list_expenses(
+    State(state): State<Arc<ServerState>>,
+    query: QueryInput<ListQuery>,
+) -> ApiResult<Json<Page<Expense>>> {
+    let Query(query) = query?;
+    let range = match (&query.month, query.start, query.end) {
+        (Some(month), _, _) => Some(parse_month(month)?),
+        (None, Some(start), Some(end)) => Some((start, end)),
+        (None, None, None) => None,
+        _ => {
+            return Err(ApiError::BadRequest(
+                "start and end must be given together".to_string(),
+            ))
+        }
+    };
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let (items, total) = state
+        .db
+        .page_expenses(query.category.as_deref(), range, offset, limit)
+        .await?;
+    Ok(Json(Page {
+        items,
+        total,
+        offset,
+        limit,
+    }))
+}
+
+async fn get_expense(
+    State(state): State<Arc<ServerState>>,
+    id: PathInput<i64>,
+) -> ApiResult<Json<Expense>> {
+    let Path(id) = id?;
+    let expense = state.db.get_expense(id).await?.ok_or(ApiError::NotFound)?;
+    Ok(Json(expense))
+}
+
+async fn create_expense(
+    State(state): State<Arc<ServerState>>,
+    input: JsonInput<ExpenseInput>,
+) -> ApiResult<(StatusCode, Json<Expense>)> {
+    let Json(input) = input?;
+    let expense = input.into_expense()?;
+    let _guard = state.write_lock.lock().await;
+    let before = state.snapshot().await?;
+    let id = state.db.insert_expense(&expense).await?;
+    state.save(before).await?;
+    let created = state.db.get_expense(id).await?.ok_or(ApiError::NotFound)?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+async fn update_expense(
+    State(state): State<Arc<ServerState>>,
+    id: PathInput<i64>,
+    input: JsonInput<ExpenseInput>,
+) -> ApiResult<Json<Expense>> {
+    let Path(id) = id?;
+    let Json(input) = input?;
+    let _guard = state.write_lock.lock().await;
+    let existing = state.db.get_expense(id).await?.ok_or(ApiError::NotFound)?;
+    let mut expense = input.into_expense()?;
+    expense.id = existing.id;
+    expense.uid = existing.uid;
+    expense.status = existing.status;
+    let before = state.snapshot().await?;
+    state.db.update_expense(&expense).await?;
+    state.save(before).await?;
+    let updated = state.db.get_expense(id).await?.ok_or(ApiError::NotFound)?;
+    Ok(Json(updated))
+}
+
+async fn delete_expense(
+    State(state): State<Arc<ServerState>>,
+    id: PathInput<i64>,
+) -> ApiResult<StatusCode> {
+    let Path(id) = id?;
+    let _guard = state.write_lock.lock().await;
+    state.db.get_expense(id).await?.ok_or(ApiError::NotFound)?;
+    let before = state.snapshot().await?;
+    state.db.delete_expense(id).await?;
+    state.save(before).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Summary of a month, a date range, or the current month if neither is
+/// given.
+async fn summary(
+    State(state): State<Arc<ServerState>>,
+    query: QueryInput<SummaryQuery>,
+) -> ApiResult<Json<PeriodSummary>> {
+    let Query(query) = query?;
+    let (start, end) = match (&query.month, query.start, query.end) {
+        (Some(month), _, _) => parse_month(month)?,
+        (None, Some(start), Some(end)) if start <= end => (start, end),
+        (None, None, None) => {
+            let today = chrono::Local::now().date_naive();
+            let month = today.format("%Y-%m").to_string();
+            parse_month(&month)?
+        }
+        _ => {
+            return Err(ApiError::BadRequest(
+                "give a month, or a start and end with start <= end".to_string(),
+            ))
+        }
+    };
+    Ok(Json(PeriodSummary::load(&state.db, start, end).await?))
+}
+
+async fn list_budgets(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<Budget>>> {
+    Ok(Json(state.db.list_budgets().await?))
+}
+
+async fn set_budget(
+    State(state): State<Arc<ServerState>>,
+    category: PathInput<String>,
+    input: JsonInput<BudgetInput>,
+) -> ApiResult<Json<Budget>> {
+    let Path(category) = category?;
+    let Json(input) = input?;
+    if input.amount < 0.0 {
+        return Err(ApiError::BadRequest(
+            "budget amount must be positive".to_string(),
+        ));
+    }
+    let _guard = state.write_lock.lock().await;
+    let before = state.snapshot().await?;
+    state.db.set_budget(&category, input.amount).await?;
+    state.save(before).await?;
+    Ok(Json(Budget {
+        category,
+        amount: input.amount,
+    }))
+}
+
+async fn delete_budget(
+    State(state): State<Arc<ServerState>>,
+    category: PathInput<String>,
+) -> ApiResult<StatusCode> {
+    let Path(category) = category?;
+    let _guard = state.write_lock.lock().await;
+    let before = state.snapshot().await?;
+    state.db.delete_budget(&category).await?;
+    state.save(before).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Method;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret";
+
+    async fn app() -> Router {
+        let db = Database::new().await.unwrap();
+        router(Arc::new(ServerState::new(
+            db,
+            TOKEN.to_string(),
+            None,
+            None,
+        )))
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN));
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, value)
+    }
+
+    fn lunch(day: u32, amount: f64) -> Value {
+        json!({
+            "date": format!("2024-03-{:02}", day),
+            "name": "Lunch",
+            "category": "Food",
+            "amount": amount,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let app = app().await;
+        let request = Request::builder()
+            .uri("/expenses")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::builder()
+            .uri("/expenses")
+            .header(header::AUTHORIZATION, "Bearer wrong")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let app = app().await;
+        let (status, created) = send(&app, Method::POST, "/expenses", Some(lunch(1, 9.5))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let id = created["id"].as_i64().unwrap();
+        assert_eq!(created["status"], "pending");
+
+        let uri = format!("/expenses/{}", id);
+        let (status, updated) = send(&app, Method::PUT, &uri, Some(lunch(2, 11.0))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["amount"], 11.0);
+        assert_eq!(updated["uid"], created["uid"]);
+
+        let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = send(&app, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "not found");
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors() {
+        let app = app().await;
+        let (status, body) = send(&app, Method::POST, "/expenses", Some(lunch(1, -3.0))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "Amount must be positive");
+
+        let (status, _) = send(&app, Method::GET, "/summary?month=2024-13", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_rejections_are_json() {
+        let app = app().await;
+        let (status, body) = send(&app, Method::GET, "/expenses/abc", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("parse"));
+
+        let (status, body) = send(&app, Method::GET, "/expenses?limit=many", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            "/expenses",
+            Some(json!({ "name": "Lunch" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().contains("missing field"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_save_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("ledger.json");
+        let db = Database::new().await.unwrap();
+        let state = Arc::new(ServerState::new(
+            db,
+            TOKEN.to_string(),
+            Some(file.to_str().unwrap().to_string()),
+            None,
+        ));
+        let app = router(state.clone());
+        let (status, created) = send(&app, Method::POST, "/expenses", Some(lunch(1, 9.5))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let uri = format!("/expenses/{}", created["id"]);
+
+        // Saving fails once the directory is gone
+        dir.close().unwrap();
+        let (status, body) = send(&app, Method::POST, "/expenses", Some(lunch(2, 3.0))).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body["error"].as_str().unwrap().starts_with("can't save"));
+        let (status, _) = send(&app, Method::PUT, &uri, Some(lunch(1, 20.0))).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            "/budgets/Food",
+            Some(json!({ "amount": 200.0 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let (_, page) = send(&app, Method::GET, "/expenses", None).await;
+        assert_eq!(page["items"], json!([created]));
+        let (_, budgets) = send(&app, Method::GET, "/budgets", None).await;
+        assert_eq!(budgets, json!([]));
+        let audit = state.db.list_audit().await.unwrap();
+        assert_eq!(audit.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_locked_expense_is_a_conflict() {
+        let db = Database::new().await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let mut expense = Expense::new(date, "Rent", "Home", 900.0).unwrap();
+        expense.account = "Bank".to_string();
+        expense.status = crate::expense::ExpenseStatus::Cleared;
+        let id = db.insert_expense(&expense).await.unwrap();
+        db.finish_reconciliation("Bank", date).await.unwrap();
+        let app = router(Arc::new(ServerState::new(
+            db,
+            TOKEN.to_string(),
+            None,
+            None,
+        )));
+
+        let (status, _) = send(&app, Method::DELETE, &format!("/expenses/{}", id), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_pagination_and_filters() {
+        let app = app().await;
+        for day in 1..=5 {
+            send(
+                &app,
+                Method::POST,
+                "/expenses",
+                Some(lunch(day, day as f64)),
+            )
+            .await;
+        }
+        let mut other = lunch(6, 40.0);
+        other["category"] = json!("Travel");
+        send(&app, Method::POST, "/expenses", Some(other)).await;
+
+        let (_, page) = send(&app, Method::GET, "/expenses?offset=2&limit=2", None).await;
+        assert_eq!(page["total"], 6);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(page["items"][0]["amount"], 3.0);
+
+        let (_, page) = send(&app, Method::GET, "/expenses?category=Travel", None).await;
+        assert_eq!(page["total"], 1);
+
+        let (_, page) = send(
+            &app,
+            Method::GET,
+            "/expenses?start=2024-03-02&end=2024-03-03",
+            None,
+        )
+        .await;
+        assert_eq!(page["total"], 2);
+
+        let (status, summary) = send(&app, Method::GET, "/summary?month=2024-03", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(summary["total"], 55.0);
+        assert_eq!(summary["count"], 6);
+    }
+
+    /// Sends a raw HTTP/1.1 request over TCP and returns the status code and
+    /// body.
+    async fn http(addr: std::net::SocketAddr, request: &str) -> (u16, String) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, body)| body.to_string())
+            .unwrap_or_default();
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_serves_over_tcp() {
+        let db = Database::new().await.unwrap();
+        let state = Arc::new(ServerState::new(db, TOKEN.to_string(), None, None));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_on(listener, state));
+
+        let body = lunch(1, 9.5).to_string();
+        let (status, created) = http(
+            addr,
+            &format!(
+                "POST /expenses HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\
+                 Content-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                TOKEN,
+                body.len(),
+                body
+            ),
+        )
+        .await;
+        assert_eq!(status, 201);
+        assert!(created.contains("\"name\":\"Lunch\""));
+
+        let (status, page) = http(
+            addr,
+            &format!(
+                "GET /expenses HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\
+                 Connection: close\r\n\r\n",
+                TOKEN
+            ),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let page: Value = serde_json::from_str(&page).unwrap();
+        assert_eq!(page["total"], 1);
+
+        let (status, _) = http(
+            addr,
+            "GET /expenses HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer \r\n\
+             Connection: close\r\n\r\n",
+        )
+        .await;
+        assert_eq!(status, 401);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_empty_token() {
+        let db = Database::new().await.unwrap();
+        let state = Arc::new(ServerState::new(db, " ".to_string(), None, None));
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let err = serve(addr, state).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_budgets() {
+        let app = app().await;
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            "/budgets/Food",
+            Some(json!({ "amount": 200.0 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, budgets) = send(&app, Method::GET, "/budgets", None).await;
+        assert_eq!(budgets, json!([{ "category": "Food", "amount": 200.0 }]));
+
+        let (status, _) = send(&app, Method::DELETE, "/budgets/Food", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, budgets) = send(&app, Method::GET, "/budgets", None).await;
+        assert_eq!(budgets, json!([]));
+    }
+}
@@ -0,0 +1,230 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::budget::BudgetStatus;
+use crate::db::Database;
+use crate::expense::Expense;
+use crate::period::{month_key, previous_month, Month};
+
+/// How many months the month-over-month comparison looks back, including the
+/// month being summarised.
+pub const HISTORY_MONTHS: usize = 6;
+pub const TOP_MERCHANTS: i64 = 5;
+pub const LARGEST_EXPENSES: i64 = 5;
+
+/// Total spent in one category during one calendar month (`YYYY-MM`).
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct CategoryMonthTotal {
+    pub month: String,
+    pub category: String,
+    pub total: f64,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub total: f64,
+    pub count: i64,
+}
+
+/// Spending grouped by expense name, which is where the merchant ends up.
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct MerchantTotal {
+    pub name: String,
+    pub total: f64,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct MonthTotal {
+    pub month: String,
+    pub total: f64,
+}
+
+/// Total spent on one day (`YYYY-MM-DD`).
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct DayTotal {
+    pub date: String,
+    pub total: f64,
+}
+
+/// Everything the reports need to describe one calendar month.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MonthlySummary {
+    pub year: i32,
+    pub month: u32,
+    pub total: f64,
+    pub count: usize,
+    pub previous_total: f64,
+    pub categories: Vec<CategoryTotal>,
+    pub top_merchants: Vec<MerchantTotal>,
+    /// Oldest first, ending with the summarised month. Months without any
+    /// expenses are included with a zero total.
+    pub history: Vec<MonthTotal>,
+}
+
+impl MonthlySummary {
+    pub async fn load(db: &Database, month: Month) -> Result<Self, sqlx::Error> {
+        let (start, end) = month.bounds();
+        let (year, month) = (month.year(), month.month());
+        let categories = db.category_totals(start, end).await?;
+        let top_merchants = db.top_merchants(start, end, TOP_MERCHANTS).await?;
+        let totals = db.monthly_totals().await?;
+
+        let mut keys = vec![month_key(year, month)];
+        let (mut y, mut m) = (year, month);
+        while keys.len() < HISTORY_MONTHS {
+            (y, m) = previous_month(y, m);
+            keys.push(month_key(y, m));
+        }
+        keys.reverse();
+
+        let history: Vec<MonthTotal> = keys
+            .into_iter()
+            .map(|key| {
+                let total = totals
+                    .iter()
+                    .find(|t| t.month == key)
+                    .map_or(0.0, |t| t.total);
+                MonthTotal { month: key, total }
+            })
+            .collect();
+
+        Ok(MonthlySummary {
+            year,
+            month,
+            total: categories.iter().map(|c| c.total).sum(),
+            count: categories.iter().map(|c| c.count as usize).sum(),
+            previous_total: history[history.len() - 2].total,
+            categories,
+            top_merchants,
+            history,
+        })
+    }
+
+    pub fn month_key(&self) -> String {
+        month_key(self.year, self.month)
+    }
+
+    /// Change against the previous month as a percentage, `None` when the
+    /// previous month had no spending to compare against.
+    pub fn change_from_previous(&self) -> Option<f64> {
+        if self.previous_total == 0.0 {
+            None
+        } else {
+            Some((self.total - self.previous_total) / self.previous_total * 100.0)
+        }
+    }
+}
+
+/// Spending on each day of the month, the first day first and zero for days
+/// without expenses.
+pub async fn daily_spending(db: &Database, month: Month) -> Result<Vec<f64>, sqlx::Error> {
+    let (start, end) = month.bounds();
+    let totals = db.daily_totals(start, end).await?;
+    Ok(start
+        .iter_days()
+        .take_while(|day| *day <= end)
+        .map(|day| {
+            let key = day.format("%Y-%m-%d").to_string();
+            totals
+                .iter()
+                .find(|t| t.date == key)
+                .map_or(0.0, |t| t.total)
+        })
+        .collect())
+}
+
+/// What the dashboard charts: the month's summary plus its spending day by
+/// day.
+#[derive(Clone, Debug)]
+pub struct DashboardData {
+    pub summary: MonthlySummary,
+    /// One entry per day of the month, zero for days without expenses.
+    pub daily: Vec<f64>,
+}
+
+impl DashboardData {
+    pub async fn load(db: &Database, month: Month) -> Result<Self, sqlx::Error> {
+        let summary = MonthlySummary::load(db, month).await?;
+        let daily = daily_spending(db, month).await?;
+        Ok(Self { summary, daily })
+    }
+
+    /// Running total at the end of each day.
+    pub fn cumulative(&self) -> Vec<f64> {
+        self.daily
+            .iter()
+            .scan(0.0, |total, amount| {
+                *total += amount;
+                Some(*total)
+            })
+            .collect()
+    }
+}
+
+/// Totals for an arbitrary inclusive date range. This is what the TUI shows
+/// above the expense list and what the text reports are rendered from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PeriodSummary {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub total: f64,
+    pub count: usize,
+    pub daily_average: f64,
+    pub categories: Vec<CategoryTotal>,
+    /// Monthly budgets scaled to the number of calendar months the period
+    /// touches.
+    pub budgets: Vec<BudgetStatus>,
+    pub largest: Vec<Expense>,
+}
+
+impl PeriodSummary {
+    pub async fn load(
+        db: &Database,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Self, sqlx::Error> {
+        let categories = db.category_totals(start, end).await?;
+        let largest = db.largest_expenses(start, end, LARGEST_EXPENSES).await?;
+        let months = months_spanned(start, end) as f64;
+
+        let budgets = db
+            .list_budgets()
+            .await?
+            .into_iter()
+            .map(|budget| BudgetStatus {
+                spent: categories
+                    .iter()
+                    .find(|c| c.category == budget.category)
+                    .map_or(0.0, |c| c.total),
+                limit: budget.amount * months,
+                category: budget.category,
+            })
+            .collect();
+
+        let total: f64 = categories.iter().map(|c| c.total).sum();
+        let days = (end - start).num_days() + 1;
+        Ok(PeriodSummary {
+            start,
+            end,
+            total,
+            count: categories.iter().map(|c| c.count as usize).sum(),
+            daily_average: if days > 0 { total / days as f64 } else { 0.0 },
+            categories,
+            budgets,
+            largest,
+        })
+    }
+
+    pub async fn for_month(db: &Database, month: Month) -> Result<Self, sqlx::Error> {
+        let (start, end) = month.bounds();
+        Self::load(db, start, end).await
+    }
+}
+
+fn months_spanned(start: NaiveDate, end: NaiveDate) -> i32 {
+    let months = (end.year() - start.year()) * 12 + end.month() as i32 - start.month() as i32 + 1;
+    months.max(1)
+}
@@ -0,0 +1,501 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::audit::AuditEntry;
+use crate::budget::Budget;
+use crate::crypto::Key;
+use crate::db::{read_data_file, Database};
+use crate::expense::Expense;
+
+/// One expense that was changed on both sides in ways that can't be combined,
+/// e.g. the amount edited differently on each machine, or edited on one and
+/// deleted on the other. `None` means the expense doesn't exist on that side.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub base: Option<Expense>,
+    pub ours: Option<Expense>,
+    pub theirs: Option<Expense>,
+}
+
+impl Conflict {
+    pub fn uid(&self) -> &str {
+        [&self.ours, &self.theirs, &self.base]
+            .into_iter()
+            .flatten()
+            .map(|expense| expense.uid.as_str())
+            .next()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+}
+
+/// Result of a three-way merge: everything that merged cleanly, plus the
+/// conflicts still waiting for a decision.
+#[derive(Clone, Debug, Default)]
+pub struct MergeOutcome {
+    pub merged: Vec<Expense>,
+    pub conflicts: Vec<Conflict>,
+    /// The other copy's audit log, added to ours when the merge is applied.
+    pub audit: Vec<AuditEntry>,
+    /// The other copy's trash, added to ours when the merge is applied.
+    pub trash: Vec<Expense>,
+    pub budgets: Vec<Budget>,
+}
+
+impl MergeOutcome {
+    /// The final list of expenses once every conflict has a resolution.
+    pub fn resolve(&self, resolutions: &[Resolution]) -> Vec<Expense> {
+        let mut expenses = self.merged.clone();
+        for (conflict, resolution) in self.conflicts.iter().zip(resolutions) {
+            let chosen = match resolution {
+                Resolution::Ours => &conflict.ours,
+                Resolution::Theirs => &conflict.theirs,
+            };
+            expenses.extend(chosen.clone());
+        }
+        expenses
+    }
+}
+
+/// Merges two copies of a ledger that diverged from `base`, matching expenses
+/// by uid. A change made on only one side is taken as is; changes on both
+/// sides are combined field by field, and only count as a conflict when the
+/// same field was changed to different values.
+pub fn merge(base: &[Expense], ours: &[Expense], theirs: &[Expense]) -> MergeOutcome {
+    let by_uid = |expenses: &[Expense]| -> HashMap<String, Expense> {
+        expenses
+            .iter()
+            .map(|expense| (expense.uid.clone(), expense.clone()))
+            .collect()
+    };
+    let (base_map, ours_map, theirs_map) = (by_uid(base), by_uid(ours), by_uid(theirs));
+
+    // Our order first, then anything only the other side has
+    let mut seen = HashSet::new();
+    let uids: Vec<&String> = ours
+        .iter()
+        .chain(theirs)
+        .chain(base)
+        .map(|expense| &expense.uid)
+        .filter(|uid| seen.insert(*uid))
+        .collect();
+
+    let mut outcome = MergeOutcome::default();
+    for uid in uids {
+        let base = base_map.get(uid);
+        let ours = ours_map.get(uid);
+        let theirs = theirs_map.get(uid);
+        match merge_one(base, ours, theirs) {
+            Ok(merged) => outcome.merged.extend(merged),
+            Err(()) => outcome.conflicts.push(Conflict {
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            }),
+        }
+    }
+    outcome
+}
+
+/// Merges budgets by category the same way. Budgets can't conflict: when
+/// both sides changed one, ours is kept.
+pub fn merge_budgets(base: &[Budget], ours: &[Budget], theirs: &[Budget]) -> Vec<Budget> {
+    let amount = |budgets: &[Budget], category: &str| {
+        budgets
+            .iter()
+            .find(|budget| budget.category == category)
+            .map(|budget| budget.amount)
+    };
+    let mut categories: Vec<&str> = ours
+        .iter()
+        .chain(theirs)
+        .chain(base)
+        .map(|budget| budget.category.as_str())
+        .collect();
+    categories.sort();
+    categories.dedup();
+
+    categories
+        .into_iter()
+        .filter_map(|category| {
+            let ours = amount(ours, category);
+            let merged = if amount(base, category) == ours {
+                amount(theirs, category)
+            } else {
+                ours
+            };
+            merged.map(|amount| Budget {
+                category: category.to_string(),
+                amount,
+            })
+        })
+        .collect()
+}
+
+fn merge_one(
+    base: Option<&Expense>,
+    ours: Option<&Expense>,
+    theirs: Option<&Expense>,
+) -> Result<Option<Expense>, ()> {
+    let same = |a: Option<&Expense>, b: Option<&Expense>| match (a, b) {
+        (Some(a), Some(b)) => same_content(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if same(ours, theirs) {
+        return Ok(newest(ours, theirs).cloned());
+    }
+    if same(base, ours) {
+        return Ok(theirs.cloned());
+    }
+    if same(base, theirs) {
+        return Ok(ours.cloned());
+    }
+    match (base, ours, theirs) {
+        (Some(base), Some(ours), Some(theirs)) => merge_fields(base, ours, theirs).map(Some),
+        _ => Err(()),
+    }
+}
+
+fn newest<'a>(ours: Option<&'a Expense>, theirs: Option<&'a Expense>) -> Option<&'a Expense> {
+    match (ours, theirs) {
+        (Some(o), Some(t)) if t.modified_at > o.modified_at => Some(t),
+        _ => ours.or(theirs),
+    }
+}
+
+/// Everything the user can change; the local row id and the modification
+/// time don't count.
+fn same_content(a: &Expense, b: &Expense) -> bool {
+    a.date == b.date
+        && a.name == b.name
+        && a.category == b.category
+        && a.amount == b.amount
+        && a.external_id == b.external_id
+        && a.account == b.account
+        && a.notes == b.notes
+        && a.tags == b.tags
+        && a.status == b.status
+}
+
+fn merge_fields(base: &Expense, ours: &Expense, theirs: &Expense) -> Result<Expense, ()> {
+    let mut merged = ours.clone();
+    macro_rules! field {
+        ($name:ident) => {
+            if ours.$name != theirs.$name {
+                if ours.$name == base.$name {
+                    merged.$name = theirs.$name.clone();
+                } else if theirs.$name != base.$name {
+                    return Err(());
+                }
+            }
+        };
+    }
+    field!(date);
+    field!(name);
+    field!(category);
+    field!(amount);
+    field!(external_id);
+    field!(account);
+    field!(notes);
+    field!(tags);
+    field!(status);
+    merged.modified_at = ours.modified_at.clone().max(theirs.modified_at.clone());
+    Ok(merged)
+}
+
+/// Merges the database with the copy in `other_file`, using the snapshot in
+/// `base_file` from the last sync as the common ancestor. Missing files count
+/// as empty, so the first sync simply combines both copies. Encrypted files
+/// are opened with `passphrase`.
+pub async fn prepare(
+    db: &Database,
+    base_file: &str,
+    other_file: &str,
+    passphrase: Option<&str>,
+) -> Result<MergeOutcome, Box<dyn std::error::Error>> {
+    let base = read_data_file(base_file, passphrase)?.unwrap_or_default();
+    let other = read_data_file(other_file, passphrase)?.unwrap_or_default();
+    // Whatever is in the trash counts as deleted
+    let (trash, theirs): (Vec<Expense>, Vec<Expense>) = other
+        .expenses
+        .into_iter()
+        .partition(|expense| expense.deleted_at.is_some());
+    let base_expenses: Vec<Expense> = base
+        .expenses
+        .into_iter()
+        .filter(|expense| expense.deleted_at.is_none())
+        .collect();
+    let ours = db.list_expenses().await?;
+    Ok(MergeOutcome {
+        audit: other.audit,
+        trash,
+        budgets: merge_budgets(&base.budgets, &db.list_budgets().await?, &other.budgets),
+        ..merge(&base_expenses, &ours, &theirs)
+    })
+}
+
+/// Stores the merge with every conflict resolved and writes the result to
+/// both the other copy and the base snapshot, so the next sync starts from
+/// this common state. The other copy's audit log and trash are kept too.
+pub async fn apply(
+    db: &Database,
+    outcome: &MergeOutcome,
+    resolutions: &[Resolution],
+    base_file: &str,
+    other_file: &str,
+    key: Option<&Key>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Both copies end up with the changes either of them recorded
+    db.add_audit_entries(&outcome.audit).await?;
+    db.replace_expenses(&outcome.resolve(resolutions), &outcome.trash)
+        .await?;
+    db.replace_budgets(&outcome.budgets).await?;
+    db.save_to_file(other_file, key).await?;
+    db.save_to_file(base_file, key).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::read_expenses_file;
+    use crate::test_support::expense;
+
+    /// An expense with a known uid, last changed at a known time.
+    fn synced(uid: &str, name: &str, amount: f64) -> Expense {
+        Expense {
+            uid: uid.to_string(),
+            modified_at: "2023-07-01T00:00:00.000Z".to_string(),
+            ..expense("2023-07-01", name, "Food", amount)
+        }
+    }
+
+    fn names(expenses: &[Expense]) -> Vec<&str> {
+        let mut names: Vec<&str> = expenses.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_one_sided_changes_merge() {
+        let base = vec![synced("a", "Lunch", 10.0), synced("b", "Rent", 900.0)];
+        let mut ours = base.clone();
+        ours.push(synced("c", "Cinema", 15.0));
+        ours[0].amount = 12.0;
+        let mut theirs = base.clone();
+        theirs.remove(1);
+        theirs.push(synced("d", "Taxi", 20.0));
+
+        let outcome = merge(&base, &ours, &theirs);
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(names(&outcome.merged), ["Cinema", "Lunch", "Taxi"]);
+        let lunch = outcome.merged.iter().find(|e| e.uid == "a").unwrap();
+        assert_eq!(lunch.amount, 12.0);
+    }
+
+    #[test]
+    fn test_different_fields_combine() {
+        let base = vec![synced("a", "Lunch", 10.0)];
+        let mut ours = base.clone();
+        ours[0].amount = 12.0;
+        let mut theirs = base.clone();
+        theirs[0].category = "Work".to_string();
+        theirs[0].modified_at = "2023-07-02T00:00:00.000Z".to_string();
+
+        let outcome = merge(&base, &ours, &theirs);
+        assert!(outcome.conflicts.is_empty());
+        let merged = &outcome.merged[0];
+        assert_eq!((merged.amount, merged.category.as_str()), (12.0, "Work"));
+        assert_eq!(merged.modified_at, "2023-07-02T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let base = vec![synced("a", "Lunch", 10.0), synced("b", "Rent", 900.0)];
+        let mut ours = base.clone();
+        ours[0].amount = 12.0;
+        ours[1].name = "Rent July".to_string();
+        let mut theirs = base.clone();
+        theirs[0].amount = 11.0;
+        theirs.remove(1);
+
+        let outcome = merge(&base, &ours, &theirs);
+        assert!(outcome.merged.is_empty());
+        assert_eq!(outcome.conflicts.len(), 2);
+        assert_eq!(outcome.conflicts[0].uid(), "a");
+        assert!(outcome.conflicts[1].theirs.is_none());
+
+        let resolved = outcome.resolve(&[Resolution::Theirs, Resolution::Theirs]);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].amount, 11.0);
+    }
+
+    #[test]
+    fn test_merge_budgets() {
+        let budget = |category: &str, amount: f64| Budget {
+            category: category.to_string(),
+            amount,
+        };
+        let base = vec![budget("Food", 200.0), budget("Rent", 900.0)];
+        let ours = vec![budget("Food", 250.0), budget("Rent", 900.0)];
+        let theirs = vec![budget("Food", 300.0), budget("Travel", 100.0)];
+        assert_eq!(
+            merge_budgets(&base, &ours, &theirs),
+            [budget("Food", 250.0), budget("Travel", 100.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_keeps_their_budgets_and_trash() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base_file = dir.path().join("base.json").to_string_lossy().into_owned();
+        let other_file = dir.path().join("other.json").to_string_lossy().into_owned();
+
+        let other = Database::new().await.unwrap();
+        other.set_budget("Travel", 100.0).await.unwrap();
+        let taxi = other
+            .insert_expense(&synced("b", "Taxi", 20.0))
+            .await
+            .unwrap();
+        other.delete_expense(taxi).await.unwrap();
+        other.save_to_file(&other_file, None).await.unwrap();
+
+        let db = Database::new().await.unwrap();
+        db.set_budget("Food", 200.0).await.unwrap();
+        db.insert_expense(&synced("a", "Lunch", 10.0))
+            .await
+            .unwrap();
+        let outcome = prepare(&db, &base_file, &other_file, None).await.unwrap();
+        apply(&db, &outcome, &[], &base_file, &other_file, None)
+            .await
+            .unwrap();
+
+        let written = read_data_file(&other_file, None).unwrap().unwrap();
+        let categories: Vec<&str> = written
+            .budgets
+            .iter()
+            .map(|b| b.category.as_str())
+            .collect();
+        assert_eq!(categories, ["Food", "Travel"]);
+        assert_eq!(names(&written.expenses), ["Lunch", "Taxi"]);
+        assert_eq!(names(&db.list_trash().await.unwrap()), ["Taxi"]);
+        assert_eq!(names(&db.list_expenses().await.unwrap()), ["Lunch"]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_through_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base_file = dir.path().join("base.json").to_string_lossy().into_owned();
+        let other_file = dir.path().join("other.json").to_string_lossy().into_owned();
+
+        let db = Database::new().await.unwrap();
+        db.insert_expense(&synced("a", "Lunch", 10.0))
+            .await
+            .unwrap();
+
+        // First sync: nothing to compare against, both copies are combined
+        let outcome = prepare(&db, &base_file, &other_file, None).await.unwrap();
+        assert!(outcome.conflicts.is_empty());
+        apply(&db, &outcome, &[], &base_file, &other_file, None)
+            .await
+            .unwrap();
+
+        // The other machine adds an expense; ours deletes the lunch
+        let other = Database::new().await.unwrap();
+        other.load_expenses_from_file(&other_file).await.unwrap();
+        other
+            .insert_expense(&synced("b", "Taxi", 20.0))
+            .await
+            .unwrap();
+        other.save_expenses_to_file(&other_file).await.unwrap();
+        let lunch = db.list_expenses().await.unwrap()[0].id.unwrap();
+        assert_eq!(lunch, 1, "applying a merge keeps the ids");
+        db.delete_expense(lunch).await.unwrap();
+
+        let outcome = prepare(&db, &base_file, &other_file, None).await.unwrap();
+        assert!(outcome.conflicts.is_empty());
+        apply(&db, &outcome, &[], &base_file, &other_file, None)
+            .await
+            .unwrap();
+        assert_eq!(names(&db.list_expenses().await.unwrap()), ["Taxi"]);
+        assert_eq!(
+            names(&read_expenses_file(&other_file, None).unwrap()),
+            ["Taxi"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_copies_migrated_separately() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let legacy_file = dir
+            .path()
+            .join("legacy.json")
+            .to_string_lossy()
+            .into_owned();
+        let base_file = dir.path().join("base.json").to_string_lossy().into_owned();
+        let other_file = dir.path().join("other.json").to_string_lossy().into_owned();
+
+        // A file from before uids, with two identical coffees
+        let legacy: Vec<Expense> = [("Coffee", 3.0), ("Coffee", 3.0), ("Lunch", 10.0)]
+            .into_iter()
+            .map(|(name, amount)| Expense {
+                uid: String::new(),
+                ..expense("2023-07-01", name, "Food", amount)
+            })
+            .collect();
+        std::fs::write(&legacy_file, serde_json::to_vec(&legacy).unwrap()).unwrap();
+
+        // Each machine migrates its own copy
+        let db = Database::new().await.unwrap();
+        db.load_expenses_from_file(&legacy_file).await.unwrap();
+        let other = Database::new().await.unwrap();
+        other.load_expenses_from_file(&legacy_file).await.unwrap();
+        other.save_to_file(&other_file, None).await.unwrap();
+
+        let outcome = prepare(&db, &base_file, &other_file, None).await.unwrap();
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(names(&outcome.merged), ["Coffee", "Coffee", "Lunch"]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_encrypted_copies() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base_file = dir.path().join("base.json").to_string_lossy().into_owned();
+        let other_file = dir.path().join("other.json").to_string_lossy().into_owned();
+
+        // Each copy was encrypted on its own machine, so with its own salt
+        let other = Database::new().await.unwrap();
+        other
+            .insert_expense(&synced("b", "Taxi", 20.0))
+            .await
+            .unwrap();
+        let their_key = Key::new("correct horse").unwrap();
+        other
+            .save_to_file(&other_file, Some(&their_key))
+            .await
+            .unwrap();
+
+        let db = Database::new().await.unwrap();
+        db.insert_expense(&synced("a", "Lunch", 10.0))
+            .await
+            .unwrap();
+        let key = Key::new("correct horse").unwrap();
+        assert!(prepare(&db, &base_file, &other_file, None).await.is_err());
+        let outcome = prepare(&db, &base_file, &other_file, Some("correct horse"))
+            .await
+            .unwrap();
+        apply(&db, &outcome, &[], &base_file, &other_file, Some(&key))
+            .await
+            .unwrap();
+        assert_eq!(
+            names(&read_expenses_file(&other_file, Some("correct horse")).unwrap()),
+            ["Lunch", "Taxi"]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::expense::Expense;
+
+/// Space between table columns.
+pub const COLUMN_SPACING: u16 = 1;
+
+/// A column of the expense table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Status,
+    Date,
+    Name,
+    Category,
+    Amount,
+    Account,
+    Notes,
+    Tags,
+}
+
+impl Column {
+    pub const DEFAULT_VISIBLE: &'static [Column] = &[
+        Column::Status,
+        Column::Date,
+        Column::Name,
+        Column::Category,
+        Column::Amount,
+    ];
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::Status => "S",
+            Column::Date => "Date",
+            Column::Name => "Name",
+            Column::Category => "Category",
+            Column::Amount => "Amount",
+            Column::Account => "Account",
+            Column::Notes => "Notes",
+            Column::Tags => "Tags",
+        }
+    }
+
+    pub fn default_width(&self) -> u16 {
+        match self {
+            Column::Status => 1,
+            Column::Date => 12,
+            Column::Name => 20,
+            Column::Category => 15,
+            Column::Amount => 12,
+            Column::Account => 12,
+            Column::Notes => 24,
+            Column::Tags => 16,
+        }
+    }
+
+    /// Numbers line up on the right.
+    pub fn right_aligned(&self) -> bool {
+        *self == Column::Amount
+    }
+
+    /// The cell text for an expense, formatted as configured.
+    pub fn cell(&self, expense: &Expense, config: &Config) -> String {
+        match self {
+            Column::Status => expense.status.marker().to_string(),
+            Column::Date => config.display_date(&expense.date),
+            Column::Name => expense.name.clone(),
+            Column::Category => expense.category.clone(),
+            Column::Amount => config.format_amount(expense.amount),
+            Column::Account => expense.account.clone(),
+            Column::Notes => expense.notes.clone(),
+            Column::Tags => expense.tags.clone(),
+        }
+    }
+
+    fn compare(&self, a: &Expense, b: &Expense) -> Ordering {
+        match self {
+            Column::Status => a.status.as_str().cmp(b.status.as_str()),
+            // Stored dates are YYYY-MM-DD, so they sort as text
+            Column::Date => a.date.cmp(&b.date),
+            Column::Name => compare_text(&a.name, &b.name),
+            Column::Category => compare_text(&a.category, &b.category),
+            Column::Amount => a.amount.total_cmp(&b.amount),
+            Column::Account => compare_text(&a.account, &b.account),
+            Column::Notes => compare_text(&a.notes, &b.notes),
+            Column::Tags => compare_text(&a.tags, &b.tags),
+        }
+    }
+}
+
+fn compare_text(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// How the expense table is ordered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sort {
+    pub column: Column,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            column: Column::Date,
+            descending: false,
+        }
+    }
+}
+
+impl Sort {
+    /// Sorts by the column, falling back to the id so equal rows keep a
+    /// stable order in both directions.
+    pub fn apply(&self, expenses: &mut [Expense]) {
+        expenses.sort_by(|a, b| {
+            let ordering = self.column.compare(a, b).then(a.id.cmp(&b.id));
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    /// Sorting by `column`: ascending at first, the other way round when it
+    /// already is the sort column.
+    pub fn toggled(&self, column: Column) -> Self {
+        Self {
+            column,
+            descending: self.column == column && !self.descending,
+        }
+    }
+
+    /// Header text with an arrow on the sort column.
+    pub fn header(&self, column: Column) -> String {
+        match (self.column == column, self.descending) {
+            (true, false) => format!("{} ▲", column.header()),
+            (true, true) => format!("{} ▼", column.header()),
+            (false, _) => column.header().to_string(),
+        }
+    }
+}
+
+/// Widths of the visible columns, from the config where it has one.
+pub fn column_widths(columns: &[Column], configured: &BTreeMap<Column, u16>) -> Vec<u16> {
+    columns
+        .iter()
+        .map(|column| {
+            configured
+                .get(column)
+                .copied()
+                .unwrap_or(column.default_width())
+        })
+        .collect()
+}
+
+/// The column under `x` when the table's columns start at `left`.
+pub fn column_at(columns: &[Column], widths: &[u16], left: u16, x: u16) -> Option<Column> {
+    let mut start = left;
+    for (column, width) in columns.iter().zip(widths) {
+        // A click on the gap after a column still counts for that column
+        let end = start + width + COLUMN_SPACING;
+        if (start..end).contains(&x) {
+            return Some(*column);
+        }
+        start = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::stored;
+
+    fn ids(expenses: &[Expense]) -> Vec<i64> {
+        expenses.iter().filter_map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut expenses = vec![
+            stored(1, "2024-05-03", "bakery", "Food", 5.0),
+            stored(2, "2024-05-01", "Cafe", "Food", 12.5),
+            stored(3, "2024-05-01", "Apples", "Food", 2.0),
+        ];
+        Sort::default().apply(&mut expenses);
+        assert_eq!(ids(&expenses), vec![2, 3, 1]);
+
+        let sort = Sort::default().toggled(Column::Name);
+        sort.apply(&mut expenses);
+        assert_eq!(ids(&expenses), vec![3, 1, 2]);
+
+        let sort = sort.toggled(Column::Name);
+        assert!(sort.descending);
+        assert_eq!(sort.header(Column::Name), "Name ▼");
+        sort.apply(&mut expenses);
+        assert_eq!(ids(&expenses), vec![2, 1, 3]);
+
+        Sort::default().toggled(Column::Amount).apply(&mut expenses);
+        assert_eq!(ids(&expenses), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_column_at() {
+        let columns = [Column::Date, Column::Name, Column::Amount];
+        let configured = BTreeMap::from([(Column::Name, 10)]);
+        let widths = column_widths(&columns, &configured);
+        assert_eq!(widths, vec![12, 10, 12]);
+        assert_eq!(column_at(&columns, &widths, 1, 1), Some(Column::Date));
+        assert_eq!(column_at(&columns, &widths, 1, 14), Some(Column::Name));
+        assert_eq!(column_at(&columns, &widths, 1, 24), Some(Column::Name));
+        assert_eq!(column_at(&columns, &widths, 1, 25), Some(Column::Amount));
+        assert_eq!(column_at(&columns, &widths, 1, 40), None);
+    }
+}
@@ -0,0 +1,19 @@
+//! Expenses for the unit tests.
+
+use chrono::NaiveDate;
+
+use crate::expense::Expense;
+
+/// A valid expense on `date`, given as `YYYY-MM-DD`.
+pub fn expense(date: &str, name: &str, category: &str, amount: f64) -> Expense {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+    Expense::new(date, name, category, amount).unwrap()
+}
+
+/// Like [`expense`], as if it had been stored under `id`.
+pub fn stored(id: i64, date: &str, name: &str, category: &str, amount: f64) -> Expense {
+    Expense {
+        id: Some(id),
+        ..expense(date, name, category, amount)
+    }
+}
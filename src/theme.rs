@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+pub const BUILT_IN_THEMES: &[&str] = &["dark", "light", "high-contrast", "monochrome"];
+
+/// Styles for the things the TUI highlights, so that no widget picks its own
+/// colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// The heading at the top.
+    pub title: Style,
+    /// The highlighted row of a list.
+    pub selected: Style,
+    /// The form field being typed into.
+    pub focused_input: Style,
+    /// Something that needs attention, like an unbalanced reconciliation.
+    pub warning: Style,
+    /// Spending over a budget.
+    pub over_budget: Style,
+    /// Negative amounts, i.e. money coming in.
+    pub income: Style,
+    /// Something that is fine, like a balanced reconciliation.
+    pub success: Style,
+    /// Less important text such as the footer.
+    pub muted: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            title: Style::default().fg(Color::Cyan),
+            selected: Style::default().bg(Color::DarkGray),
+            focused_input: Style::default().fg(Color::Yellow),
+            warning: Style::default().fg(Color::Yellow),
+            over_budget: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            income: Style::default().fg(Color::Green),
+            success: Style::default().fg(Color::Green),
+            muted: Style::default().fg(Color::Gray),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            title: Style::default().fg(Color::Blue),
+            selected: Style::default().fg(Color::Black).bg(Color::Gray),
+            focused_input: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            warning: Style::default().fg(Color::Magenta),
+            over_budget: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            income: Style::default().fg(Color::Green),
+            success: Style::default().fg(Color::Green),
+            muted: Style::default().fg(Color::DarkGray),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        let bold = Modifier::BOLD;
+        Self {
+            title: Style::default().fg(Color::White).add_modifier(bold),
+            selected: Style::default()
+                .fg(Color::Black)
+                .bg(Color::White)
+                .add_modifier(bold),
+            focused_input: Style::default().fg(Color::Black).bg(Color::Yellow),
+            warning: Style::default().fg(Color::LightYellow).add_modifier(bold),
+            over_budget: Style::default()
+                .fg(Color::LightRed)
+                .add_modifier(bold | Modifier::UNDERLINED),
+            income: Style::default().fg(Color::LightGreen).add_modifier(bold),
+            success: Style::default().fg(Color::LightGreen).add_modifier(bold),
+            muted: Style::default().fg(Color::White),
+        }
+    }
+
+    /// No colors at all, only bold, underline and the like. Used for
+    /// `NO_COLOR`.
+    pub fn monochrome() -> Self {
+        Self {
+            title: Style::default().add_modifier(Modifier::BOLD),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+            focused_input: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            warning: Style::default().add_modifier(Modifier::BOLD),
+            over_budget: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            income: Style::default().add_modifier(Modifier::ITALIC),
+            success: Style::default().add_modifier(Modifier::BOLD),
+            muted: Style::default().add_modifier(Modifier::DIM),
+        }
+    }
+
+    pub fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// The theme called `name`, either built in or one of the user's. With
+    /// `no_color` set the monochrome theme is used whatever the name.
+    pub fn load(
+        name: &str,
+        themes: &BTreeMap<String, ThemeConfig>,
+        no_color: bool,
+    ) -> Result<Self, ThemeError> {
+        let theme = Self::resolve(name, themes)?;
+        Ok(if no_color { Self::monochrome() } else { theme })
+    }
+
+    fn resolve(name: &str, themes: &BTreeMap<String, ThemeConfig>) -> Result<Self, ThemeError> {
+        // User themes shadow built-in ones of the same name
+        let Some(config) = themes.get(name) else {
+            return Self::built_in(name).ok_or_else(|| ThemeError {
+                key: "display.theme".to_string(),
+                message: format!("no theme called '{}'", name),
+            });
+        };
+        let base = config.base.as_deref().unwrap_or("dark");
+        let mut theme = Self::built_in(base).ok_or_else(|| ThemeError {
+            key: format!("themes.{}.base", name),
+            message: format!(
+                "'{}' is not one of the built-in themes ({})",
+                base,
+                BUILT_IN_THEMES.join(", ")
+            ),
+        })?;
+        let roles = [
+            ("title", &config.title, &mut theme.title),
+            ("selected", &config.selected, &mut theme.selected),
+            (
+                "focused_input",
+                &config.focused_input,
+                &mut theme.focused_input,
+            ),
+            ("warning", &config.warning, &mut theme.warning),
+            ("over_budget", &config.over_budget, &mut theme.over_budget),
+            ("income", &config.income, &mut theme.income),
+            ("success", &config.success, &mut theme.success),
+            ("muted", &config.muted, &mut theme.muted),
+        ];
+        for (role, style, target) in roles {
+            if let Some(style) = style {
+                *target = style.to_style().map_err(|(field, message)| ThemeError {
+                    key: format!("themes.{}.{}.{}", name, role, field),
+                    message,
+                })?;
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// Whether the `NO_COLOR` environment variable asks for no colors.
+pub fn no_color_requested() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// A `[themes.<name>]` table. Roles that aren't given come from `base`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Built-in theme to start from, `dark` if not given.
+    pub base: Option<String>,
+    pub title: Option<StyleConfig>,
+    pub selected: Option<StyleConfig>,
+    pub focused_input: Option<StyleConfig>,
+    pub warning: Option<StyleConfig>,
+    pub over_budget: Option<StyleConfig>,
+    pub income: Option<StyleConfig>,
+    pub success: Option<StyleConfig>,
+    pub muted: Option<StyleConfig>,
+}
+
+/// A style in the config, e.g. `{ fg = "yellow", bg = "#202020", bold = true }`.
+/// Colors are names like `lightred`, `#rrggbb` or a 256-color index.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub reversed: bool,
+    pub dim: bool,
+}
+
+impl StyleConfig {
+    /// The style, or the offending field and what's wrong with it.
+    fn to_style(&self) -> Result<Style, (&'static str, String)> {
+        let color = |field, value: &str| {
+            value
+                .parse::<Color>()
+                .map_err(|_| (field, format!("'{}' is not a color", value)))
+        };
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(color("fg", fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(color("bg", bg)?);
+        }
+        let modifiers = [
+            (self.bold, Modifier::BOLD),
+            (self.italic, Modifier::ITALIC),
+            (self.underlined, Modifier::UNDERLINED),
+            (self.reversed, Modifier::REVERSED),
+            (self.dim, Modifier::DIM),
+        ];
+        for (enabled, modifier) in modifiers {
+            if enabled {
+                style = style.add_modifier(modifier);
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// A theme problem in the config: the offending key and what's wrong.
+#[derive(Debug, PartialEq)]
+pub struct ThemeError {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid `{}`: {}", self.key, self.message)
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_themes(contents: &str) -> BTreeMap<String, ThemeConfig> {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn test_built_in_themes() {
+        for name in BUILT_IN_THEMES {
+            assert!(
+                Theme::load(name, &BTreeMap::new(), false).is_ok(),
+                "{}",
+                name
+            );
+        }
+        let err = Theme::load("neon", &BTreeMap::new(), false).unwrap_err();
+        assert_eq!(err.key, "display.theme");
+        // NO_COLOR wins over the configured theme
+        assert_eq!(
+            Theme::load("dark", &BTreeMap::new(), true).unwrap(),
+            Theme::monochrome()
+        );
+    }
+
+    #[test]
+    fn test_user_theme() {
+        let themes = parse_themes(
+            r##"
+            [solar]
+            base = "light"
+            warning = { fg = "#b58900", bold = true }
+            selected = { reversed = true }
+            "##,
+        );
+        let theme = Theme::load("solar", &themes, false).unwrap();
+        assert_eq!(
+            theme.warning,
+            Style::default()
+                .fg(Color::Rgb(0xb5, 0x89, 0x00))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(
+            theme.selected,
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+        assert_eq!(theme.title, Theme::light().title);
+    }
+
+    #[test]
+    fn test_user_theme_errors() {
+        let themes = parse_themes("[bad]\nincome = { fg = \"greenish\" }\n");
+        let err = Theme::load("bad", &themes, false).unwrap_err();
+        assert_eq!(err.key, "themes.bad.income.fg");
+
+        let themes = parse_themes("[loop]\nbase = \"loop\"\n");
+        let err = Theme::load("loop", &themes, false).unwrap_err();
+        assert_eq!(err.key, "themes.loop.base");
+    }
+}
@@ -1,15 +1,40 @@
+use chrono::{Datelike, NaiveDate, Weekday};
 use ratatui::{
-    backend::Backend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    symbols,
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Cell, Chart, Dataset, GraphType, List,
+        ListItem, ListState, Paragraph, Row, Sparkline, Table, TableState, Tabs,
+    },
     Frame,
 };
 
-use crate::app::{App, InputMode};
+use crate::app::{
+    App, BulkMenu, Calendar, DuplicateReview, ExpenseHistory, FilterField, FilterPanel, InputMode,
+    LedgerPicker, MergeReview, PendingDelete, ReconcileField, ReconcileSetup, ReviewKind, Screen,
+    SettingsField, SettingsForm, REPORTS,
+};
+use crate::audit::{AuditAction, AuditEntry};
+use crate::bulk::BulkAction;
+use crate::duplicates::DuplicateAction;
+use crate::expense::Expense;
+use crate::expense::ExpenseStatus;
+use crate::input::TextInput;
+use crate::keymap::{Action, Mode};
+use crate::period::{calendar_weeks, parse_month};
+use crate::reconcile::Reconciliation;
+use crate::recurring::{find_recurring, MIN_MONTHS};
+use crate::summary::DashboardData;
+use crate::sync::Resolution;
+use crate::table::{column_widths, Column, COLUMN_SPACING};
+use crate::theme::Theme;
 
-pub fn ui<B: Backend>(f: &mut Frame, app: &App) {
+pub fn ui(f: &mut Frame, app: &App) {
+    let theme = &app.theme;
+    // Set again below if the expense table is on screen
+    app.table_header.set(None);
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
@@ -23,28 +48,205 @@ pub fn ui<B: Backend>(f: &mut Frame, app: &App) {
         )
         .split(f.size());
 
-    let title = Paragraph::new("Expense Tracker")
-        .style(Style::default().fg(Color::Cyan))
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(title, chunks[0]);
+    let mut heading = "Expense Tracker".to_string();
+    if let Some(ledger) = &app.ledger {
+        heading.push_str(&format!(" — {}", ledger.name));
+        if !app.ledger_settings.description.is_empty() {
+            heading.push_str(&format!(": {}", app.ledger_settings.description));
+        }
+    }
+    let tabs = Tabs::new(Screen::ALL.iter().map(|screen| screen.title()))
+        .select(app.screen.index())
+        .style(theme.muted)
+        .highlight_style(
+            theme
+                .title
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled(heading, theme.title)),
+        );
+    f.render_widget(tabs, chunks[0]);
 
-    if app.adding_expense {
-        render_add_expense_form(f, app, chunks[1]);
+    if let Some(picker) = &app.ledger_picker {
+        render_ledger_picker(f, theme, picker, chunks[1]);
+    } else if let Some(input) = &app.unlock_prompt {
+        let name = app
+            .ledger
+            .as_ref()
+            .map_or("", |ledger| ledger.name.as_str());
+        let title = format!("Passphrase to unlock '{}'", name);
+        render_secret_prompt(f, theme, &title, input, chunks[1]);
+    } else if let Some(change) = &app.passphrase_change {
+        let title = if change.first.is_some() {
+            "Repeat the new passphrase"
+        } else {
+            "New passphrase (empty to disable encryption)"
+        };
+        render_secret_prompt(f, theme, title, &change.input, chunks[1]);
+    } else if let Some(path) = &app.import_path {
+        render_path_prompt(f, theme, "Import file", path, chunks[1]);
+    } else if let Some(path) = &app.sync_path {
+        render_path_prompt(f, theme, "Sync with file", path, chunks[1]);
+    } else if let Some(prompt) = &app.sync_passphrase {
+        let title = format!("Passphrase to open '{}'", prompt.other_file);
+        render_secret_prompt(f, theme, &title, &prompt.input, chunks[1]);
+    } else if let Some(review) = &app.merge_review {
+        render_merge_review(f, theme, review, chunks[1]);
+    } else if let Some(review) = &app.duplicate_review {
+        render_duplicate_review(f, theme, review, chunks[1]);
+    } else if let Some(setup) = &app.reconcile_setup {
+        render_reconcile_setup(f, theme, setup, chunks[1]);
+    } else if let Some(form) = &app.settings_form {
+        render_settings_form(f, theme, form, chunks[1]);
+    } else if let Some(reconciliation) = &app.reconciliation {
+        render_reconciliation(f, theme, reconciliation, chunks[1]);
+    } else if let Some(pending) = &app.pending_delete {
+        render_delete_confirmation(f, app, pending, chunks[1]);
+    } else if let Some(history) = &app.expense_history {
+        render_expense_history(f, app, history, chunks[1]);
+    } else if let Some(menu) = &app.bulk_menu {
+        render_bulk_menu(f, app, menu, chunks[1]);
     } else {
-        render_expense_list(f, app, chunks[1]);
+        match app.screen {
+            Screen::Transactions => {
+                if let Some(panel) = &app.filter_panel {
+                    render_filter_panel(f, app, panel, chunks[1]);
+                } else if app.adding_expense {
+                    render_add_expense_form(f, app, chunks[1]);
+                } else {
+                    render_expense_list(f, app, chunks[1]);
+                }
+            }
+            Screen::Dashboard => match &app.dashboard {
+                Some(data) => render_dashboard(f, app, data, chunks[1]),
+                None => render_placeholder(f, "Dashboard", "Nothing loaded", chunks[1]),
+            },
+            Screen::Calendar => match &app.calendar {
+                Some(calendar) => render_calendar(f, app, calendar, chunks[1]),
+                None => render_placeholder(f, "Calendar", "Nothing loaded", chunks[1]),
+            },
+            Screen::Budgets => render_budgets(f, app, chunks[1]),
+            Screen::Categories => render_categories(f, app, chunks[1]),
+            Screen::Recurring => render_recurring(f, app, chunks[1]),
+            Screen::Reports => render_reports(f, app, chunks[1]),
+            Screen::Trash => render_trash(f, app, chunks[1]),
+            Screen::Settings => render_settings(f, app, chunks[1]),
+        }
     }
 
-    let mut footer_text = "Press 'q' to quit, 'a' to add expense".to_string();
-    if !app.expenses.is_empty() {
-        footer_text.push_str(", 'up/down' to select, 'd' to delete expense");
+    let mut footer_text = app.keymap.help(Mode::List);
+    if let Some(picker) = &app.ledger_picker {
+        footer_text = if picker.new_name.is_some() {
+            "Type a name, 'enter' to create, 'esc' to cancel".to_string()
+        } else if app.ledger.is_some() {
+            "'up/down' to select, 'enter' to open, 'n' for a new ledger, 'esc' to go back"
+                .to_string()
+        } else {
+            "'up/down' to select, 'enter' to open, 'n' for a new ledger, 'q' to quit".to_string()
+        };
+    } else if app.unlock_prompt.is_some() {
+        footer_text = "'enter' to unlock, 'esc' to pick another ledger".to_string();
+    } else if app.passphrase_change.is_some() {
+        footer_text = "'enter' to continue, 'esc' to cancel".to_string();
+    } else if app.import_path.is_some() {
+        footer_text =
+            "Type the path of a JSON file, 'enter' to import, 'esc' to cancel".to_string();
+    } else if app.sync_path.is_some() {
+        footer_text =
+            "Type the path of the other copy, 'enter' to merge, 'esc' to cancel".to_string();
+    } else if app.sync_passphrase.is_some() {
+        footer_text = "'enter' to merge, 'esc' to cancel".to_string();
+    } else if app.merge_review.is_some() {
+        footer_text =
+            "'up/down' to select, 'o' keep ours, 't' take theirs, 'enter' to apply, 'esc' to cancel"
+                .to_string();
+    } else if app.reconcile_setup.is_some() {
+        footer_text = "'tab' to switch field, 'enter' to start, 'esc' to cancel".to_string();
+    } else if app.settings_form.is_some() {
+        footer_text = "'tab' to switch field, 'enter' to save, 'esc' to cancel".to_string();
+    } else if app.reconciliation.is_some() {
+        footer_text =
+            "'up/down' to select, 'space' to tick, 'enter' to finish, 'esc' to pause".to_string();
+    } else if app.duplicate_review.is_some() {
+        footer_text =
+            "'up/down' to select, 'space' to toggle merge/keep, 'enter' to apply, 'esc' to cancel"
+                .to_string();
+    } else if app.pending_delete.is_some() {
+        footer_text = "'y' to delete, 'n' to keep it".to_string();
+    } else if let Some(menu) = &app.bulk_menu {
+        footer_text = if menu.input.is_some() {
+            "'enter' to run, 'esc' to go back".to_string()
+        } else {
+            "'up/down' to select, 'enter' to choose, 'esc' to cancel".to_string()
+        };
+    } else if app.expense_history.is_some() {
+        footer_text =
+            "'up/down' to select, 'enter' to revert to that version, 'esc' to close".to_string();
+    } else if app.screen == Screen::Trash {
+        footer_text = format!(
+            "'up/down' to select, {}, 'esc' back",
+            app.keymap.help(Mode::Trash)
+        );
+    } else if app.screen == Screen::Calendar {
+        footer_text = format!(
+            "{}, 'enter' to list the day, 'esc' back",
+            app.keymap.help(Mode::Calendar)
+        );
+    } else if app.screen == Screen::Dashboard {
+        footer_text = format!(
+            "{}, 'tab' next screen, 'esc' to go back",
+            app.keymap.help(Mode::Dashboard)
+        );
+    } else if app.screen == Screen::Reports {
+        footer_text =
+            "'up/down' to select, 'enter' to write the report, 'esc' to go back".to_string();
+    } else if app.screen == Screen::Settings {
+        footer_text = if app.ledger.is_some() {
+            "'enter' to edit the ledger's settings, 'tab' next screen, 'esc' to go back"
+        } else {
+            "'tab' next screen, 'esc' to go back"
+        }
+        .to_string();
+    } else if app.screen != Screen::Transactions {
+        footer_text =
+            "'up/down' to select, 'enter' to list its expenses, 'esc' to go back".to_string();
+    } else if app.search.is_some() {
+        footer_text = format!(
+            "Type to search name, category and notes: {}",
+            app.keymap.help(Mode::Form)
+        );
+    } else if app.filter_panel.is_some() {
+        footer_text = format!(
+            "Empty fields match everything, categories are comma separated: {}",
+            app.keymap.help(Mode::Form)
+        );
+    } else if app.adding_expense {
+        footer_text = app.keymap.help(Mode::Form);
+    }
+    if let Some(message) = &app.status_message {
+        footer_text = message.clone();
     }
     let footer = Paragraph::new(footer_text)
-        .style(Style::default().fg(Color::Gray))
+        .style(theme.muted)
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(footer, chunks[2]);
 }
 
 fn render_add_expense_form(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let title = match app.editing {
+        Some(id) => format!("Edit expense #{}", id),
+        None => "New expense".to_string(),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let area = {
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        inner
+    };
     let input_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -53,67 +255,1107 @@ fn render_add_expense_form(f: &mut Frame, app: &App, area: Rect) {
                 Constraint::Length(3),
                 Constraint::Length(3),
                 Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
             ]
             .as_ref(),
         )
         .split(area);
 
-    let date_input = Paragraph::new(app.new_expense.date.as_str())
-        .style(input_style(app.input_mode == InputMode::Date))
-        .block(Block::default().borders(Borders::ALL).title("Date"));
-    f.render_widget(date_input, input_chunks[0]);
-
-    let name_input = Paragraph::new(app.new_expense.name.as_str())
-        .style(input_style(app.input_mode == InputMode::Name))
-        .block(Block::default().borders(Borders::ALL).title("Name"));
-    f.render_widget(name_input, input_chunks[1]);
-
-    let category_input = Paragraph::new(app.new_expense.category.as_str())
-        .style(input_style(app.input_mode == InputMode::Category))
-        .block(Block::default().borders(Borders::ALL).title("Category"));
-    f.render_widget(category_input, input_chunks[2]);
+    let date_title = format!("Date ({})", app.config.display.date_input_format);
+    let fields = [
+        (InputMode::Date, date_title.as_str()),
+        (InputMode::Name, "Name"),
+        (InputMode::Category, "Category"),
+        (InputMode::Amount, "Amount"),
+        (InputMode::Account, "Account"),
+        (InputMode::Notes, "Notes"),
+        (InputMode::Tags, "Tags (comma separated)"),
+    ];
+    for (index, (mode, title)) in fields.into_iter().enumerate() {
+        render_text_input(
+            f,
+            theme,
+            app.form.field(mode),
+            title,
+            app.input_mode == mode,
+            input_chunks[index],
+        );
+    }
+}
 
-    let amount_input = Paragraph::new(app.new_expense.amount.to_string())
-        .style(input_style(app.input_mode == InputMode::Amount))
-        .block(Block::default().borders(Borders::ALL).title("Amount"));
-    f.render_widget(amount_input, input_chunks[3]);
+/// Draws a text field in a bordered box. The focused one shows its
+/// selection and gets the terminal cursor, scrolled so it stays visible.
+fn render_text_input(
+    f: &mut Frame,
+    theme: &Theme,
+    input: &TextInput,
+    title: &str,
+    focused: bool,
+    area: Rect,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title.to_string());
+    let width = block.inner(area).width as usize;
+    let scroll = if focused {
+        (input.cursor() + 1).saturating_sub(width)
+    } else {
+        0
+    };
+    let chars: Vec<char> = input.value().chars().collect();
+    let text = |from: usize, to: usize| -> String {
+        chars[from.clamp(scroll, chars.len())..to.clamp(scroll, chars.len())]
+            .iter()
+            .collect()
+    };
+    let line = match input.selection().filter(|_| focused) {
+        Some((start, end)) => Line::from(vec![
+            Span::raw(text(0, start)),
+            Span::styled(text(start, end), theme.selected),
+            Span::raw(text(end, chars.len())),
+        ]),
+        None => Line::from(text(0, chars.len())),
+    };
+    let paragraph = Paragraph::new(line)
+        .style(input_style(theme, focused))
+        .block(block);
+    f.render_widget(paragraph, area);
+    if focused {
+        f.set_cursor(area.x + 1 + (input.cursor() - scroll) as u16, area.y + 1);
+    }
 }
 
 fn render_expense_list(f: &mut Frame, app: &App, area: Rect) {
-    let expenses: Vec<ListItem> = app
+    let theme = &app.theme;
+    // The search bar stays visible while a search is active
+    let search = app
+        .search
+        .clone()
+        .or_else(|| app.filter.text.as_deref().map(TextInput::new));
+    let area = match &search {
+        Some(input) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(3)].as_ref())
+                .split(area);
+            render_text_input(f, theme, input, "Search", app.search.is_some(), chunks[0]);
+            chunks[1]
+        }
+        None => area,
+    };
+    let columns = &app.config.display.columns;
+    let widths = column_widths(columns, &app.config.display.column_widths);
+    let cell = |column: &Column, text: String| {
+        if column.right_aligned() {
+            Cell::from(Line::from(text).alignment(Alignment::Right))
+        } else {
+            Cell::from(text)
+        }
+    };
+    let rows: Vec<Row> = app
         .expenses
         .iter()
+        .map(|expense| {
+            let row = Row::new(columns.iter().map(|column| {
+                let text = cell(column, column.cell(expense, &app.config));
+                match column {
+                    Column::Date => text.style(Style::default().add_modifier(Modifier::BOLD)),
+                    Column::Amount if expense.amount < 0.0 => text.style(theme.income),
+                    _ => text,
+                }
+            }));
+            if expense.id.is_some_and(|id| app.marked.contains(&id)) {
+                row.style(theme.focused_input)
+            } else {
+                row
+            }
+        })
+        .collect();
+    let header = Row::new(
+        columns
+            .iter()
+            .map(|column| cell(column, app.sort.header(*column))),
+    )
+    .style(theme.title.add_modifier(Modifier::BOLD));
+    let total: f64 = app.expenses.iter().map(|e| e.amount).sum();
+    let footer = Row::new(columns.iter().map(|column| match column {
+        Column::Amount => cell(column, app.config.format_amount(total)),
+        Column::Name => Cell::from(format!("{} expenses", app.expenses.len())),
+        _ => Cell::from(""),
+    }))
+    .style(theme.muted.add_modifier(Modifier::BOLD));
+
+    let currency = app.config.currency(app.ledger_settings.currency.as_deref());
+    let title = match (app.filter_description(), &app.summary) {
+        (Some(description), _) => {
+            let total: f64 = app.expenses.iter().map(|e| e.amount).sum();
+            Line::from(format!(
+                "Expenses — filter: {} — {} of {} match, total {}{}",
+                description,
+                app.expenses.len(),
+                app.all_expenses.len(),
+                currency,
+                app.config.format_amount(total)
+            ))
+        }
+        (None, Some(summary)) => {
+            let over = summary.budgets.iter().filter(|b| b.is_over()).count();
+            let mut title = vec![Span::raw(format!(
+                "Expenses — this month: {}{} ({}{}/day)",
+                currency,
+                app.config.format_amount(summary.total),
+                currency,
+                app.config.format_amount(summary.daily_average)
+            ))];
+            if over > 0 {
+                title.push(Span::raw(", "));
+                title.push(Span::styled(
+                    format!("{} over budget", over),
+                    theme.over_budget,
+                ));
+            }
+            Line::from(title)
+        }
+        (None, None) => Line::from("Expenses"),
+    };
+    let title = if app.marked.is_empty() {
+        title
+    } else {
+        let marked = format!(
+            " — {} marked, '{}' for bulk actions",
+            app.marked.len(),
+            app.keymap.help_for(Mode::List, Action::BulkActions)
+        );
+        let mut spans = title.spans;
+        spans.push(Span::styled(marked, theme.focused_input));
+        Line::from(spans)
+    };
+
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    app.table_header
+        .set(Some(Rect::new(inner.x, inner.y, inner.width, 1)));
+    let table = Table::new(rows, widths.iter().map(|width| Constraint::Length(*width)))
+        .header(header)
+        .footer(footer)
+        .column_spacing(COLUMN_SPACING)
+        .block(block)
+        .highlight_style(theme.selected);
+
+    let mut state = TableState::default();
+    state.select(app.selected_index);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// Bar heights are whole currency units; income pulls a total below zero,
+/// which a bar can't show.
+fn bar_value(amount: f64) -> u64 {
+    amount.max(0.0).round() as u64
+}
+
+fn render_dashboard(f: &mut Frame, app: &App, data: &DashboardData, area: Rect) {
+    let theme = &app.theme;
+    let summary = &data.summary;
+    let config = &app.config;
+    let currency = config.currency(app.ledger_settings.currency.as_deref());
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(area);
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[0]);
+    let bottom = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(5)].as_ref())
+        .split(chunks[1]);
+
+    let month = NaiveDate::from_ymd_opt(summary.year, summary.month, 1)
+        .map_or_else(String::new, |date| date.format("%B %Y").to_string());
+    let bars: Vec<Bar> = summary
+        .categories
+        .iter()
+        .map(|category| {
+            Bar::default()
+                .label(Line::from(category.category.clone()))
+                .value(bar_value(category.total))
+                .text_value(config.format_amount(category.total))
+        })
+        .collect();
+    let categories = BarChart::default()
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "{} — {}{} by category",
+            month,
+            currency,
+            config.format_amount(summary.total)
+        )))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(theme.title)
+        .value_style(theme.selected)
+        .data(BarGroup::default().bars(&bars));
+    f.render_widget(categories, top[0]);
+
+    // The month being shown is the last bar, set apart from the ones before
+    let bars: Vec<Bar> = summary
+        .history
+        .iter()
         .enumerate()
-        .map(|(index, expense)| {
-            let content = Line::from(vec![
+        .map(|(index, month)| {
+            let style = if index + 1 == summary.history.len() {
+                theme.warning
+            } else {
+                theme.title
+            };
+            let label = parse_month(&month.month)
+                .and_then(|(year, month)| NaiveDate::from_ymd_opt(year, month, 1))
+                .map_or_else(|| month.month.clone(), |date| date.format("%b").to_string());
+            Bar::default()
+                .label(Line::from(label))
+                .value(bar_value(month.total))
+                .text_value(config.format_amount(month.total))
+                .style(style)
+        })
+        .collect();
+    let change = summary.total - summary.previous_total;
+    // Narrow enough that every month fits, the shown one included
+    let slots = top[1].width.saturating_sub(2) / summary.history.len().max(1) as u16;
+    let bar_width = slots.saturating_sub(1).clamp(1, 9);
+    let history = BarChart::default()
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Month over month: {}{}",
+            if change >= 0.0 { "+" } else { "" },
+            config.format_amount(change)
+        )))
+        .bar_width(bar_width)
+        .bar_gap(1)
+        .value_style(theme.selected)
+        .data(BarGroup::default().bars(&bars));
+    f.render_widget(history, top[1]);
+
+    let daily: Vec<u64> = data.daily.iter().map(|amount| bar_value(*amount)).collect();
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Spending per day"),
+        )
+        .style(theme.title)
+        .data(&daily);
+    f.render_widget(sparkline, bottom[0]);
+
+    let cumulative = data.cumulative();
+    let points: Vec<(f64, f64)> = cumulative
+        .iter()
+        .enumerate()
+        .map(|(index, total)| ((index + 1) as f64, *total))
+        .collect();
+    let days = data.daily.len() as f64;
+    let highest = cumulative.iter().copied().fold(0.0, f64::max).max(1.0);
+    let lowest = cumulative.iter().copied().fold(0.0, f64::min);
+    let dataset = Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(theme.warning)
+        .data(&points);
+    let chart = Chart::new(vec![dataset])
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Cumulative total: {}{}",
+            currency,
+            config.format_amount(cumulative.last().copied().unwrap_or(0.0))
+        )))
+        .x_axis(
+            Axis::default()
+                .style(theme.muted)
+                .bounds([1.0, days.max(1.0)])
+                .labels(vec![
+                    Span::raw("1"),
+                    Span::raw(format!("{}", data.daily.len())),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(theme.muted)
+                .bounds([lowest, highest])
+                .labels(vec![
+                    Span::raw(config.format_amount(lowest)),
+                    Span::raw(config.format_amount(highest)),
+                ]),
+        );
+    f.render_widget(chart, bottom[1]);
+}
+
+fn render_placeholder(f: &mut Frame, title: &str, text: &str, area: Rect) {
+    let paragraph = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title.to_string()),
+    );
+    f.render_widget(paragraph, area);
+}
+
+/// A table for one of the list screens, with its remembered row selected.
+fn render_screen_table(
+    f: &mut Frame,
+    app: &App,
+    title: String,
+    header: &[&str],
+    rows: Vec<Row>,
+    widths: &[Constraint],
+    area: Rect,
+) {
+    let theme = &app.theme;
+    let header = Row::new(header.iter().map(|text| Cell::from(*text)))
+        .style(theme.title.add_modifier(Modifier::BOLD));
+    let selected = (!rows.is_empty()).then(|| app.screen_row(app.screen));
+    let table = Table::new(rows, widths)
+        .header(header)
+        .column_spacing(COLUMN_SPACING)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected);
+    let mut state = TableState::default();
+    state.select(selected);
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+fn amount_cell(text: String) -> Cell<'static> {
+    Cell::from(Line::from(text).alignment(Alignment::Right))
+}
+
+fn render_budgets(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let config = &app.config;
+    let budgets = app
+        .summary
+        .as_ref()
+        .map_or(&[][..], |summary| &summary.budgets[..]);
+    let rows: Vec<Row> = budgets
+        .iter()
+        .map(|budget| {
+            let row = Row::new(vec![
+                Cell::from(budget.category.clone()),
+                amount_cell(config.format_amount(budget.limit)),
+                amount_cell(config.format_amount(budget.spent)),
+                amount_cell(config.format_amount(budget.remaining())),
+            ]);
+            if budget.is_over() {
+                row.style(theme.over_budget)
+            } else {
+                row
+            }
+        })
+        .collect();
+    let title = if budgets.is_empty() {
+        "Budgets this month — none set, see `budget set`".to_string()
+    } else {
+        "Budgets this month".to_string()
+    };
+    render_screen_table(
+        f,
+        app,
+        title,
+        &["Category", "Limit", "Spent", "Remaining"],
+        rows,
+        &[
+            Constraint::Length(20),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+        ],
+        area,
+    );
+}
+
+fn render_categories(f: &mut Frame, app: &App, area: Rect) {
+    let config = &app.config;
+    let rows: Vec<Row> = app
+        .category_totals()
+        .into_iter()
+        .map(|total| {
+            Row::new(vec![
+                Cell::from(total.category),
+                amount_cell(total.count.to_string()),
+                amount_cell(config.format_amount(total.total)),
+            ])
+        })
+        .collect();
+    render_screen_table(
+        f,
+        app,
+        "Categories, all time".to_string(),
+        &["Category", "Expenses", "Total"],
+        rows,
+        &[
+            Constraint::Length(20),
+            Constraint::Length(10),
+            Constraint::Length(12),
+        ],
+        area,
+    );
+}
+
+fn render_recurring(f: &mut Frame, app: &App, area: Rect) {
+    let config = &app.config;
+    let rows: Vec<Row> = find_recurring(&app.all_expenses)
+        .into_iter()
+        .map(|recurring| {
+            Row::new(vec![
+                Cell::from(recurring.name),
+                Cell::from(recurring.category),
+                amount_cell(recurring.months.to_string()),
+                amount_cell(config.format_amount(recurring.average)),
+                Cell::from(config.display_date(&recurring.last_date)),
+            ])
+        })
+        .collect();
+    render_screen_table(
+        f,
+        app,
+        format!("Paid in at least {} different months", MIN_MONTHS),
+        &["Name", "Category", "Months", "Average", "Last paid"],
+        rows,
+        &[
+            Constraint::Length(20),
+            Constraint::Length(15),
+            Constraint::Length(7),
+            Constraint::Length(12),
+            Constraint::Length(12),
+        ],
+        area,
+    );
+}
+
+fn render_reports(f: &mut Frame, app: &App, area: Rect) {
+    let rows: Vec<Row> = REPORTS
+        .iter()
+        .map(|report| {
+            let (name, file) = match report {
+                Action::ExportXlsx => ("Excel workbook of every expense", "expenses.xlsx"),
+                Action::HtmlReport => ("This month as a web page", "report-YYYY-MM.html"),
+                _ => ("This month in Markdown", "report-YYYY-MM.md"),
+            };
+            Row::new(vec![
+                Cell::from(name),
+                Cell::from(file),
+                Cell::from(app.keymap.help_for(Mode::List, *report)),
+            ])
+        })
+        .collect();
+    render_screen_table(
+        f,
+        app,
+        "Reports".to_string(),
+        &["Report", "Written to", "Key"],
+        rows,
+        &[
+            Constraint::Length(32),
+            Constraint::Length(20),
+            Constraint::Length(8),
+        ],
+        area,
+    );
+}
+
+fn render_trash(f: &mut Frame, app: &App, area: Rect) {
+    let config = &app.config;
+    let rows: Vec<Row> = app
+        .trash
+        .iter()
+        .map(|expense| {
+            let deleted = expense.deleted_at.as_deref().unwrap_or_default();
+            Row::new(vec![
+                Cell::from(config.display_date(&expense.date)),
+                Cell::from(expense.name.clone()),
+                Cell::from(expense.category.clone()),
+                amount_cell(config.format_amount(expense.amount)),
+                Cell::from(config.display_date(deleted.get(..10).unwrap_or(deleted))),
+            ])
+        })
+        .collect();
+    let retention = match config.behavior.trash_retention_days {
+        0 => "kept until deleted for good".to_string(),
+        days => format!("emptied after {} days", days),
+    };
+    render_screen_table(
+        f,
+        app,
+        format!("Trash, {}", retention),
+        &["Date", "Name", "Category", "Amount", "Deleted"],
+        rows,
+        &[
+            Constraint::Length(12),
+            Constraint::Length(20),
+            Constraint::Length(15),
+            Constraint::Length(12),
+            Constraint::Length(12),
+        ],
+        area,
+    );
+}
+
+fn render_settings(f: &mut Frame, app: &App, area: Rect) {
+    let config = &app.config;
+    let display = &config.display;
+    let currency = config.currency(app.ledger_settings.currency.as_deref());
+    let setting = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<18}", name), app.theme.title),
+            Span::raw(value),
+        ])
+    };
+    let mut lines = vec![
+        setting(
+            "Ledger",
+            app.ledger
+                .as_ref()
+                .map_or("-".to_string(), |ledger| ledger.name.clone()),
+        ),
+        setting("Currency", currency.to_string()),
+        setting("Locale", display.locale.clone()),
+        setting("Date format", display.date_format.clone()),
+        setting("Date input", display.date_input_format.clone()),
+        setting("Week starts on", display.week_start.weekday().to_string()),
+        setting("Theme", display.theme.clone()),
+        setting(
+            "Autosave",
+            if config.behavior.autosave {
+                "on"
+            } else {
+                "off"
+            }
+            .to_string(),
+        ),
+        setting(
+            "Trash kept for",
+            match config.behavior.trash_retention_days {
+                0 => "ever".to_string(),
+                days => format!("{} days", days),
+            },
+        ),
+        setting(
+            "Encrypted",
+            if app.encryption_key.is_some() {
+                "yes"
+            } else {
+                "no"
+            }
+            .to_string(),
+        ),
+    ];
+    lines.push(Line::from(""));
+    lines.push(Line::styled(
+        "Change these in config.toml; the passphrase with 'P'.",
+        app.theme.muted,
+    ));
+    let paragraph =
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Settings"));
+    f.render_widget(paragraph, area);
+}
+
+fn render_calendar(f: &mut Frame, app: &App, calendar: &Calendar, area: Rect) {
+    let theme = &app.theme;
+    let config = &app.config;
+    let week_start = config.display.week_start.weekday();
+    let weekdays: Vec<Weekday> = std::iter::successors(Some(week_start), |day| Some(day.succ()))
+        .take(7)
+        .collect();
+    let header = Row::new(
+        weekdays
+            .iter()
+            .map(|day| Cell::from(Line::from(day.to_string()).alignment(Alignment::Center))),
+    )
+    .style(theme.title.add_modifier(Modifier::BOLD));
+
+    let rows: Vec<Row> = calendar_weeks(calendar.year(), calendar.month(), week_start)
+        .into_iter()
+        .map(|week| {
+            Row::new(week.into_iter().map(|day| {
+                let Some(day) = day else {
+                    return Cell::from("");
+                };
+                let total = calendar.total(day);
+                let amount = if total == 0.0 {
+                    String::new()
+                } else {
+                    config.format_amount(total)
+                };
+                let style = if day == calendar.selected {
+                    theme.selected
+                } else {
+                    match calendar.heat(day) {
+                        0 => theme.muted,
+                        1 => theme.success,
+                        2 => theme.warning,
+                        _ => theme.over_budget,
+                    }
+                };
+                Cell::from(vec![
+                    Line::from(day.day().to_string()).alignment(Alignment::Center),
+                    Line::from(amount).alignment(Alignment::Center),
+                ])
+                .style(style)
+            }))
+            .height(2)
+        })
+        .collect();
+
+    let currency = config.currency(app.ledger_settings.currency.as_deref());
+    let total: f64 = calendar.daily.iter().sum();
+    let title = format!(
+        "Calendar — {} — {}{} spent",
+        calendar.selected.format("%B %Y"),
+        currency,
+        config.format_amount(total)
+    );
+    let table = Table::new(rows, [Constraint::Ratio(1, 7); 7])
+        .header(header)
+        .column_spacing(COLUMN_SPACING)
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(table, area);
+}
+
+fn render_filter_panel(f: &mut Frame, app: &App, panel: &FilterPanel, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("Filter");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3); 5].as_ref())
+        .split(inner);
+
+    let date_format = &app.config.display.date_input_format;
+    for (index, field) in FilterField::ALL.into_iter().enumerate() {
+        let title = match field {
+            FilterField::From => format!("From ({})", date_format),
+            FilterField::To => format!("To ({})", date_format),
+            FilterField::Categories => "Categories".to_string(),
+            FilterField::MinAmount => "Minimum amount".to_string(),
+            FilterField::MaxAmount => "Maximum amount".to_string(),
+        };
+        render_text_input(
+            f,
+            &app.theme,
+            panel.field(field),
+            &title,
+            panel.field == field,
+            chunks[index],
+        );
+    }
+}
+
+fn render_ledger_picker(f: &mut Frame, theme: &Theme, picker: &LedgerPicker, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .split(area);
+
+    let items: Vec<ListItem> = picker
+        .ledgers
+        .iter()
+        .map(|ledger| ListItem::new(ledger.name.as_str()))
+        .collect();
+    let title = if picker.ledgers.is_empty() {
+        "Ledgers — none yet, press 'n' to create one"
+    } else {
+        "Ledgers"
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected);
+    let mut state = ListState::default();
+    if !picker.ledgers.is_empty() {
+        state.select(Some(picker.selected));
+    }
+    f.render_stateful_widget(list, chunks[0], &mut state);
+
+    if let Some(name) = &picker.new_name {
+        let input = Paragraph::new(name.as_str())
+            .style(input_style(theme, true))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("New ledger name"),
+            );
+        f.render_widget(input, chunks[1]);
+    }
+}
+
+fn render_secret_prompt(f: &mut Frame, theme: &Theme, title: &str, input: &str, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let masked = "*".repeat(input.chars().count());
+    let prompt = Paragraph::new(masked)
+        .style(input_style(theme, true))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(prompt, chunks[0]);
+}
+
+fn render_delete_confirmation(f: &mut Frame, app: &App, pending: &PendingDelete, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let config = &app.config;
+    let expense = &pending.expense;
+    let (title, note) = if pending.purge {
+        ("Delete this expense for good?", "This can't be undone.")
+    } else {
+        (
+            "Delete this expense?",
+            "It goes to the trash and can be brought back.",
+        )
+    };
+    let text = vec![
+        Line::from(format!(
+            "{}  {}  {}  {}",
+            config.display_date(&expense.date),
+            expense.name,
+            expense.category,
+            config.format_amount(expense.amount)
+        )),
+        Line::styled(note, app.theme.muted),
+    ];
+    let prompt = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.theme.warning)
+            .title(title),
+    );
+    f.render_widget(prompt, chunks[0]);
+}
+
+fn render_bulk_menu(f: &mut Frame, app: &App, menu: &BulkMenu, area: Rect) {
+    let theme = &app.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(8), Constraint::Min(0)].as_ref())
+        .split(area);
+    let items: Vec<ListItem> = BulkAction::ALL
+        .iter()
+        .map(|action| ListItem::new(action.title()))
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{} marked expenses", app.marked.len())),
+        )
+        .highlight_style(theme.selected);
+    let mut state = ListState::default();
+    state.select(Some(menu.selected));
+    f.render_stateful_widget(list, chunks[0], &mut state);
+
+    if let (Some(input), Some(prompt)) = (&menu.input, menu.action().prompt()) {
+        render_path_prompt(f, theme, prompt, input, chunks[1]);
+    }
+}
+
+fn render_path_prompt(f: &mut Frame, theme: &Theme, title: &str, path: &str, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let input = Paragraph::new(path)
+        .style(input_style(theme, true))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(input, chunks[0]);
+}
+
+fn render_duplicate_review(f: &mut Frame, theme: &Theme, review: &DuplicateReview, area: Rect) {
+    let items: Vec<ListItem> = review
+        .preview
+        .matches
+        .iter()
+        .zip(&review.actions)
+        .map(|(found, action)| {
+            let (label, style) = match action {
+                DuplicateAction::Merge => ("merge", theme.warning),
+                DuplicateAction::Keep => ("keep ", theme.success),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("[{}] ", label), style),
                 Span::styled(
-                    format!("{:<12}", expense.date),
+                    format!("{:>4.0}% ", found.score * 100.0),
                     Style::default().add_modifier(Modifier::BOLD),
                 ),
-                Span::styled(format!("{:<20}", expense.name), Style::default()),
-                Span::styled(format!("{:<15}", expense.category), Style::default()),
-                Span::styled(format!("{:<15}", expense.amount), Style::default()),
-            ]);
-            if Some(index) == app.selected_index {
-                ListItem::new(content).style(Style::default().bg(Color::DarkGray))
+                Span::raw(format!(
+                    "{:<12}{:<20}{:>10.2}  <-  {:<12}{:<20}{:>10.2}",
+                    found.original.date,
+                    found.original.name,
+                    found.original.amount,
+                    found.duplicate.date,
+                    found.duplicate.name,
+                    found.duplicate.amount
+                )),
+            ]))
+        })
+        .collect();
+
+    let title = match review.kind {
+        ReviewKind::Import => format!(
+            "Import: {} new, {} possible duplicates",
+            review.preview.fresh.len(),
+            review.preview.matches.len()
+        ),
+        ReviewKind::Existing => format!("{} possible duplicates", review.preview.matches.len()),
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected);
+
+    let mut state = ListState::default();
+    state.select(Some(review.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_expense_history(f: &mut Frame, app: &App, history: &ExpenseHistory, area: Rect) {
+    let config = &app.config;
+    let rows: Vec<Row> = history
+        .entries
+        .iter()
+        .map(|entry| {
+            let when = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+                .map(|time| {
+                    let time = time.with_timezone(&chrono::Local);
+                    format!(
+                        "{} {}",
+                        config.display_date(&time.format("%Y-%m-%d").to_string()),
+                        time.format("%H:%M")
+                    )
+                })
+                .unwrap_or_else(|_| entry.timestamp.clone());
+            Row::new(vec![
+                Cell::from(when),
+                Cell::from(entry.actor.clone()),
+                Cell::from(entry.action.as_str()),
+                Cell::from(describe_audit_entry(app, entry)),
+            ])
+        })
+        .collect();
+    let header = Row::new(["When", "Who", "What", "Changes"])
+        .style(app.theme.title.add_modifier(Modifier::BOLD));
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(17),
+            Constraint::Length(20),
+            Constraint::Length(9),
+            Constraint::Min(20),
+        ],
+    )
+    .header(header)
+    .column_spacing(COLUMN_SPACING)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("History of '{}'", history.expense.name)),
+    )
+    .highlight_style(app.theme.selected);
+    let mut state = TableState::default();
+    state.select(Some(history.selected));
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// What a change did, e.g. `amount 700.00 -> 730.00`.
+fn describe_audit_entry(app: &App, entry: &AuditEntry) -> String {
+    let config = &app.config;
+    match entry.action {
+        AuditAction::Update => entry
+            .changes()
+            .into_iter()
+            .map(|(field, old, new)| format!("{} {} -> {}", field, old, new))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => entry
+            .after()
+            .or_else(|| entry.before())
+            .map(|expense| {
+                format!(
+                    "{}  {}  {}  {}",
+                    config.display_date(&expense.date),
+                    expense.name,
+                    expense.category,
+                    config.format_amount(expense.amount)
+                )
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn render_merge_review(f: &mut Frame, theme: &Theme, review: &MergeReview, area: Rect) {
+    let describe = |expense: &Option<Expense>| match expense {
+        Some(e) => format!(
+            "{:<12}{:<20}{:<12}{:>10.2}",
+            e.date, e.name, e.category, e.amount
+        ),
+        None => format!("{:<54}", "(deleted)"),
+    };
+    let items: Vec<ListItem> = review
+        .outcome
+        .conflicts
+        .iter()
+        .zip(&review.resolutions)
+        .map(|(conflict, resolution)| {
+            let (ours_style, theirs_style) = match resolution {
+                Resolution::Ours => (theme.success, Style::default()),
+                Resolution::Theirs => (Style::default(), theme.success),
+            };
+            ListItem::new(vec![
+                Line::from(vec![
+                    Span::styled("ours:   ", ours_style.add_modifier(Modifier::BOLD)),
+                    Span::styled(describe(&conflict.ours), ours_style),
+                ]),
+                Line::from(vec![
+                    Span::styled("theirs: ", theirs_style.add_modifier(Modifier::BOLD)),
+                    Span::styled(describe(&conflict.theirs), theirs_style),
+                ]),
+            ])
+        })
+        .collect();
+
+    let title = format!(
+        "Sync with {}: {} merged, {} conflicts",
+        review.other_file,
+        review.outcome.merged.len(),
+        review.outcome.conflicts.len()
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected);
+
+    let mut state = ListState::default();
+    state.select(Some(review.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_reconcile_setup(f: &mut Frame, theme: &Theme, setup: &ReconcileSetup, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    let fields = [
+        (&setup.account, "Account", ReconcileField::Account),
+        (
+            &setup.statement_date,
+            "Statement end date",
+            ReconcileField::StatementDate,
+        ),
+        (
+            &setup.closing_balance,
+            "Closing balance",
+            ReconcileField::ClosingBalance,
+        ),
+    ];
+    for (index, (value, title, field)) in fields.into_iter().enumerate() {
+        let input = Paragraph::new(value.as_str())
+            .style(input_style(theme, setup.field == field))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(input, chunks[index]);
+    }
+}
+
+fn render_settings_form(f: &mut Frame, theme: &Theme, form: &SettingsForm, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    let fields = [
+        (&form.description, "Description", SettingsField::Description),
+        (
+            &form.currency,
+            "Currency (empty for the config's)",
+            SettingsField::Currency,
+        ),
+    ];
+    for (index, (value, title, field)) in fields.into_iter().enumerate() {
+        let input = Paragraph::new(value.as_str())
+            .style(input_style(theme, form.field == field))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(input, chunks[index]);
+    }
+}
+
+fn render_reconciliation(
+    f: &mut Frame,
+    theme: &Theme,
+    reconciliation: &Reconciliation,
+    area: Rect,
+) {
+    let items: Vec<ListItem> = reconciliation
+        .entries
+        .iter()
+        .map(|expense| {
+            let tick = if expense.status == ExpenseStatus::Cleared {
+                "[x] "
             } else {
-                ListItem::new(content)
-            }
+                "[ ] "
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(tick),
+                Span::raw(format!(
+                    "{:<12}{:<20}{:<15}{:>10.2}",
+                    expense.date, expense.name, expense.category, expense.amount
+                )),
+            ]))
         })
         .collect();
 
-    let expenses_list = List::new(expenses)
-        .block(Block::default().borders(Borders::ALL).title("Expenses"))
-        .highlight_style(Style::default().bg(Color::DarkGray));
+    let difference_style = if reconciliation.is_balanced() {
+        theme.success
+    } else {
+        theme.warning
+    };
+    let title = Line::from(vec![
+        Span::raw(format!(
+            "Reconcile '{}' to {} — statement {:.2}, cleared {:.2}, ",
+            reconciliation.account,
+            reconciliation.statement_date,
+            reconciliation.closing_balance,
+            reconciliation.opening_balance + reconciliation.cleared_total()
+        )),
+        Span::styled(
+            format!("difference {:.2}", reconciliation.difference()),
+            difference_style,
+        ),
+    ]);
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected);
 
     let mut state = ListState::default();
-    state.select(app.selected_index);
-    f.render_stateful_widget(expenses_list, area, &mut state);
+    state.select(Some(reconciliation.selected));
+    f.render_stateful_widget(list, area, &mut state);
 }
 
-fn input_style(is_selected: bool) -> Style {
+fn input_style(theme: &Theme, is_selected: bool) -> Style {
     if is_selected {
-        Style::default().fg(Color::Yellow)
+        theme.focused_input
     } else {
         Style::default()
     }
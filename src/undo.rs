@@ -0,0 +1,157 @@
+use crate::db::{Database, ExpenseChange};
+use crate::expense::Expense;
+
+/// A change made in the TUI, with the expenses as stored before and after so
+/// it can be reverted and made again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Insert(Box<Expense>),
+    Update {
+        before: Box<Expense>,
+        after: Box<Expense>,
+    },
+    Delete(Box<Expense>),
+    /// Taking an expense, as it was in the trash, back out of it.
+    Restore(Box<Expense>),
+    /// Several changes made as one step, undone and redone together.
+    Bulk {
+        description: String,
+        changes: Vec<Change>,
+    },
+}
+
+impl Change {
+    /// What the change did, e.g. `deleting 'Rent'`.
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Insert(expense) => format!("adding '{}'", expense.name),
+            Change::Update { after, .. } => format!("editing '{}'", after.name),
+            Change::Delete(expense) => format!("deleting '{}'", expense.name),
+            Change::Restore(expense) => format!("restoring '{}'", expense.name),
+            Change::Bulk { description, .. } => description.clone(),
+        }
+    }
+
+    /// The change with everything done to the expense with this uid left
+    /// out, `None` when nothing else is left.
+    fn without(self, uid: &str) -> Option<Change> {
+        match self {
+            Change::Insert(expense) | Change::Delete(expense) | Change::Restore(expense)
+                if expense.uid == uid =>
+            {
+                None
+            }
+            Change::Update { after, .. } if after.uid == uid => None,
+            Change::Bulk {
+                description,
+                changes,
+            } => {
+                let changes: Vec<Change> = changes
+                    .into_iter()
+                    .filter_map(|change| change.without(uid))
+                    .collect();
+                (!changes.is_empty()).then_some(Change::Bulk {
+                    description,
+                    changes,
+                })
+            }
+            change => Some(change),
+        }
+    }
+
+    fn undo_writes(&self) -> Vec<ExpenseChange> {
+        match self {
+            Change::Insert(expense) => expense.id.map(ExpenseChange::Remove).into_iter().collect(),
+            Change::Update { before, .. } => vec![ExpenseChange::Put(before.clone())],
+            Change::Delete(expense) | Change::Restore(expense) => {
+                vec![ExpenseChange::Put(expense.clone())]
+            }
+            Change::Bulk { changes, .. } => {
+                changes.iter().rev().flat_map(Change::undo_writes).collect()
+            }
+        }
+    }
+
+    fn redo_writes(&self) -> Vec<ExpenseChange> {
+        match self {
+            Change::Insert(expense) => vec![ExpenseChange::Put(expense.clone())],
+            Change::Update { after, .. } => vec![ExpenseChange::Put(after.clone())],
+            Change::Delete(expense) => expense.id.map(ExpenseChange::Trash).into_iter().collect(),
+            Change::Restore(expense) => vec![ExpenseChange::Put(Box::new(Expense {
+                deleted_at: None,
+                ..(**expense).clone()
+            }))],
+            Change::Bulk { changes, .. } => changes.iter().flat_map(Change::redo_writes).collect(),
+        }
+    }
+}
+
+/// Undo and redo stacks for the session. Nothing is kept once the app
+/// exits.
+#[derive(Debug, Default)]
+pub struct History {
+    done: Vec<Change>,
+    undone: Vec<Change>,
+}
+
+impl History {
+    /// Remembers a change that was just made. Anything undone before can no
+    /// longer be redone.
+    pub fn record(&mut self, change: Change) {
+        self.done.push(change);
+        self.undone.clear();
+    }
+
+    /// Makes a change and remembers it, unless the database refuses it.
+    pub async fn apply(&mut self, db: &Database, change: Change) -> Result<(), sqlx::Error> {
+        db.apply_changes(&change.redo_writes()).await?;
+        self.record(change);
+        Ok(())
+    }
+
+    /// Forgets everything, e.g. when the expenses were replaced wholesale.
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+
+    /// Forgets every change to the expense with this uid, e.g. once it's
+    /// been purged and undoing would bring it back.
+    pub fn forget(&mut self, uid: &str) {
+        for stack in [&mut self.done, &mut self.undone] {
+            *stack = std::mem::take(stack)
+                .into_iter()
+                .filter_map(|change| change.without(uid))
+                .collect();
+        }
+    }
+
+    /// Reverts the last change. Returns what was undone, `None` when there
+    /// was nothing to undo. A change that fails to revert stays on the stack.
+    pub async fn undo(&mut self, db: &Database) -> Result<Option<String>, sqlx::Error> {
+        let Some(change) = self.done.pop() else {
+            return Ok(None);
+        };
+        if let Err(err) = db.apply_changes(&change.undo_writes()).await {
+            self.done.push(change);
+            return Err(err);
+        }
+        let description = change.describe();
+        self.undone.push(change);
+        Ok(Some(description))
+    }
+
+    /// Makes the last undone change again.
+    pub async fn redo(&mut self, db: &Database) -> Result<Option<String>, sqlx::Error> {
+        let Some(change) = self.undone.pop() else {
+            return Ok(None);
+        };
+        if let Err(err) = db.apply_changes(&change.redo_writes()).await {
+            self.undone.push(change);
+            return Err(err);
+        }
+        let description = change.describe();
+        self.done.push(change);
+        Ok(Some(description))
+    }
+}